@@ -0,0 +1,131 @@
+//! Jitter and custom strategies for [`RetryPolicy`](crate::RetryPolicy).
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::time::Duration;
+
+/// Randomization applied to retry delays so that workers retrying the same
+/// dependency do not wake up in lockstep.
+///
+/// Apply it to any policy with [`RetryPolicy::with_jitter`](crate::RetryPolicy::with_jitter).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Jitter {
+    /// A random delay between zero and the policy's delay.
+    Full,
+    /// Half the policy's delay plus a random share of the other half.
+    Equal,
+    /// A random delay between the policy's first delay and three times the
+    /// previous jittered delay, capped at `cap`.
+    ///
+    /// Each delay builds on the one before rather than on the policy's
+    /// curve, so even a fixed policy spreads its retries out.
+    Decorrelated {
+        /// The largest delay produced.
+        cap: Duration,
+    },
+}
+
+/// A user-defined backoff strategy for [`RetryPolicy::Custom`](crate::RetryPolicy::Custom).
+///
+/// Implemented for closures taking the zero-based retry attempt.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use tsumugi_core::RetryPolicy;
+///
+/// // 100ms, 300ms, 600ms, ...
+/// let policy = RetryPolicy::custom(5, |attempt: u32| {
+///     Duration::from_millis(100) * (attempt + 1) * (attempt + 2) / 2
+/// });
+/// assert_eq!(policy.delay_for_attempt(2), Some(Duration::from_millis(600)));
+/// ```
+pub trait BackoffStrategy: Send + Sync {
+    /// Returns the delay before the given zero-based retry attempt.
+    fn delay(&self, attempt: u32) -> Duration;
+}
+
+impl<F> BackoffStrategy for F
+where
+    F: Fn(u32) -> Duration + Send + Sync,
+{
+    fn delay(&self, attempt: u32) -> Duration {
+        self(attempt)
+    }
+}
+
+impl fmt::Debug for dyn BackoffStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("BackoffStrategy")
+    }
+}
+
+/// Returns a random number for the given attempt.
+///
+/// With a seed the result is deterministic, which keeps jittered delays
+/// reproducible in tests.
+pub(crate) fn random(seed: Option<u64>, attempt: u32) -> u64 {
+    match seed {
+        Some(seed) => splitmix64(seed ^ splitmix64(u64::from(attempt))),
+        None => RandomState::new().hash_one(attempt),
+    }
+}
+
+/// Picks a delay in `[low, high)` using `random`, or `low` if the range is empty.
+pub(crate) fn between(low: Duration, high: Duration, random: u64) -> Duration {
+    if high <= low {
+        return low;
+    }
+    // Scale the range by a 53-bit fraction in [0, 1), split in two halves so
+    // that even `Duration::MAX` cannot overflow.
+    let range = (high - low).as_nanos();
+    let fraction = u128::from(random >> 11);
+    let nanos = (range >> 53) * fraction + (((range & ((1 << 53) - 1)) * fraction) >> 53);
+    low + from_nanos(nanos)
+}
+
+/// Converts nanoseconds to a `Duration`, saturating at `Duration::MAX`.
+pub(crate) fn from_nanos(nanos: u128) -> Duration {
+    const NANOS_PER_SEC: u128 = 1_000_000_000;
+    match u64::try_from(nanos / NANOS_PER_SEC) {
+        Ok(secs) => Duration::new(secs, (nanos % NANOS_PER_SEC) as u32),
+        Err(_) => Duration::MAX,
+    }
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_random_is_deterministic() {
+        assert_eq!(random(Some(7), 3), random(Some(7), 3));
+        assert_ne!(random(Some(7), 3), random(Some(7), 4));
+        assert_ne!(random(Some(7), 3), random(Some(8), 3));
+    }
+
+    #[test]
+    fn test_between_stays_in_range() {
+        let low = Duration::from_millis(10);
+        let high = Duration::from_millis(20);
+        assert_eq!(between(low, high, 0), low);
+        assert!(between(low, high, u64::MAX) < high);
+        assert_eq!(between(high, low, 42), high);
+        assert!(between(Duration::ZERO, Duration::MAX, u64::MAX) < Duration::MAX);
+    }
+
+    #[test]
+    fn test_from_nanos_saturates() {
+        assert_eq!(from_nanos(1_500_000_000), Duration::from_millis(1500));
+        assert_eq!(from_nanos(u128::MAX), Duration::MAX);
+    }
+}
@@ -0,0 +1,187 @@
+//! Cooperative cancellation of workflow runs.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context as TaskContext, Poll, Waker};
+
+/// A token used to request cancellation of a running workflow.
+///
+/// Clones share the same state, so a token can be handed to another task
+/// and cancelled from there. Every [`Context`](crate::Context) carries a
+/// token; the engine stops starting new steps once it is cancelled, and
+/// long-running steps can observe it through
+/// [`Context::is_cancelled`](crate::Context::is_cancelled) or by awaiting
+/// [`cancelled`](Self::cancelled).
+///
+/// # Examples
+///
+/// ```
+/// use tsumugi_core::{CancellationToken, Context};
+///
+/// let token = CancellationToken::new();
+/// let mut ctx = Context::new();
+/// ctx.set_cancellation_token(token.clone());
+///
+/// assert!(!ctx.is_cancelled());
+/// token.cancel();
+/// assert!(ctx.is_cancelled());
+/// ```
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    waiters: Mutex<Waiters>,
+}
+
+/// The wakers of pending [`Cancelled`] futures, keyed by future so a dropped
+/// future can take its waker out again.
+#[derive(Default)]
+struct Waiters {
+    next_key: u64,
+    wakers: HashMap<u64, Waker>,
+}
+
+impl CancellationToken {
+    /// Creates a new token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests cancellation and wakes every task awaiting [`cancelled`](Self::cancelled).
+    pub fn cancel(&self) {
+        if !self.inner.cancelled.swap(true, Ordering::SeqCst) {
+            let wakers = std::mem::take(&mut self.waiters().wakers);
+            for waker in wakers.into_values() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Returns `true` if cancellation has been requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns a future that completes once cancellation has been requested.
+    pub fn cancelled(&self) -> Cancelled<'_> {
+        Cancelled {
+            token: self,
+            key: None,
+        }
+    }
+
+    fn waiters(&self) -> std::sync::MutexGuard<'_, Waiters> {
+        self.inner
+            .waiters
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// Future returned by [`CancellationToken::cancelled`].
+#[derive(Debug)]
+#[must_use = "futures do nothing unless awaited"]
+pub struct Cancelled<'a> {
+    token: &'a CancellationToken,
+    key: Option<u64>,
+}
+
+impl Future for Cancelled<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        if this.token.is_cancelled() {
+            return Poll::Ready(());
+        }
+        {
+            let mut waiters = this.token.waiters();
+            let key = *this.key.get_or_insert_with(|| {
+                waiters.next_key += 1;
+                waiters.next_key
+            });
+            match waiters.wakers.get_mut(&key) {
+                Some(waker) if waker.will_wake(cx.waker()) => {}
+                Some(waker) => waker.clone_from(cx.waker()),
+                None => {
+                    waiters.wakers.insert(key, cx.waker().clone());
+                }
+            }
+        }
+        // Cancellation may have happened while the waker was being registered.
+        if this.token.is_cancelled() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Cancelled<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            self.token.waiters().wakers.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_cancel_is_shared_between_clones() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_cancelled());
+
+        token.cancel();
+        assert!(clone.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_future_wakes() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        let handle = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            clone.cancel();
+        });
+
+        tokio::time::timeout(Duration::from_secs(1), token.cancelled())
+            .await
+            .expect("cancellation should wake the future");
+        handle.await.expect("task panicked");
+    }
+
+    #[tokio::test]
+    async fn test_dropped_futures_release_their_wakers() {
+        let token = CancellationToken::new();
+        let mut pending = Box::pin(token.cancelled());
+        for _ in 0..3 {
+            let poll = std::future::poll_fn(|cx| Poll::Ready(pending.as_mut().poll(cx))).await;
+            assert!(poll.is_pending());
+        }
+        assert_eq!(token.waiters().wakers.len(), 1);
+
+        drop(pending);
+        assert!(token.waiters().wakers.is_empty());
+    }
+}
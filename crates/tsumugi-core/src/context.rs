@@ -1,8 +1,12 @@
 //! Workflow execution context with heterogeneous type storage.
 
-use std::any::Any;
+use crate::cancellation::CancellationToken;
+use crate::error::ContextError;
+use std::any::{type_name, Any, TypeId};
 use std::collections::HashMap;
 use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
 use std::time::Instant;
 
 /// Type-safe context key wrapper.
@@ -51,6 +55,196 @@ impl std::borrow::Borrow<str> for ContextKey {
     }
 }
 
+/// A context key bound to the type of its value.
+///
+/// Declare keys once as constants and use them with
+/// [`Context::insert_typed`] and [`Context::get_typed`], so every step that
+/// shares a key also agrees on its type.
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::HashMap;
+/// use tsumugi_core::{Context, TypedKey};
+///
+/// const SCORES: TypedKey<HashMap<u64, f64>> = TypedKey::new("scores");
+///
+/// let mut ctx = Context::new();
+/// ctx.insert_typed(&SCORES, HashMap::from([(1, 85.5)]));
+///
+/// assert_eq!(ctx.get_typed(&SCORES).and_then(|s| s.get(&1)), Some(&85.5));
+/// ```
+pub struct TypedKey<T> {
+    name: &'static str,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T> TypedKey<T> {
+    /// Creates a key named `name` holding values of type `T`.
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            _type: PhantomData,
+        }
+    }
+
+    /// Returns the name of the key.
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl<T> Clone for TypedKey<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for TypedKey<T> {}
+
+impl<T> fmt::Debug for TypedKey<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TypedKey<{}>({:?})", type_name::<T>(), self.name)
+    }
+}
+
+impl<T> From<&TypedKey<T>> for ContextKey {
+    fn from(key: &TypedKey<T>) -> Self {
+        Self::new(key.name)
+    }
+}
+
+/// A context key a step reads or writes, optionally with its value type.
+///
+/// Returned by [`Step::required_keys`](crate::Step::required_keys) and
+/// [`Step::produced_keys`](crate::Step::produced_keys) so the workflow
+/// builder can check that every key a step needs is set before it runs.
+///
+/// # Examples
+///
+/// ```
+/// use tsumugi_core::{KeySpec, TypedKey};
+///
+/// const ORDER: TypedKey<String> = TypedKey::new("order");
+///
+/// let typed = KeySpec::of::<u32>("quantity");
+/// let any_type = KeySpec::from("inventory");
+/// let from_key = KeySpec::from(&ORDER);
+///
+/// assert_eq!(typed.type_name(), Some("u32"));
+/// assert_eq!(any_type.type_name(), None);
+/// assert_eq!(from_key.key().as_str(), "order");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeySpec {
+    key: ContextKey,
+    value_type: Option<(TypeId, &'static str)>,
+}
+
+impl KeySpec {
+    /// Declares `key` holding a value of type `T`.
+    pub fn of<T: Any>(key: impl Into<ContextKey>) -> Self {
+        Self {
+            key: key.into(),
+            value_type: Some((TypeId::of::<T>(), type_name::<T>())),
+        }
+    }
+
+    /// Declares `key` without saying what type its value has.
+    pub fn any(key: impl Into<ContextKey>) -> Self {
+        Self {
+            key: key.into(),
+            value_type: None,
+        }
+    }
+
+    /// Returns the key.
+    pub fn key(&self) -> &ContextKey {
+        &self.key
+    }
+
+    /// Returns the declared value type, if any.
+    pub fn type_id(&self) -> Option<TypeId> {
+        self.value_type.map(|(id, _)| id)
+    }
+
+    /// Returns the name of the declared value type, if any.
+    pub fn type_name(&self) -> Option<&'static str> {
+        self.value_type.map(|(_, name)| name)
+    }
+
+    /// Checks that `ctx` holds a value for this key of the declared type.
+    pub fn check(&self, ctx: &Context) -> Result<(), ContextError> {
+        let entry = ctx
+            .entry(self.key.as_str())
+            .ok_or_else(|| ContextError::MissingKey {
+                key: self.key.clone(),
+            })?;
+        match self.value_type {
+            Some((id, expected)) if entry.value.as_ref().type_id() != id => {
+                Err(ContextError::TypeMismatch {
+                    key: self.key.clone(),
+                    expected,
+                    found: entry.type_name,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl From<&str> for KeySpec {
+    fn from(key: &str) -> Self {
+        Self::any(key)
+    }
+}
+
+impl From<String> for KeySpec {
+    fn from(key: String) -> Self {
+        Self::any(key)
+    }
+}
+
+impl From<ContextKey> for KeySpec {
+    fn from(key: ContextKey) -> Self {
+        Self::any(key)
+    }
+}
+
+impl<T: Any> From<&TypedKey<T>> for KeySpec {
+    fn from(key: &TypedKey<T>) -> Self {
+        Self::of::<T>(key.name)
+    }
+}
+
+/// A stored value, together with how to serialize it if it was inserted
+/// with `insert_serializable`.
+pub(crate) struct Entry {
+    pub(crate) value: Box<dyn Any + Send + Sync>,
+    pub(crate) type_name: &'static str,
+    #[cfg(feature = "serde")]
+    pub(crate) serialize: Option<crate::json::SerializeFn>,
+}
+
+impl Entry {
+    pub(crate) fn new<T: Any + Send + Sync>(value: T) -> Self {
+        Self {
+            value: Box::new(value),
+            type_name: type_name::<T>(),
+            #[cfg(feature = "serde")]
+            serialize: None,
+        }
+    }
+}
+
+type Entries = HashMap<ContextKey, Entry>;
+
+/// Read-only entries shared with branch contexts created by [`Context::fork`].
+struct Layer {
+    data: Entries,
+    parent: Option<Arc<Layer>>,
+}
+
 /// Execution context for workflow steps with heterogeneous type storage.
 ///
 /// Stores any `Send + Sync` type, retrieved by downcasting.
@@ -76,14 +270,19 @@ impl std::borrow::Borrow<str> for ContextKey {
 /// assert_eq!(ctx.get::<String>("user_id"), None);
 /// ```
 pub struct Context {
-    data: HashMap<ContextKey, Box<dyn Any + Send + Sync>>,
+    data: Entries,
+    parent: Option<Arc<Layer>>,
+    /// Number of layers on top of `parent` created by this context's forks.
+    forks: usize,
+    cancellation: CancellationToken,
     started_at: Instant,
 }
 
 impl fmt::Debug for Context {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Context")
-            .field("keys", &self.data.keys().collect::<Vec<_>>())
+            .field("keys", &self.keys().collect::<Vec<_>>())
+            .field("cancelled", &self.is_cancelled())
             .field("started_at", &self.started_at)
             .finish()
     }
@@ -100,64 +299,305 @@ impl Context {
     pub fn new() -> Self {
         Self {
             data: HashMap::new(),
+            parent: None,
+            forks: 0,
+            cancellation: CancellationToken::new(),
             started_at: Instant::now(),
         }
     }
 
+    fn layers(&self) -> impl Iterator<Item = &Entries> {
+        std::iter::successors(self.parent.as_deref(), |layer| layer.parent.as_deref())
+            .map(|layer| &layer.data)
+    }
+
     /// Inserts a value with the given key.
     ///
     /// If the key already exists, the previous value is replaced.
     pub fn insert<T: Any + Send + Sync>(&mut self, key: impl Into<ContextKey>, value: T) {
-        self.data.insert(key.into(), Box::new(value));
+        self.data.insert(key.into(), Entry::new(value));
+    }
+
+    /// Inserts a prepared entry, replacing any previous value.
+    #[cfg(feature = "serde")]
+    pub(crate) fn insert_entry(&mut self, key: ContextKey, entry: Entry) {
+        self.data.insert(key, entry);
     }
 
     /// Returns a reference to the value for the given key.
     ///
     /// Returns `None` if the key doesn't exist or the type doesn't match.
     pub fn get<T: Any>(&self, key: &str) -> Option<&T> {
-        self.data.get(key).and_then(|v| v.downcast_ref::<T>())
+        self.raw(key).and_then(|v| v.downcast_ref::<T>())
+    }
+
+    /// Returns a reference to the value for the given key, or an error
+    /// saying whether the key is missing or holds another type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tsumugi_core::{Context, ContextError};
+    ///
+    /// let mut ctx = Context::new();
+    /// ctx.insert("user_id", 123u64);
+    ///
+    /// assert_eq!(ctx.try_get::<u64>("user_id"), Ok(&123));
+    /// assert!(matches!(
+    ///     ctx.try_get::<String>("user_id"),
+    ///     Err(ContextError::TypeMismatch { found: "u64", .. })
+    /// ));
+    /// assert!(matches!(
+    ///     ctx.try_get::<u64>("name"),
+    ///     Err(ContextError::MissingKey { .. })
+    /// ));
+    /// ```
+    pub fn try_get<T: Any>(&self, key: &str) -> Result<&T, ContextError> {
+        let entry = self.entry(key).ok_or_else(|| ContextError::MissingKey {
+            key: ContextKey::new(key),
+        })?;
+        entry
+            .value
+            .downcast_ref::<T>()
+            .ok_or_else(|| ContextError::TypeMismatch {
+                key: ContextKey::new(key),
+                expected: type_name::<T>(),
+                found: entry.type_name,
+            })
+    }
+
+    /// Inserts a value under a typed key.
+    ///
+    /// If the key already exists, the previous value is replaced.
+    pub fn insert_typed<T: Any + Send + Sync>(&mut self, key: &TypedKey<T>, value: T) {
+        self.insert(key, value);
+    }
+
+    /// Returns a reference to the value for a typed key.
+    ///
+    /// Returns `None` if the key doesn't exist, or if it was overwritten with
+    /// another type through the untyped methods.
+    pub fn get_typed<T: Any>(&self, key: &TypedKey<T>) -> Option<&T> {
+        self.get(key.name)
+    }
+
+    fn raw(&self, key: &str) -> Option<&(dyn Any + Send + Sync)> {
+        self.entry(key).map(|entry| entry.value.as_ref())
+    }
+
+    /// Returns the visible entry for `key`, looking through forked layers.
+    pub(crate) fn entry(&self, key: &str) -> Option<&Entry> {
+        match self.data.get(key) {
+            Some(entry) => Some(entry),
+            None => self.layers().find_map(|layer| layer.get(key)),
+        }
     }
 
     /// Returns a mutable reference to the value for the given key.
     ///
     /// Returns `None` if the key doesn't exist or the type doesn't match.
+    /// Entries inherited from a forked parent are read-only.
     pub fn get_mut<T: Any>(&mut self, key: &str) -> Option<&mut T> {
-        self.data.get_mut(key).and_then(|v| v.downcast_mut::<T>())
+        self.data
+            .get_mut(key)
+            .and_then(|entry| entry.value.downcast_mut::<T>())
     }
 
     /// Removes a value by key and returns it.
     ///
     /// Returns `None` if the key doesn't exist or the type doesn't match.
+    /// Entries inherited from a forked parent cannot be removed.
     pub fn remove<T: Any>(&mut self, key: &str) -> Option<T> {
         self.data
             .remove(key)
-            .and_then(|v| v.downcast::<T>().ok())
+            .and_then(|entry| entry.value.downcast::<T>().ok())
             .map(|b| *b)
     }
 
+    /// Moves the value stored under `key` into `target` under `target_key`,
+    /// whatever its type.
+    ///
+    /// Returns `false` if this context has no entry for `key`. Entries
+    /// inherited from a forked parent cannot be moved.
+    pub fn transfer(
+        &mut self,
+        key: &str,
+        target: &mut Context,
+        target_key: impl Into<ContextKey>,
+    ) -> bool {
+        match self.data.remove(key) {
+            Some(value) => {
+                target.data.insert(target_key.into(), value);
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Returns `true` if the context contains a value for the given key.
     pub fn contains_key(&self, key: &str) -> bool {
-        self.data.contains_key(key)
+        self.raw(key).is_some()
     }
 
     /// Returns an iterator over all keys in the context.
     pub fn keys(&self) -> impl Iterator<Item = &ContextKey> {
-        self.data.keys()
+        let own = self.data.keys();
+        let inherited = self.layers().enumerate().flat_map(move |(depth, layer)| {
+            layer
+                .keys()
+                .filter(move |key| !self.shadowed(key.as_str(), depth))
+        });
+        own.chain(inherited)
+    }
+
+    /// Returns `true` if `key` is defined in this context or in a layer
+    /// closer than `depth`.
+    fn shadowed(&self, key: &str, depth: usize) -> bool {
+        self.data.contains_key(key) || self.layers().take(depth).any(|l| l.contains_key(key))
     }
 
     /// Returns the number of entries in the context.
     pub fn len(&self) -> usize {
-        self.data.len()
+        self.keys().count()
     }
 
     /// Returns `true` if the context contains no entries.
     pub fn is_empty(&self) -> bool {
-        self.data.is_empty()
+        self.data.is_empty() && self.layers().all(|layer| layer.is_empty())
     }
 
     /// Removes all entries from the context.
     pub fn clear(&mut self) {
         self.data.clear();
+        self.parent = None;
+        self.forks = 0;
+    }
+
+    /// Splits the context into `branches` child contexts for concurrent work.
+    ///
+    /// Every child can read the entries of this context, while values inserted
+    /// into a child stay private to it until [`join`](Self::join) merges them
+    /// back. Until then, the entries of this context are read-only.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tsumugi_core::Context;
+    ///
+    /// let mut ctx = Context::new();
+    /// ctx.insert("input", 2u32);
+    ///
+    /// let mut branches = ctx.fork(2);
+    /// for (i, branch) in branches.iter_mut().enumerate() {
+    ///     let input = *branch.get::<u32>("input").unwrap_or(&0);
+    ///     branch.insert(format!("output_{}", i), input * 10);
+    /// }
+    /// ctx.join(branches);
+    ///
+    /// assert_eq!(ctx.get::<u32>("input"), Some(&2));
+    /// assert_eq!(ctx.get::<u32>("output_1"), Some(&20));
+    /// ```
+    pub fn fork(&mut self, branches: usize) -> Vec<Context> {
+        let layer = Arc::new(Layer {
+            data: std::mem::take(&mut self.data),
+            parent: self.parent.take(),
+        });
+        self.parent = Some(layer.clone());
+        self.forks += 1;
+        (0..branches)
+            .map(|_| Context {
+                data: HashMap::new(),
+                parent: Some(layer.clone()),
+                forks: 0,
+                cancellation: self.cancellation.clone(),
+                started_at: Instant::now(),
+            })
+            .collect()
+    }
+
+    /// Merges branch contexts created by [`fork`](Self::fork) back into this one.
+    ///
+    /// Entries written by the branches are moved into this context in
+    /// iteration order, so a later branch overwrites an earlier one that wrote
+    /// the same key. Branches that are not passed here are simply dropped; if
+    /// one is still alive elsewhere, the shared entries stay readable but
+    /// cannot be modified.
+    pub fn join(&mut self, branches: impl IntoIterator<Item = Context>) {
+        let written: Vec<Entries> = branches.into_iter().map(|b| b.data).collect();
+
+        if self.forks > 0 {
+            if let Some(layer) = self.parent.take() {
+                match Arc::try_unwrap(layer) {
+                    Ok(layer) => {
+                        let own = std::mem::replace(&mut self.data, layer.data);
+                        self.data.extend(own);
+                        self.parent = layer.parent;
+                        self.forks -= 1;
+                    }
+                    Err(layer) => self.parent = Some(layer),
+                }
+            }
+        }
+
+        for data in written {
+            self.data.extend(data);
+        }
+    }
+
+    /// Like [`fork`](Self::fork), but joins again when the returned guard is
+    /// dropped.
+    ///
+    /// Use this where the branches live in a future that may be dropped
+    /// before it completes, e.g. on a timeout or cancellation: without a
+    /// join, the entries of this context would stay read-only. Call
+    /// [`ForkGuard::join`] to merge the branches' entries; a guard dropped
+    /// without it discards them. Branches still alive when the guard is
+    /// dropped keep this context forked, so drop them first.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tsumugi_core::Context;
+    ///
+    /// let mut ctx = Context::new();
+    /// ctx.insert("count", 1u32);
+    /// {
+    ///     let (_guard, branches) = ctx.fork_scoped(2);
+    ///     drop(branches); // e.g. the work was abandoned
+    /// }
+    /// assert!(ctx.get_mut::<u32>("count").is_some());
+    /// ```
+    pub fn fork_scoped(&mut self, branches: usize) -> (ForkGuard<'_>, Vec<Context>) {
+        let branches = self.fork(branches);
+        (
+            ForkGuard {
+                ctx: self,
+                joined: false,
+            },
+            branches,
+        )
+    }
+
+    /// Returns the cancellation token of this context.
+    ///
+    /// Clone it and call [`CancellationToken::cancel`] from another task to
+    /// stop a running workflow. Contexts created by [`fork`](Self::fork)
+    /// share the token of their parent.
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// Replaces the cancellation token of this context.
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) {
+        self.cancellation = token;
+    }
+
+    /// Returns `true` if cancellation of the workflow has been requested.
+    ///
+    /// Long-running steps should check this and stop early, returning
+    /// [`WorkflowError::Cancelled`](crate::WorkflowError::Cancelled).
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
     }
 
     /// Returns the time elapsed since the context was created.
@@ -166,6 +606,30 @@ impl Context {
     }
 }
 
+/// Joins a context forked by [`Context::fork_scoped`] when dropped.
+#[derive(Debug)]
+#[must_use = "dropping the guard joins the context immediately"]
+pub struct ForkGuard<'a> {
+    ctx: &'a mut Context,
+    joined: bool,
+}
+
+impl ForkGuard<'_> {
+    /// Merges the branches back as [`Context::join`] does.
+    pub fn join(mut self, branches: impl IntoIterator<Item = Context>) {
+        self.ctx.join(branches);
+        self.joined = true;
+    }
+}
+
+impl Drop for ForkGuard<'_> {
+    fn drop(&mut self) {
+        if !self.joined {
+            self.ctx.join(Vec::new());
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -208,6 +672,153 @@ mod tests {
         assert!(!ctx.contains_key("key"));
     }
 
+    #[test]
+    fn test_transfer() {
+        let mut source = Context::new();
+        let mut target = Context::new();
+        source.insert("key", vec![1u8, 2]);
+
+        assert!(source.transfer("key", &mut target, "renamed"));
+        assert!(!source.contains_key("key"));
+        assert_eq!(target.get::<Vec<u8>>("renamed"), Some(&vec![1, 2]));
+        assert!(!source.transfer("key", &mut target, "renamed"));
+    }
+
+    #[test]
+    fn test_fork_and_join() {
+        let mut ctx = Context::new();
+        ctx.insert("shared", 1i32);
+
+        let mut branches = ctx.fork(2);
+        assert_eq!(branches[0].get::<i32>("shared"), Some(&1));
+        assert!(branches[0].get_mut::<i32>("shared").is_none());
+
+        branches[0].insert("a", "first".to_string());
+        branches[1].insert("a", "second".to_string());
+        branches[1].insert("shared", 2i32);
+        assert_eq!(branches[1].len(), 2);
+        assert!(!branches[0].contains_key("b"));
+
+        ctx.join(branches);
+
+        assert_eq!(ctx.get::<String>("a"), Some(&"second".to_string()));
+        assert_eq!(ctx.get::<i32>("shared"), Some(&2));
+        assert_eq!(ctx.len(), 2);
+        if let Some(shared) = ctx.get_mut::<i32>("shared") {
+            *shared += 1;
+        }
+        assert_eq!(ctx.get::<i32>("shared"), Some(&3));
+    }
+
+    #[test]
+    fn test_nested_fork_reads_all_layers() {
+        let mut ctx = Context::new();
+        ctx.insert("root", 1i32);
+
+        let mut branches = ctx.fork(1);
+        branches[0].insert("branch", 2i32);
+        let mut nested = branches[0].fork(1);
+        assert_eq!(nested[0].get::<i32>("root"), Some(&1));
+        assert_eq!(nested[0].get::<i32>("branch"), Some(&2));
+
+        nested[0].insert("leaf", 3i32);
+        branches[0].join(nested);
+        ctx.join(branches);
+
+        assert_eq!(ctx.len(), 3);
+        assert_eq!(ctx.remove::<i32>("leaf"), Some(3));
+    }
+
+    #[test]
+    fn test_fork_guard_joins_on_drop() {
+        let mut ctx = Context::new();
+        ctx.insert("count", 1i32);
+
+        let (guard, mut branches) = ctx.fork_scoped(1);
+        branches[0].insert("discarded", true);
+        drop(branches);
+        drop(guard);
+        assert!(!ctx.contains_key("discarded"));
+        if let Some(count) = ctx.get_mut::<i32>("count") {
+            *count += 1;
+        }
+        assert_eq!(ctx.remove::<i32>("count"), Some(2));
+
+        ctx.insert("count", 1i32);
+        let (guard, mut branches) = ctx.fork_scoped(1);
+        branches[0].insert("kept", true);
+        guard.join(branches);
+        assert_eq!(ctx.len(), 2);
+        assert!(ctx.get_mut::<i32>("count").is_some());
+    }
+
+    #[test]
+    fn test_fork_shares_cancellation_token() {
+        let mut ctx = Context::new();
+        let branches = ctx.fork(2);
+
+        ctx.cancellation_token().cancel();
+        assert!(branches.iter().all(Context::is_cancelled));
+    }
+
+    #[test]
+    fn test_try_get_distinguishes_missing_and_mismatched() {
+        let mut ctx = Context::new();
+        ctx.insert("count", 1i32);
+        let branches = ctx.fork(1);
+
+        assert_eq!(branches[0].try_get::<i32>("count"), Ok(&1));
+        assert_eq!(
+            branches[0].try_get::<i32>("missing"),
+            Err(ContextError::MissingKey {
+                key: ContextKey::new("missing")
+            })
+        );
+        assert_eq!(
+            branches[0]
+                .try_get::<String>("count")
+                .unwrap_err()
+                .to_string(),
+            "Context key 'count' has type i32, expected alloc::string::String"
+        );
+    }
+
+    #[test]
+    fn test_typed_key() {
+        const COUNT: TypedKey<i32> = TypedKey::new("count");
+
+        let mut ctx = Context::new();
+        ctx.insert_typed(&COUNT, 5);
+        assert_eq!(ctx.get_typed(&COUNT), Some(&5));
+        assert_eq!(ctx.get::<i32>(COUNT.name()), Some(&5));
+
+        ctx.insert(COUNT.name(), "five");
+        assert_eq!(ctx.get_typed(&COUNT), None);
+        assert_eq!(format!("{:?}", COUNT), "TypedKey<i32>(\"count\")");
+    }
+
+    #[test]
+    fn test_key_spec_check() {
+        let mut ctx = Context::new();
+        ctx.insert("count", 1i32);
+        let branches = ctx.fork(1);
+
+        assert_eq!(KeySpec::of::<i32>("count").check(&branches[0]), Ok(()));
+        assert_eq!(KeySpec::any("count").check(&branches[0]), Ok(()));
+        assert_eq!(
+            KeySpec::of::<u64>("count").check(&branches[0]),
+            Err(ContextError::TypeMismatch {
+                key: ContextKey::new("count"),
+                expected: "u64",
+                found: "i32",
+            })
+        );
+        assert!(matches!(
+            KeySpec::any("missing").check(&branches[0]),
+            Err(ContextError::MissingKey { .. })
+        ));
+    }
+
     #[test]
     fn test_context_key() {
         let key1 = ContextKey::new("test");
@@ -1,6 +1,8 @@
 //! Workflow error types.
 
+use crate::context::ContextKey;
 use crate::step::StepName;
+use std::time::Duration;
 use thiserror::Error;
 
 /// The type of lifecycle hook that failed.
@@ -22,7 +24,7 @@ impl std::fmt::Display for HookType {
 }
 
 /// Errors that can occur during workflow execution.
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone)]
 #[non_exhaustive]
 pub enum WorkflowError {
     /// A step failed during execution.
@@ -59,12 +61,329 @@ pub enum WorkflowError {
         /// Details about the failure.
         details: String,
     },
+
+    /// Undoing a completed step failed after the workflow failed.
+    #[error("Compensation failed in step: {step_name}, details: {details}")]
+    CompensationFailed {
+        /// The step whose compensation failed.
+        step_name: StepName,
+        /// Details about the failure.
+        details: String,
+    },
+
+    /// The workflow was cancelled through its [`CancellationToken`](crate::CancellationToken).
+    #[error("Workflow cancelled in step: {step_name}")]
+    Cancelled {
+        /// The step that was running or about to start when the workflow was cancelled.
+        step_name: StepName,
+    },
+
+    /// A step failed on every attempt.
+    ///
+    /// Returned instead of the last error when a step was attempted more than
+    /// once, so the full history of a flaky step is preserved.
+    #[error(
+        "Retries exhausted in step: {step_name} after {} attempts: {}",
+        attempts.len(),
+        join_attempts(attempts)
+    )]
+    RetriesExhausted {
+        /// The name of the step that failed.
+        step_name: StepName,
+        /// Every failed attempt, in order.
+        attempts: Vec<AttemptFailure>,
+    },
+
+    /// The workflow-wide deadline passed while the step was running or waiting to retry.
+    #[error("Workflow deadline exceeded in step: {step_name}")]
+    DeadlineExceeded {
+        /// The step that was running when the deadline passed.
+        step_name: StepName,
+    },
+
+    /// The workflow-wide retry budget was used up, so the step was not retried.
+    #[error("Workflow retry budget exhausted in step: {step_name}")]
+    RetryBudgetExhausted {
+        /// The step that could not be retried.
+        step_name: StepName,
+    },
+
+    /// A path ran through more step transitions than the workflow allows.
+    #[error(
+        "Transition limit of {limit} exceeded, recent steps: {}",
+        join_steps(recent_steps)
+    )]
+    TransitionLimitExceeded {
+        /// The configured maximum number of transitions.
+        limit: usize,
+        /// The most recently visited steps, oldest first, ending with the step
+        /// that would have exceeded the limit.
+        recent_steps: Vec<StepName>,
+    },
+
+    /// A step was visited more often than its visit limit allows.
+    #[error(
+        "Visit limit of {limit} exceeded for step: {step_name}, recent steps: {}",
+        join_steps(recent_steps)
+    )]
+    VisitLimitExceeded {
+        /// The step whose visit limit was exceeded.
+        step_name: StepName,
+        /// The configured maximum number of visits.
+        limit: usize,
+        /// The most recently visited steps, oldest first, ending with `step_name`.
+        recent_steps: Vec<StepName>,
+    },
+
+    /// Saving or restoring a checkpoint failed.
+    #[error("Checkpoint failed: {0}")]
+    Checkpoint(String),
+
+    /// A context value could not be read.
+    #[error("{0}")]
+    Context(#[from] ContextError),
+
+    /// A step succeeded without setting a context key it declares to produce.
+    ///
+    /// Only reported when output verification is enabled.
+    #[error("Step '{step_name}' did not produce a declared output: {error}")]
+    InvalidOutput {
+        /// The step that broke its declaration.
+        step_name: StepName,
+        /// What is wrong with the output.
+        error: ContextError,
+    },
+
+    /// The workflow definition is invalid.
+    ///
+    /// Returned by `WorkflowBuilder::build` with every problem found.
+    #[error("Invalid workflow: {}", join_issues(.0))]
+    Validation(Vec<ValidationIssue>),
+
+    /// An error that retrying cannot fix, such as invalid input.
+    ///
+    /// The engine skips any remaining retries of a step failing with this
+    /// error. Create it with [`WorkflowError::permanent`].
+    #[error("{0}")]
+    Permanent(Box<WorkflowError>),
+
+    /// The step was not run because its circuit breaker is open.
+    ///
+    /// The breaker opens after too many consecutive failures and lets a
+    /// trial attempt through once its cool-down has passed.
+    #[error("Circuit breaker '{circuit}' is open in step: {step_name}")]
+    CircuitOpen {
+        /// The step that was not run.
+        step_name: StepName,
+        /// The name of the open circuit breaker.
+        circuit: String,
+    },
+
+    /// A nested workflow run by a step failed.
+    ///
+    /// The display output shows the path from the parent step to the
+    /// failing child step, e.g. `billing -> Step failed: charge, details: ...`.
+    #[error("{step_name} -> {}", join_errors(errors))]
+    SubWorkflow {
+        /// The name of the step that ran the nested workflow.
+        step_name: StepName,
+        /// The errors returned by the nested workflow.
+        errors: Vec<WorkflowError>,
+    },
+}
+
+impl WorkflowError {
+    /// Marks this error as permanent so the failing step is not retried.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tsumugi_core::{StepName, WorkflowError};
+    ///
+    /// let error = WorkflowError::StepError {
+    ///     step_name: StepName::new("validate"),
+    ///     details: "price must be positive".to_string(),
+    /// }
+    /// .permanent();
+    /// assert!(error.is_permanent());
+    /// ```
+    pub fn permanent(self) -> Self {
+        match self {
+            WorkflowError::Permanent(_) => self,
+            error => WorkflowError::Permanent(Box::new(error)),
+        }
+    }
+
+    /// Returns `true` if this error was marked [permanent](Self::permanent).
+    pub fn is_permanent(&self) -> bool {
+        matches!(self, WorkflowError::Permanent(_))
+    }
+}
+
+/// A single failed attempt of a step.
+#[derive(Debug, Clone)]
+pub struct AttemptFailure {
+    /// The error the attempt failed with.
+    pub error: WorkflowError,
+    /// How long the attempt ran before failing.
+    pub duration: Duration,
+    /// Whether the attempt was cut short by a timeout or deadline.
+    pub timed_out: bool,
+}
+
+fn join_attempts(attempts: &[AttemptFailure]) -> String {
+    attempts
+        .iter()
+        .enumerate()
+        .map(|(index, attempt)| format!("[{}] {}", index + 1, attempt.error))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Why a value could not be read from a [`Context`](crate::Context).
+///
+/// Returned by [`Context::try_get`](crate::Context::try_get). Converts into
+/// [`WorkflowError::Context`], so steps can use `?` on it.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ContextError {
+    /// No value is stored under the key.
+    #[error("Context key '{key}' not found")]
+    MissingKey {
+        /// The key that was looked up.
+        key: ContextKey,
+    },
+
+    /// The stored value has a different type than requested.
+    #[error("Context key '{key}' has type {found}, expected {expected}")]
+    TypeMismatch {
+        /// The key that was looked up.
+        key: ContextKey,
+        /// The type that was requested.
+        expected: &'static str,
+        /// The type of the stored value.
+        found: &'static str,
+    },
+}
+
+/// A problem found while validating a workflow definition.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ValidationIssue {
+    /// A step name is referenced but no such step is registered.
+    #[error("Unknown step '{step_name}'{}", referenced_by.as_ref().map(|s| format!(" referenced by '{s}'")).unwrap_or_default())]
+    UnknownStep {
+        /// The name that could not be resolved.
+        step_name: StepName,
+        /// The step that references it, or `None` for workflow settings
+        /// such as the start step.
+        referenced_by: Option<StepName>,
+    },
+
+    /// A step cannot be reached from the start step.
+    #[error("Step '{0}' is unreachable from the start step")]
+    UnreachableStep(StepName),
+
+    /// Every path from a step loops forever instead of completing.
+    #[error("Step '{0}' has no path to completion")]
+    NoPathToCompletion(StepName),
+
+    /// A step requires a context key that is not set on every path to it.
+    #[error(
+        "Step '{step_name}' requires context key '{key}', which is not set on every path to it"
+    )]
+    MissingInput {
+        /// The step requiring the key.
+        step_name: StepName,
+        /// The key that may be missing.
+        key: ContextKey,
+    },
+
+    /// A step requires a context key with another type than is produced.
+    #[error("Step '{step_name}' requires context key '{key}' as {expected}, but it is produced as {found}")]
+    InputTypeMismatch {
+        /// The step requiring the key.
+        step_name: StepName,
+        /// The key with conflicting types.
+        key: ContextKey,
+        /// The type the step requires.
+        expected: &'static str,
+        /// The type the key is produced with.
+        found: &'static str,
+    },
+}
+
+fn join_issues(issues: &[ValidationIssue]) -> String {
+    issues
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn join_steps(steps: &[StepName]) -> String {
+    steps
+        .iter()
+        .map(StepName::as_str)
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+fn join_errors(errors: &[WorkflowError]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_context_error_display() {
+        let error: WorkflowError = ContextError::TypeMismatch {
+            key: ContextKey::new("scores"),
+            expected: "u64",
+            found: "i32",
+        }
+        .into();
+        assert_eq!(
+            error.to_string(),
+            "Context key 'scores' has type i32, expected u64"
+        );
+        assert_eq!(
+            ContextError::MissingKey {
+                key: ContextKey::new("scores")
+            }
+            .to_string(),
+            "Context key 'scores' not found"
+        );
+    }
+
+    #[test]
+    fn test_input_issue_display() {
+        let issue = ValidationIssue::MissingInput {
+            step_name: StepName::new("ship"),
+            key: ContextKey::new("order"),
+        };
+        assert_eq!(
+            issue.to_string(),
+            "Step 'ship' requires context key 'order', which is not set on every path to it"
+        );
+        let error = WorkflowError::InvalidOutput {
+            step_name: StepName::new("load"),
+            error: ContextError::MissingKey {
+                key: ContextKey::new("order"),
+            },
+        };
+        assert_eq!(
+            error.to_string(),
+            "Step 'load' did not produce a declared output: Context key 'order' not found"
+        );
+    }
+
     #[test]
     fn test_error_display() {
         let error = WorkflowError::StepError {
@@ -77,6 +396,141 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_compensation_failed_display() {
+        let error = WorkflowError::CompensationFailed {
+            step_name: StepName::new("payment"),
+            details: "refund declined".to_string(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "Compensation failed in step: payment, details: refund declined"
+        );
+    }
+
+    #[test]
+    fn test_checkpoint_error_display() {
+        let error = WorkflowError::Checkpoint("disk full".to_string());
+        assert_eq!(error.to_string(), "Checkpoint failed: disk full");
+    }
+
+    #[test]
+    fn test_circuit_open_display() {
+        let error = WorkflowError::CircuitOpen {
+            step_name: StepName::new("notify"),
+            circuit: "webhook".to_string(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "Circuit breaker 'webhook' is open in step: notify"
+        );
+    }
+
+    #[test]
+    fn test_sub_workflow_error_display() {
+        let error = WorkflowError::SubWorkflow {
+            step_name: StepName::new("outer"),
+            errors: vec![WorkflowError::SubWorkflow {
+                step_name: StepName::new("inner"),
+                errors: vec![
+                    WorkflowError::StepNotFound(StepName::new("missing")),
+                    WorkflowError::Timeout {
+                        step_name: StepName::new("slow"),
+                    },
+                ],
+            }],
+        };
+        assert_eq!(
+            error.to_string(),
+            "outer -> inner -> Step not found: missing; Timeout occurred in step: slow"
+        );
+    }
+
+    #[test]
+    fn test_transition_limit_error_display() {
+        let error = WorkflowError::TransitionLimitExceeded {
+            limit: 3,
+            recent_steps: vec![
+                StepName::new("a"),
+                StepName::new("b"),
+                StepName::new("a"),
+                StepName::new("b"),
+            ],
+        };
+        assert_eq!(
+            error.to_string(),
+            "Transition limit of 3 exceeded, recent steps: a -> b -> a -> b"
+        );
+    }
+
+    #[test]
+    fn test_validation_error_display() {
+        let error = WorkflowError::Validation(vec![
+            ValidationIssue::UnknownStep {
+                step_name: StepName::new("paymnet"),
+                referenced_by: Some(StepName::new("order")),
+            },
+            ValidationIssue::UnknownStep {
+                step_name: StepName::new("begin"),
+                referenced_by: None,
+            },
+            ValidationIssue::UnreachableStep(StepName::new("orphan")),
+        ]);
+        assert_eq!(
+            error.to_string(),
+            "Invalid workflow: Unknown step 'paymnet' referenced by 'order'; \
+             Unknown step 'begin'; Step 'orphan' is unreachable from the start step"
+        );
+    }
+
+    #[test]
+    fn test_retries_exhausted_display() {
+        let step_name = StepName::new("fetch");
+        let error = WorkflowError::RetriesExhausted {
+            step_name: step_name.clone(),
+            attempts: vec![
+                AttemptFailure {
+                    error: WorkflowError::Timeout {
+                        step_name: step_name.clone(),
+                    },
+                    duration: Duration::from_secs(1),
+                    timed_out: true,
+                },
+                AttemptFailure {
+                    error: WorkflowError::StepError {
+                        step_name,
+                        details: "connection reset".to_string(),
+                    },
+                    duration: Duration::from_millis(20),
+                    timed_out: false,
+                },
+            ],
+        };
+        assert_eq!(
+            error.to_string(),
+            "Retries exhausted in step: fetch after 2 attempts: \
+             [1] Timeout occurred in step: fetch; \
+             [2] Step failed: fetch, details: connection reset"
+        );
+    }
+
+    #[test]
+    fn test_permanent_error() {
+        let error = WorkflowError::Configuration("bad input".to_string());
+        assert!(!error.is_permanent());
+
+        let error = error.permanent().permanent();
+        assert!(error.is_permanent());
+        assert!(matches!(
+            &error,
+            WorkflowError::Permanent(inner) if matches!(**inner, WorkflowError::Configuration(_))
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Invalid workflow configuration: bad input"
+        );
+    }
+
     #[test]
     fn test_hook_type_display() {
         assert_eq!(HookType::OnSuccess.to_string(), "on_success");
@@ -0,0 +1,456 @@
+//! JSON serialization of [`Context`] entries.
+//!
+//! Available with the `serde` feature.
+
+use crate::context::{Context, ContextKey, Entry};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use thiserror::Error;
+
+/// Serializes a stored value back into JSON.
+pub(crate) type SerializeFn = fn(&(dyn Any + Send + Sync)) -> Result<Value, serde_json::Error>;
+
+type DeserializeFn = fn(Value) -> Result<Entry, serde_json::Error>;
+
+fn serialize_value<T: Serialize + Any>(
+    value: &(dyn Any + Send + Sync),
+) -> Result<Value, serde_json::Error> {
+    match value.downcast_ref::<T>() {
+        Some(value) => serde_json::to_value(value),
+        None => Err(serde::ser::Error::custom(format!(
+            "not a {}",
+            std::any::type_name::<T>()
+        ))),
+    }
+}
+
+fn deserialize_value<T>(value: Value) -> Result<Entry, serde_json::Error>
+where
+    T: Serialize + DeserializeOwned + Any + Send + Sync,
+{
+    let value: T = serde_json::from_value(value)?;
+    Ok(serializable(value))
+}
+
+fn serializable<T: Serialize + Any + Send + Sync>(value: T) -> Entry {
+    Entry {
+        serialize: Some(serialize_value::<T>),
+        ..Entry::new(value)
+    }
+}
+
+/// Errors from restoring a [`Context`] with [`Context::from_json`].
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum ContextSerdeError {
+    /// A value did not match the type the schema declares for its key.
+    #[error("Cannot deserialize key '{key}': {source}")]
+    Deserialize {
+        /// The key whose value was rejected.
+        key: ContextKey,
+        /// The underlying `serde_json` error.
+        #[source]
+        source: serde_json::Error,
+    },
+
+    /// The JSON object has keys the schema does not declare, sorted by name.
+    #[error("Keys missing from the schema: {}", join_keys(.0))]
+    UnknownKeys(Vec<ContextKey>),
+
+    /// The JSON value is not an object; holds the kind of value found.
+    #[error("Expected a JSON object, found {0}")]
+    NotAnObject(&'static str),
+}
+
+fn join_keys(keys: &[ContextKey]) -> String {
+    keys.iter()
+        .map(ContextKey::as_str)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A JSON object of a [`Context`]'s entries, returned by [`Context::to_json`].
+///
+/// Entries that cannot be serialized are left out of the object and listed
+/// in [`skipped`](Self::skipped) instead, so one opaque value does not cost
+/// the rest of the snapshot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonSnapshot {
+    json: Value,
+    skipped: Vec<ContextKey>,
+}
+
+impl JsonSnapshot {
+    /// The serialized entries, as a JSON object keyed by context key.
+    pub fn json(&self) -> &Value {
+        &self.json
+    }
+
+    /// Consumes the snapshot, returning the JSON object.
+    pub fn into_json(self) -> Value {
+        self.json
+    }
+
+    /// Keys left out of the object, sorted by name.
+    ///
+    /// A key is skipped if it was not inserted with
+    /// [`insert_serializable`](Context::insert_serializable) or its value
+    /// failed to serialize.
+    pub fn skipped(&self) -> &[ContextKey] {
+        &self.skipped
+    }
+
+    /// Returns `true` if no key was skipped.
+    pub fn is_complete(&self) -> bool {
+        self.skipped.is_empty()
+    }
+}
+
+/// The keys and value types [`Context::from_json`] restores.
+///
+/// # Examples
+///
+/// ```
+/// use tsumugi_core::{Context, ContextSchema};
+///
+/// let schema = ContextSchema::new()
+///     .key::<u64>("user_id")
+///     .key::<Vec<String>>("roles");
+///
+/// let json = serde_json::json!({ "user_id": 7, "roles": ["admin"] });
+/// let ctx = Context::from_json(&json, &schema).unwrap();
+///
+/// assert_eq!(ctx.get::<u64>("user_id"), Some(&7));
+/// assert_eq!(ctx.to_json().json(), &json);
+/// ```
+#[derive(Clone, Default)]
+pub struct ContextSchema {
+    keys: HashMap<ContextKey, Codec>,
+}
+
+/// How a schema converts the value of one key.
+#[derive(Clone, Copy)]
+struct Codec {
+    serialize: SerializeFn,
+    deserialize: DeserializeFn,
+}
+
+impl ContextSchema {
+    /// Creates an empty schema.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares that `key` holds a `T`.
+    ///
+    /// Declaring the same key again replaces its type.
+    pub fn key<T>(mut self, key: impl Into<ContextKey>) -> Self
+    where
+        T: Serialize + DeserializeOwned + Any + Send + Sync,
+    {
+        self.keys.insert(
+            key.into(),
+            Codec {
+                serialize: serialize_value::<T>,
+                deserialize: deserialize_value::<T>,
+            },
+        );
+        self
+    }
+
+    /// Returns `true` if `key` is declared in this schema.
+    pub fn contains(&self, key: &str) -> bool {
+        self.keys.contains_key(key)
+    }
+}
+
+impl fmt::Debug for ContextSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut keys: Vec<_> = self.keys.keys().map(ContextKey::as_str).collect();
+        keys.sort_unstable();
+        f.debug_struct("ContextSchema")
+            .field("keys", &keys)
+            .finish()
+    }
+}
+
+impl Context {
+    /// Inserts a value that [`to_json`](Self::to_json) can serialize.
+    ///
+    /// The value is stored and read back like one added with
+    /// [`insert`](Self::insert). Replacing it with `insert` makes the key
+    /// non-serializable again.
+    pub fn insert_serializable<T>(&mut self, key: impl Into<ContextKey>, value: T)
+    where
+        T: Serialize + DeserializeOwned + Any + Send + Sync,
+    {
+        self.insert_entry(key.into(), serializable(value));
+    }
+
+    /// Serializes every entry it can into a JSON object keyed by context key.
+    ///
+    /// Entries inserted with [`insert_serializable`](Self::insert_serializable)
+    /// are serialized; the keys of all others are reported in
+    /// [`JsonSnapshot::skipped`].
+    pub fn to_json(&self) -> JsonSnapshot {
+        let mut keys: Vec<&ContextKey> = self.keys().collect();
+        keys.sort_unstable_by(|a, b| a.as_str().cmp(b.as_str()));
+
+        let mut object = Map::new();
+        let mut skipped = Vec::new();
+        for key in keys {
+            let value = self
+                .entry(key.as_str())
+                .and_then(|entry| entry.serialize?(entry.value.as_ref()).ok());
+            match value {
+                Some(value) => {
+                    object.insert(key.to_string(), value);
+                }
+                None => skipped.push(key.clone()),
+            }
+        }
+
+        JsonSnapshot {
+            json: Value::Object(object),
+            skipped,
+        }
+    }
+
+    /// Serializes the entries of the keys declared in `schema` into a JSON
+    /// object, however they were inserted.
+    ///
+    /// Keys this context has no entry for are left out. Keys whose value is
+    /// not of the declared type, or fails to serialize, are reported in
+    /// [`JsonSnapshot::skipped`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tsumugi_core::{Context, ContextSchema};
+    ///
+    /// let schema = ContextSchema::new().key::<u64>("user_id").key::<bool>("admin");
+    /// let mut ctx = Context::new();
+    /// ctx.insert("user_id", 7u64);
+    ///
+    /// let snapshot = ctx.to_json_with(&schema);
+    /// assert!(snapshot.is_complete());
+    /// assert_eq!(snapshot.json(), &serde_json::json!({ "user_id": 7 }));
+    /// ```
+    pub fn to_json_with(&self, schema: &ContextSchema) -> JsonSnapshot {
+        let mut keys: Vec<(&ContextKey, &Codec)> = schema.keys.iter().collect();
+        keys.sort_unstable_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+
+        let mut object = Map::new();
+        let mut skipped = Vec::new();
+        for (key, codec) in keys {
+            if let Some(entry) = self.entry(key.as_str()) {
+                match (codec.serialize)(entry.value.as_ref()) {
+                    Ok(value) => {
+                        object.insert(key.to_string(), value);
+                    }
+                    Err(_) => skipped.push(key.clone()),
+                }
+            }
+        }
+
+        JsonSnapshot {
+            json: Value::Object(object),
+            skipped,
+        }
+    }
+
+    /// Restores a context from a JSON object produced by
+    /// [`to_json`](Self::to_json).
+    ///
+    /// Skipped keys are not part of the object, so they are not restored.
+    ///
+    /// Every key of the object must be declared in `schema`. Restored
+    /// values are serializable again.
+    pub fn from_json(json: &Value, schema: &ContextSchema) -> Result<Context, ContextSerdeError> {
+        let object = match json {
+            Value::Object(object) => object,
+            other => return Err(ContextSerdeError::NotAnObject(json_type(other))),
+        };
+
+        let mut unknown: Vec<ContextKey> = object
+            .keys()
+            .filter(|key| !schema.keys.contains_key(key.as_str()))
+            .map(|key| ContextKey::new(key.as_str()))
+            .collect();
+        if !unknown.is_empty() {
+            unknown.sort_unstable_by(|a, b| a.as_str().cmp(b.as_str()));
+            return Err(ContextSerdeError::UnknownKeys(unknown));
+        }
+
+        let mut ctx = Context::new();
+        for (key, value) in object {
+            if let Some((key, codec)) = schema.keys.get_key_value(key.as_str()) {
+                let entry = (codec.deserialize)(value.clone()).map_err(|source| {
+                    ContextSerdeError::Deserialize {
+                        key: key.clone(),
+                        source,
+                    }
+                })?;
+                ctx.insert_entry(key.clone(), entry);
+            }
+        }
+        Ok(ctx)
+    }
+}
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Order {
+        id: u64,
+        items: Vec<String>,
+    }
+
+    fn schema() -> ContextSchema {
+        ContextSchema::new()
+            .key::<Order>("order")
+            .key::<bool>("paid")
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut ctx = Context::new();
+        ctx.insert_serializable(
+            "order",
+            Order {
+                id: 1,
+                items: vec!["book".to_string()],
+            },
+        );
+        ctx.insert_serializable("paid", true);
+
+        let json = ctx.to_json().into_json();
+        assert_eq!(
+            json,
+            serde_json::json!({ "order": { "id": 1, "items": ["book"] }, "paid": true })
+        );
+
+        let mut restored = Context::from_json(&json, &schema()).unwrap();
+        assert_eq!(restored.get::<Order>("order").map(|o| o.id), Some(1));
+        if let Some(paid) = restored.get_mut::<bool>("paid") {
+            *paid = false;
+        }
+        assert_eq!(restored.to_json().json()["paid"], false);
+    }
+
+    #[test]
+    fn test_to_json_skips_non_serializable_keys() {
+        let mut ctx = Context::new();
+        ctx.insert_serializable("paid", true);
+        ctx.insert("b_handle", 1u8);
+        ctx.insert("a_handle", 2u8);
+        // Maps with non-string keys have no JSON representation.
+        ctx.insert_serializable("by_id", HashMap::from([((1u8, 2u8), true)]));
+
+        let snapshot = ctx.to_json();
+        assert!(!snapshot.is_complete());
+        assert_eq!(snapshot.json(), &serde_json::json!({ "paid": true }));
+        assert_eq!(
+            snapshot
+                .skipped()
+                .iter()
+                .map(ContextKey::as_str)
+                .collect::<Vec<_>>(),
+            vec!["a_handle", "b_handle", "by_id"]
+        );
+
+        // Replacing with a plain insert drops the serializer.
+        ctx.remove::<u8>("a_handle");
+        ctx.remove::<u8>("b_handle");
+        ctx.remove::<HashMap<(u8, u8), bool>>("by_id");
+        ctx.insert("paid", false);
+        let snapshot = ctx.to_json();
+        assert_eq!(snapshot.json(), &serde_json::json!({}));
+        assert_eq!(snapshot.skipped().len(), 1);
+    }
+
+    #[test]
+    fn test_serializers_follow_fork_and_transfer() {
+        let mut ctx = Context::new();
+        ctx.insert_serializable("paid", true);
+
+        let mut branches = ctx.fork(1);
+        branches[0].insert_serializable(
+            "order",
+            Order {
+                id: 2,
+                items: vec![],
+            },
+        );
+        assert!(branches[0].to_json().is_complete());
+        assert_eq!(branches[0].to_json().json()["paid"], true);
+        ctx.join(branches);
+
+        let mut target = Context::new();
+        assert!(ctx.transfer("order", &mut target, "order"));
+        assert_eq!(target.to_json().json()["order"]["id"], 2);
+        assert_eq!(ctx.to_json().json(), &serde_json::json!({ "paid": true }));
+    }
+
+    #[test]
+    fn test_to_json_with_schema() {
+        let mut ctx = Context::new();
+        ctx.insert(
+            "order",
+            Order {
+                id: 3,
+                items: vec![],
+            },
+        );
+        ctx.insert("paid", "yes");
+        ctx.insert("other", 1u8);
+
+        let snapshot = ctx.to_json_with(&schema());
+        assert_eq!(
+            snapshot.json(),
+            &serde_json::json!({ "order": { "id": 3, "items": [] } })
+        );
+        assert_eq!(snapshot.skipped(), &[ContextKey::new("paid")]);
+        assert!(schema().contains("paid"));
+        assert!(!schema().contains("other"));
+
+        let restored = Context::from_json(snapshot.json(), &schema()).unwrap();
+        assert_eq!(restored.get::<Order>("order").map(|o| o.id), Some(3));
+    }
+
+    #[test]
+    fn test_from_json_errors() {
+        assert!(matches!(
+            Context::from_json(&serde_json::json!([1]), &schema()),
+            Err(ContextSerdeError::NotAnObject("an array"))
+        ));
+
+        let err = Context::from_json(&serde_json::json!({ "paid": true, "x": 1 }), &schema())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Keys missing from the schema: x");
+
+        let err = Context::from_json(&serde_json::json!({ "paid": "yes" }), &schema()).unwrap_err();
+        assert!(matches!(
+            err,
+            ContextSerdeError::Deserialize { ref key, .. } if key.as_str() == "paid"
+        ));
+    }
+}
@@ -8,20 +8,39 @@
 //! - [`Step`] - The core trait for workflow steps
 //! - [`StepOutput`] - Result of step execution
 //! - [`Context`] - Heterogeneous type storage for sharing data between steps
+//! - [`TypedKey`] - A context key bound to the type of its value
+//! - [`KeySpec`] - A context key a step requires or produces
+//! - [`CancellationToken`] - Cooperative cancellation of running workflows
 //! - [`WorkflowError`] - Error types for workflow execution
+//! - [`ValidationIssue`] - Problems found when building a workflow
 //!
 //! # Optional Traits
 //!
 //! - [`WithHooks`] - Add lifecycle callbacks (on_success, on_failure)
 //! - [`Retryable`] - Configure retry policy
 //! - [`WithTimeout`] - Configure custom timeout
+//! - [`Compensable`] - Undo a completed step when a later step fails
+//!
+//! # Features
+//!
+//! - `serde` - `Context::insert_serializable`, `Context::to_json`,
+//!   `Context::to_json_with` and `Context::from_json` for JSON snapshots of
+//!   a context
 
+mod backoff;
+mod cancellation;
 mod context;
 mod error;
+#[cfg(feature = "serde")]
+mod json;
 mod step;
 mod traits;
 
-pub use context::{Context, ContextKey};
-pub use error::{HookType, WorkflowError};
+pub use backoff::{BackoffStrategy, Jitter};
+pub use cancellation::{CancellationToken, Cancelled};
+pub use context::{Context, ContextKey, ForkGuard, KeySpec, TypedKey};
+pub use error::{AttemptFailure, ContextError, HookType, ValidationIssue, WorkflowError};
+#[cfg(feature = "serde")]
+pub use json::{ContextSchema, ContextSerdeError, JsonSnapshot};
 pub use step::{RetryPolicy, RetryPolicyError, Step, StepConfig, StepName, StepOutput};
-pub use traits::{Retryable, WithHooks, WithTimeout};
+pub use traits::{Compensable, Retryable, WithHooks, WithTimeout};
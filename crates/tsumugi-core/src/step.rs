@@ -1,9 +1,11 @@
 //! Step trait and related types.
 
-use crate::context::Context;
+use crate::backoff::{self, BackoffStrategy, Jitter};
+use crate::context::{Context, KeySpec};
 use crate::error::WorkflowError;
 use async_trait::async_trait;
 use std::fmt::{self, Debug};
+use std::sync::Arc;
 use std::time::Duration;
 
 /// Type-safe step name wrapper.
@@ -115,10 +117,38 @@ pub trait Step: Send + Sync + Debug {
 
     /// Returns the step name.
     fn name(&self) -> StepName;
+
+    /// Declares the outputs this step can return, if known.
+    ///
+    /// When declared, the workflow builder validates every transition at
+    /// build time. Include [`StepOutput::Complete`] if the step can end the
+    /// workflow. Returns `None` (undeclared) by default.
+    fn successors(&self) -> Option<Vec<StepOutput>> {
+        None
+    }
+
+    /// Declares the context keys this step reads and expects to be set.
+    ///
+    /// The workflow builder checks that each key is produced by an earlier
+    /// step on every path to this one, or declared as an initial input.
+    /// Returns no keys by default.
+    fn required_keys(&self) -> Vec<KeySpec> {
+        Vec::new()
+    }
+
+    /// Declares the context keys this step sets whenever it succeeds.
+    ///
+    /// Used by the workflow builder to check the
+    /// [required keys](Self::required_keys) of later steps, and verified
+    /// after each run of the step when output verification is enabled.
+    /// Returns no keys by default.
+    fn produced_keys(&self) -> Vec<KeySpec> {
+        Vec::new()
+    }
 }
 
 /// Retry policy for step execution.
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Default)]
 pub enum RetryPolicy {
     /// No retry - fail immediately on error.
     #[default]
@@ -130,6 +160,17 @@ pub enum RetryPolicy {
         /// Delay between each retry.
         delay: Duration,
     },
+    /// Delay growing by a fixed increment with each retry.
+    Linear {
+        /// Maximum number of retry attempts.
+        max_retries: u32,
+        /// Initial delay before first retry.
+        initial_delay: Duration,
+        /// Amount added to the delay for each further retry.
+        increment: Duration,
+        /// Maximum delay cap.
+        max_delay: Duration,
+    },
     /// Exponential backoff with configurable parameters.
     ExponentialBackoff {
         /// Maximum number of retry attempts.
@@ -141,8 +182,138 @@ pub enum RetryPolicy {
         /// Multiplier for each retry.
         multiplier: u32,
     },
+    /// Exponential backoff with a fractional multiplier, e.g. `1.5`.
+    ///
+    /// Created with [`RetryPolicy::fractional_backoff`]. Policies compare
+    /// equal if their multipliers have the same bits.
+    FractionalBackoff {
+        /// Maximum number of retry attempts.
+        max_retries: u32,
+        /// Initial delay before first retry.
+        initial_delay: Duration,
+        /// Maximum delay cap.
+        max_delay: Duration,
+        /// Multiplier for each retry.
+        multiplier: f64,
+    },
+    /// Another policy with randomized delays.
+    ///
+    /// Created with [`RetryPolicy::with_jitter`].
+    Jittered {
+        /// The policy providing the base delays.
+        policy: Box<RetryPolicy>,
+        /// How the base delays are randomized.
+        jitter: Jitter,
+        /// Seed for reproducible delays, e.g. in tests.
+        seed: Option<u64>,
+    },
+    /// Delays computed by a user-supplied [`BackoffStrategy`].
+    Custom {
+        /// Maximum number of retry attempts.
+        max_retries: u32,
+        /// The strategy computing each delay.
+        strategy: Arc<dyn BackoffStrategy>,
+    },
+}
+
+impl PartialEq for RetryPolicy {
+    /// Custom policies are equal only if they share the same strategy.
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (RetryPolicy::None, RetryPolicy::None) => true,
+            (
+                RetryPolicy::Fixed { max_retries, delay },
+                RetryPolicy::Fixed {
+                    max_retries: other_retries,
+                    delay: other_delay,
+                },
+            ) => max_retries == other_retries && delay == other_delay,
+            (
+                RetryPolicy::Linear {
+                    max_retries,
+                    initial_delay,
+                    increment,
+                    max_delay,
+                },
+                RetryPolicy::Linear {
+                    max_retries: other_retries,
+                    initial_delay: other_initial,
+                    increment: other_increment,
+                    max_delay: other_max,
+                },
+            ) => {
+                max_retries == other_retries
+                    && initial_delay == other_initial
+                    && increment == other_increment
+                    && max_delay == other_max
+            }
+            (
+                RetryPolicy::ExponentialBackoff {
+                    max_retries,
+                    initial_delay,
+                    max_delay,
+                    multiplier,
+                },
+                RetryPolicy::ExponentialBackoff {
+                    max_retries: other_retries,
+                    initial_delay: other_initial,
+                    max_delay: other_max,
+                    multiplier: other_multiplier,
+                },
+            ) => {
+                max_retries == other_retries
+                    && initial_delay == other_initial
+                    && max_delay == other_max
+                    && multiplier == other_multiplier
+            }
+            (
+                RetryPolicy::FractionalBackoff {
+                    max_retries,
+                    initial_delay,
+                    max_delay,
+                    multiplier,
+                },
+                RetryPolicy::FractionalBackoff {
+                    max_retries: other_retries,
+                    initial_delay: other_initial,
+                    max_delay: other_max,
+                    multiplier: other_multiplier,
+                },
+            ) => {
+                max_retries == other_retries
+                    && initial_delay == other_initial
+                    && max_delay == other_max
+                    && multiplier.to_bits() == other_multiplier.to_bits()
+            }
+            (
+                RetryPolicy::Jittered {
+                    policy,
+                    jitter,
+                    seed,
+                },
+                RetryPolicy::Jittered {
+                    policy: other_policy,
+                    jitter: other_jitter,
+                    seed: other_seed,
+                },
+            ) => policy == other_policy && jitter == other_jitter && seed == other_seed,
+            (
+                RetryPolicy::Custom {
+                    max_retries,
+                    strategy,
+                },
+                RetryPolicy::Custom {
+                    max_retries: other_retries,
+                    strategy: other_strategy,
+                },
+            ) => max_retries == other_retries && Arc::ptr_eq(strategy, other_strategy),
+            _ => false,
+        }
+    }
 }
 
+impl Eq for RetryPolicy {}
+
 /// Error returned when [`RetryPolicy`] configuration is invalid.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct RetryPolicyError(pub &'static str);
@@ -161,6 +332,17 @@ impl RetryPolicy {
         RetryPolicy::Fixed { max_retries, delay }
     }
 
+    /// Creates a linear retry policy: `initial_delay`, then `increment` more
+    /// for each further retry, capped at 60 seconds.
+    pub fn linear(max_retries: u32, initial_delay: Duration, increment: Duration) -> Self {
+        RetryPolicy::Linear {
+            max_retries,
+            initial_delay,
+            increment,
+            max_delay: Duration::from_secs(60),
+        }
+    }
+
     /// Creates an exponential backoff retry policy with default settings.
     pub fn exponential(max_retries: u32, initial_delay: Duration) -> Self {
         RetryPolicy::ExponentialBackoff {
@@ -172,6 +354,9 @@ impl RetryPolicy {
     }
 
     /// Creates an exponential backoff retry policy with custom settings.
+    ///
+    /// Delays saturate at `max_delay` however large the multiplier or
+    /// attempt number.
     pub fn exponential_backoff(
         max_retries: u32,
         initial_delay: Duration,
@@ -181,11 +366,6 @@ impl RetryPolicy {
         if multiplier == 0 {
             return Err(RetryPolicyError("multiplier must be greater than 0"));
         }
-        if multiplier > 10 {
-            return Err(RetryPolicyError(
-                "multiplier must be 10 or less to avoid overflow",
-            ));
-        }
         if max_delay < initial_delay {
             return Err(RetryPolicyError("max_delay must be >= initial_delay"));
         }
@@ -197,12 +377,97 @@ impl RetryPolicy {
         })
     }
 
+    /// Creates an exponential backoff retry policy with a fractional
+    /// multiplier, e.g. `1.5`.
+    ///
+    /// Like [`exponential_backoff`](Self::exponential_backoff), but the
+    /// multiplier may be any finite number greater than 0.
+    pub fn fractional_backoff(
+        max_retries: u32,
+        initial_delay: Duration,
+        max_delay: Duration,
+        multiplier: f64,
+    ) -> Result<Self, RetryPolicyError> {
+        if !multiplier.is_finite() || multiplier <= 0.0 {
+            return Err(RetryPolicyError("multiplier must be greater than 0"));
+        }
+        if max_delay < initial_delay {
+            return Err(RetryPolicyError("max_delay must be >= initial_delay"));
+        }
+        Ok(RetryPolicy::FractionalBackoff {
+            max_retries,
+            initial_delay,
+            max_delay,
+            multiplier,
+        })
+    }
+
+    /// Creates a policy whose delays are computed by `strategy`.
+    pub fn custom(max_retries: u32, strategy: impl BackoffStrategy + 'static) -> Self {
+        RetryPolicy::Custom {
+            max_retries,
+            strategy: Arc::new(strategy),
+        }
+    }
+
+    /// Randomizes the delays of this policy.
+    ///
+    /// Replaces any jitter already applied. Has no effect on
+    /// [`RetryPolicy::None`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use tsumugi_core::{Jitter, RetryPolicy};
+    ///
+    /// let policy = RetryPolicy::exponential(5, Duration::from_millis(100))
+    ///     .with_jitter(Jitter::Full)
+    ///     .with_seed(42);
+    /// let delay = policy.delay_for_attempt(1).unwrap_or_default();
+    /// assert!(delay < Duration::from_millis(200));
+    /// assert_eq!(policy.delay_for_attempt(1), Some(delay));
+    /// ```
+    pub fn with_jitter(self, jitter: Jitter) -> Self {
+        match self {
+            RetryPolicy::None => RetryPolicy::None,
+            RetryPolicy::Jittered { policy, seed, .. } => RetryPolicy::Jittered {
+                policy,
+                jitter,
+                seed,
+            },
+            policy => RetryPolicy::Jittered {
+                policy: Box::new(policy),
+                jitter,
+                seed: None,
+            },
+        }
+    }
+
+    /// Makes jittered delays deterministic for the given seed.
+    ///
+    /// Has no effect on policies without [jitter](Self::with_jitter).
+    pub fn with_seed(self, seed: u64) -> Self {
+        match self {
+            RetryPolicy::Jittered { policy, jitter, .. } => RetryPolicy::Jittered {
+                policy,
+                jitter,
+                seed: Some(seed),
+            },
+            policy => policy,
+        }
+    }
+
     /// Returns the maximum number of retries for this policy.
     pub fn max_retries(&self) -> u32 {
         match self {
             RetryPolicy::None => 0,
             RetryPolicy::Fixed { max_retries, .. } => *max_retries,
+            RetryPolicy::Linear { max_retries, .. } => *max_retries,
             RetryPolicy::ExponentialBackoff { max_retries, .. } => *max_retries,
+            RetryPolicy::FractionalBackoff { max_retries, .. } => *max_retries,
+            RetryPolicy::Jittered { policy, .. } => policy.max_retries(),
+            RetryPolicy::Custom { max_retries, .. } => *max_retries,
         }
     }
 
@@ -211,21 +476,94 @@ impl RetryPolicy {
         match self {
             RetryPolicy::None => None,
             RetryPolicy::Fixed { delay, .. } => Some(*delay),
+            RetryPolicy::Linear {
+                initial_delay,
+                increment,
+                max_delay,
+                ..
+            } => {
+                let delay = initial_delay.saturating_add(increment.saturating_mul(attempt));
+                Some(delay.min(*max_delay))
+            }
             RetryPolicy::ExponentialBackoff {
                 initial_delay,
                 max_delay,
                 multiplier,
                 ..
+            } => Some(exponential_delay(
+                *initial_delay,
+                *max_delay,
+                f64::from(*multiplier),
+                attempt,
+            )),
+            RetryPolicy::FractionalBackoff {
+                initial_delay,
+                max_delay,
+                multiplier,
+                ..
+            } => Some(exponential_delay(
+                *initial_delay,
+                *max_delay,
+                *multiplier,
+                attempt,
+            )),
+            RetryPolicy::Jittered {
+                policy,
+                jitter,
+                seed,
             } => {
-                let delay = initial_delay.as_millis() as u64 * (*multiplier as u64).pow(attempt);
-                Some(Duration::from_millis(
-                    delay.min(max_delay.as_millis() as u64),
-                ))
+                let delay = policy.delay_for_attempt(attempt)?;
+                let random = backoff::random(*seed, attempt);
+                Some(match jitter {
+                    Jitter::Full => backoff::between(Duration::ZERO, delay, random),
+                    Jitter::Equal => {
+                        let half = delay / 2;
+                        half + backoff::between(Duration::ZERO, delay - half, random)
+                    }
+                    Jitter::Decorrelated { cap } => {
+                        // Replays the chain of jittered delays up to this
+                        // attempt. Deeper attempts restart the chain a window
+                        // back, which keeps them cheap; the chain drifts to
+                        // the cap long before the window ends.
+                        let base = policy.delay_for_attempt(0).unwrap_or(delay).min(*cap);
+                        let mut previous = base;
+                        for step in attempt.saturating_sub(DECORRELATED_WINDOW)..=attempt {
+                            let random = backoff::random(*seed, step);
+                            previous = backoff::between(base, previous.saturating_mul(3), random)
+                                .min(*cap);
+                        }
+                        previous
+                    }
+                })
             }
+            RetryPolicy::Custom { strategy, .. } => Some(strategy.delay(attempt)),
         }
     }
 }
 
+/// How many attempts back decorrelated jitter replays its chain of delays.
+const DECORRELATED_WINDOW: u32 = 1024;
+
+/// Returns `initial_delay * multiplier^attempt`, capped at `max_delay`.
+fn exponential_delay(
+    initial_delay: Duration,
+    max_delay: Duration,
+    multiplier: f64,
+    attempt: u32,
+) -> Duration {
+    if initial_delay.is_zero() {
+        return Duration::ZERO;
+    }
+    // Computed in nanoseconds; anything that does not fit below the cap,
+    // including infinity, is the cap.
+    let nanos = initial_delay.as_nanos() as f64 * multiplier.powf(f64::from(attempt));
+    if nanos < max_delay.as_nanos() as f64 {
+        backoff::from_nanos(nanos as u128).min(max_delay)
+    } else {
+        max_delay
+    }
+}
+
 /// Configuration for a workflow step.
 #[derive(Debug, Clone)]
 pub struct StepConfig {
@@ -247,6 +585,7 @@ impl Default for StepConfig {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test_step_name() {
@@ -287,6 +626,135 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_retry_policy_linear() {
+        let policy = RetryPolicy::linear(3, Duration::from_millis(100), Duration::from_millis(50));
+        assert_eq!(policy.max_retries(), 3);
+        assert_eq!(
+            policy.delay_for_attempt(0),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(
+            policy.delay_for_attempt(2),
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(
+            policy.delay_for_attempt(u32::MAX),
+            Some(Duration::from_secs(60))
+        );
+    }
+
+    #[test]
+    fn test_retry_policy_fractional_multiplier() {
+        let policy = RetryPolicy::fractional_backoff(
+            4,
+            Duration::from_millis(100),
+            Duration::from_secs(10),
+            1.5,
+        )
+        .expect("valid policy");
+        assert_eq!(
+            policy.delay_for_attempt(1),
+            Some(Duration::from_millis(150))
+        );
+        assert_eq!(
+            policy.delay_for_attempt(2),
+            Some(Duration::from_millis(225))
+        );
+        assert_eq!(policy, policy.clone());
+        assert_ne!(
+            policy,
+            RetryPolicy::exponential_backoff(
+                4,
+                Duration::from_millis(100),
+                Duration::from_secs(10),
+                2
+            )
+            .expect("valid policy")
+        );
+    }
+
+    #[test]
+    fn test_retry_policy_custom() {
+        let policy = RetryPolicy::custom(2, |attempt: u32| Duration::from_secs(u64::from(attempt)));
+        assert_eq!(policy.max_retries(), 2);
+        assert_eq!(policy.delay_for_attempt(3), Some(Duration::from_secs(3)));
+        assert_eq!(policy, policy.clone());
+        assert_ne!(
+            policy,
+            RetryPolicy::custom(2, |attempt: u32| Duration::from_secs(u64::from(attempt)))
+        );
+    }
+
+    #[test]
+    fn test_retry_policy_jitter_bounds() {
+        let base = RetryPolicy::exponential(10, Duration::from_millis(100));
+        for seed in 0..100 {
+            for attempt in 0..6 {
+                let delay = base.delay_for_attempt(attempt).unwrap_or_default();
+
+                let full = base.clone().with_jitter(Jitter::Full).with_seed(seed);
+                assert!(full.delay_for_attempt(attempt).unwrap_or_default() <= delay);
+
+                let equal = base.clone().with_jitter(Jitter::Equal).with_seed(seed);
+                let jittered = equal.delay_for_attempt(attempt).unwrap_or_default();
+                assert!(jittered >= delay / 2 && jittered <= delay);
+
+                let decorrelated = base
+                    .clone()
+                    .with_jitter(Jitter::Decorrelated {
+                        cap: Duration::from_secs(1),
+                    })
+                    .with_seed(seed);
+                let jittered = decorrelated.delay_for_attempt(attempt).unwrap_or_default();
+                assert!(jittered >= Duration::from_millis(100));
+                assert!(jittered <= Duration::from_secs(1));
+            }
+        }
+    }
+
+    #[test]
+    fn test_retry_policy_decorrelated_jitter() {
+        let delay = Duration::from_millis(100);
+        let cap = Duration::from_secs(2);
+        let policy = RetryPolicy::fixed(10, delay)
+            .with_jitter(Jitter::Decorrelated { cap })
+            .with_seed(7);
+        let delays: Vec<Duration> = (0..10)
+            .map(|attempt| policy.delay_for_attempt(attempt).unwrap_or_default())
+            .collect();
+        assert!(delays.iter().all(|d| *d >= delay && *d <= cap));
+        assert!(delays.windows(2).any(|pair| pair[0] != pair[1]));
+        assert!(delays.iter().any(|d| *d > delay * 3));
+        // Each delay is at most three times the one before.
+        assert!(delays.windows(2).all(|pair| pair[1] <= pair[0] * 3));
+        assert_eq!(policy.delay_for_attempt(5), Some(delays[5]));
+    }
+
+    #[test]
+    fn test_retry_policy_jitter_seeding() {
+        let policy = RetryPolicy::fixed(5, Duration::from_secs(1)).with_jitter(Jitter::Full);
+        let seeded = policy.clone().with_seed(42);
+        assert_eq!(seeded.max_retries(), 5);
+        assert_eq!(
+            seeded.delay_for_attempt(2),
+            seeded.clone().delay_for_attempt(2)
+        );
+        assert_ne!(seeded.delay_for_attempt(1), seeded.delay_for_attempt(2));
+        assert_eq!(
+            seeded.clone().with_jitter(Jitter::Equal),
+            RetryPolicy::Jittered {
+                policy: Box::new(RetryPolicy::fixed(5, Duration::from_secs(1))),
+                jitter: Jitter::Equal,
+                seed: Some(42),
+            }
+        );
+        assert_eq!(
+            RetryPolicy::None.with_jitter(Jitter::Full),
+            RetryPolicy::None
+        );
+    }
+
     #[test]
     fn test_retry_policy_validation() {
         let result = RetryPolicy::exponential_backoff(
@@ -296,5 +764,114 @@ mod tests {
             0,
         );
         assert!(result.is_err());
+
+        let result = RetryPolicy::fractional_backoff(
+            3,
+            Duration::from_millis(100),
+            Duration::from_secs(10),
+            f64::NAN,
+        );
+        assert!(result.is_err());
+
+        let result = RetryPolicy::exponential_backoff(
+            3,
+            Duration::from_millis(100),
+            Duration::from_secs(10),
+            1000,
+        );
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_retry_policy_sub_millisecond_delays() {
+        let policy = RetryPolicy::exponential(3, Duration::from_micros(300));
+        assert_eq!(
+            policy.delay_for_attempt(0),
+            Some(Duration::from_micros(300))
+        );
+        assert_eq!(
+            policy.delay_for_attempt(2),
+            Some(Duration::from_micros(1200))
+        );
+
+        let policy = RetryPolicy::exponential(3, Duration::from_nanos(1));
+        assert_eq!(policy.delay_for_attempt(3), Some(Duration::from_nanos(8)));
+    }
+
+    #[test]
+    fn test_retry_policy_saturates() {
+        let policy = RetryPolicy::exponential_backoff(3, Duration::MAX, Duration::MAX, 10)
+            .expect("valid policy");
+        assert_eq!(policy.delay_for_attempt(u32::MAX), Some(Duration::MAX));
+
+        let policy = RetryPolicy::linear(3, Duration::MAX, Duration::MAX);
+        assert_eq!(
+            policy.delay_for_attempt(u32::MAX),
+            Some(Duration::from_secs(60))
+        );
+
+        let policy = RetryPolicy::exponential(3, Duration::ZERO);
+        assert_eq!(policy.delay_for_attempt(u32::MAX), Some(Duration::ZERO));
+    }
+
+    fn any_duration() -> impl Strategy<Value = Duration> {
+        prop_oneof![
+            (0u64..1_000_000_000_000).prop_map(Duration::from_nanos),
+            (any::<u64>(), 0u32..1_000_000_000)
+                .prop_map(|(secs, nanos)| Duration::new(secs, nanos)),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn prop_exponential_delay_is_bounded(
+            attempt in any::<u32>(),
+            initial in any_duration(),
+            extra in any_duration(),
+            multiplier in 0.001f64..1e9,
+        ) {
+            let max = initial.saturating_add(extra);
+            let policy = RetryPolicy::fractional_backoff(3, initial, max, multiplier)
+                .expect("valid policy");
+            let delay = policy.delay_for_attempt(attempt).unwrap_or_default();
+            prop_assert!(delay <= max);
+            if multiplier >= 1.0 {
+                prop_assert!(delay >= initial);
+                let next = policy.delay_for_attempt(attempt.saturating_add(1)).unwrap_or_default();
+                prop_assert!(next >= delay);
+            }
+        }
+
+        #[test]
+        fn prop_linear_delay_is_bounded(
+            attempt in any::<u32>(),
+            initial in any_duration(),
+            increment in any_duration(),
+        ) {
+            let policy = RetryPolicy::linear(3, initial, increment);
+            let delay = policy.delay_for_attempt(attempt).unwrap_or_default();
+            prop_assert!(delay <= Duration::from_secs(60));
+            let next = policy.delay_for_attempt(attempt.saturating_add(1)).unwrap_or_default();
+            prop_assert!(next >= delay);
+        }
+
+        #[test]
+        fn prop_jittered_delay_is_bounded(
+            attempt in any::<u32>(),
+            initial in any_duration(),
+            seed in any::<u64>(),
+        ) {
+            let base = RetryPolicy::exponential_backoff(3, initial, Duration::MAX, 2)
+                .expect("valid policy");
+            let delay = base.delay_for_attempt(attempt).unwrap_or_default();
+            for jitter in [Jitter::Full, Jitter::Equal] {
+                let jittered = base.clone().with_jitter(jitter).with_seed(seed);
+                prop_assert!(jittered.delay_for_attempt(attempt).unwrap_or_default() <= delay);
+            }
+            let cap = initial.saturating_mul(100);
+            let decorrelated = base.with_jitter(Jitter::Decorrelated { cap }).with_seed(seed);
+            let jittered = decorrelated.delay_for_attempt(attempt).unwrap_or_default();
+            prop_assert!(jittered >= initial && jittered <= cap);
+        }
     }
 }
@@ -91,6 +91,15 @@ pub trait Retryable: Step {
     fn retry_policy(&self) -> RetryPolicy {
         RetryPolicy::None
     }
+
+    /// Returns `true` if a failed attempt with `error` should be retried.
+    ///
+    /// Timeouts are classified as [`WorkflowError::Timeout`]. By default
+    /// every error except a [permanent](WorkflowError::permanent) one is
+    /// retried.
+    fn is_retryable(&self, error: &WorkflowError) -> bool {
+        !error.is_permanent()
+    }
 }
 
 /// Optional trait for steps with custom timeout.
@@ -130,3 +139,50 @@ pub trait WithTimeout: Step {
         Duration::from_secs(30)
     }
 }
+
+/// Optional trait for steps whose effects can be undone.
+///
+/// When a later step fails, the engine calls [`compensate`](Self::compensate)
+/// for every compensable step that completed during the run, in reverse
+/// order of completion. This is the saga pattern: instead of a transaction
+/// spanning all steps, each step knows how to reverse its own work.
+///
+/// # Examples
+///
+/// ```
+/// use tsumugi_core::{Step, StepOutput, StepName, Context, WorkflowError, Compensable};
+/// use async_trait::async_trait;
+///
+/// #[derive(Debug)]
+/// struct ChargeStep;
+///
+/// #[async_trait]
+/// impl Step for ChargeStep {
+///     async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+///         ctx.insert("charge_id", "ch_123".to_string());
+///         Ok(StepOutput::next("ship"))
+///     }
+///
+///     fn name(&self) -> StepName {
+///         StepName::new("ChargeStep")
+///     }
+/// }
+///
+/// #[async_trait]
+/// impl Compensable for ChargeStep {
+///     async fn compensate(&self, ctx: &mut Context) -> Result<(), WorkflowError> {
+///         if let Some(charge_id) = ctx.get::<String>("charge_id") {
+///             println!("refunding {charge_id}");
+///         }
+///         Ok(())
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait Compensable: Step {
+    /// Undoes the effects of a successful [`Step::execute`].
+    ///
+    /// Runs against the workflow's context after the run has failed. A
+    /// failing compensation is reported but does not stop the remaining ones.
+    async fn compensate(&self, ctx: &mut Context) -> Result<(), WorkflowError>;
+}
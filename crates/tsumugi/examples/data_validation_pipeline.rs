@@ -5,6 +5,7 @@
 //! 2. Business rule validation
 //! 3. Cross-reference validation
 //! 4. Report generation
+//! 5. Retrying transient load failures while failing fast on invalid input
 //!
 //! Use cases:
 //! - CI/CD data quality gates
@@ -15,6 +16,7 @@
 #![allow(dead_code)]
 
 use async_trait::async_trait;
+use std::time::Duration;
 use tsumugi::prelude::*;
 
 // Input data to validate
@@ -172,12 +174,14 @@ impl Step for SchemaValidationStep {
     async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
         println!("Running schema validation...");
 
-        let data =
-            ctx.get::<ImportData>("import_data")
-                .ok_or_else(|| WorkflowError::StepError {
-                    step_name: self.name(),
-                    details: "Import data not found".to_string(),
-                })?;
+        let data = ctx.get::<ImportData>("import_data").ok_or_else(|| {
+            // Retrying cannot make missing input appear.
+            WorkflowError::StepError {
+                step_name: self.name(),
+                details: "Import data not found".to_string(),
+            }
+            .permanent()
+        })?;
 
         let mut result = ValidationResult::default();
 
@@ -247,12 +251,13 @@ impl Step for BusinessValidationStep {
     async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
         println!("Running business rule validation...");
 
-        let data =
-            ctx.get::<ImportData>("import_data")
-                .ok_or_else(|| WorkflowError::StepError {
-                    step_name: self.name(),
-                    details: "Import data not found".to_string(),
-                })?;
+        let data = ctx.get::<ImportData>("import_data").ok_or_else(|| {
+            WorkflowError::StepError {
+                step_name: self.name(),
+                details: "Import data not found".to_string(),
+            }
+            .permanent()
+        })?;
 
         let mut result = ValidationResult::default();
 
@@ -322,12 +327,13 @@ impl Step for ReferenceValidationStep {
     async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
         println!("Running reference validation...");
 
-        let data =
-            ctx.get::<ImportData>("import_data")
-                .ok_or_else(|| WorkflowError::StepError {
-                    step_name: self.name(),
-                    details: "Import data not found".to_string(),
-                })?;
+        let data = ctx.get::<ImportData>("import_data").ok_or_else(|| {
+            WorkflowError::StepError {
+                step_name: self.name(),
+                details: "Import data not found".to_string(),
+            }
+            .permanent()
+        })?;
 
         let mut result = ValidationResult::default();
 
@@ -438,12 +444,20 @@ impl Step for ReportStep {
     }
 }
 
+// Loading may hit transient I/O errors; validation errors are permanent.
+impl Retryable for LoadDataStep {
+    fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy::exponential(3, Duration::from_millis(200))
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt::init();
 
     let workflow = Workflow::builder()
-        .add_step("load", LoadDataStep)
+        .add("load", LoadDataStep)
+        .retryable()
         .add_step("schema_validation", SchemaValidationStep)
         .add_step("business_validation", BusinessValidationStep)
         .add_step("reference_validation", ReferenceValidationStep)
@@ -2,7 +2,7 @@
 //!
 //! This example demonstrates batch file transformation:
 //! 1. Scan directory for input files
-//! 2. Parse and validate each file
+//! 2. Parse each file concurrently with a `MapStep` (per-file retry and timeout)
 //! 3. Transform data
 //! 4. Write output files
 //!
@@ -16,6 +16,7 @@
 
 use async_trait::async_trait;
 use std::collections::HashMap;
+use std::time::Duration;
 use tsumugi::prelude::*;
 
 // Input file representation
@@ -72,7 +73,7 @@ impl Step for ScanDirectoryStep {
         // let entries = std::fs::read_dir("./input")?;
 
         // Simulated file discovery
-        let files = vec![
+        let files = [
             InputFile {
                 path: "./input/app-2024-01-01.json".to_string(),
                 size_bytes: 1024,
@@ -113,43 +114,70 @@ impl Step for ScanDirectoryStep {
     }
 }
 
-// Step 2: Parse files
+// Step 2a: Parse a single file. The map step runs one instance per input file.
 #[derive(Debug)]
-struct ParseFilesStep;
+struct ParseFileStep;
 
 #[async_trait]
-impl Step for ParseFilesStep {
+impl Step for ParseFileStep {
     async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
-        println!("Parsing files...");
-
-        let files = ctx
-            .get::<Vec<InputFile>>("input_files")
+        let file = ctx
+            .get::<InputFile>(MapStep::<InputFile, Vec<LogEntry>>::ITEM_KEY)
             .ok_or_else(|| WorkflowError::StepError {
                 step_name: self.name(),
-                details: "Input files not found".to_string(),
-            })?
-            .clone();
+                details: "Input file not found".to_string(),
+            })?;
 
-        let mut all_entries: Vec<LogEntry> = Vec::new();
-        let mut stats = ProcessingStats::default();
+        println!("  Processing: {}", file.path);
 
-        for file in &files {
-            println!("  Processing: {}", file.path);
+        // In production, read and parse actual files:
+        // let content = tokio::fs::read_to_string(&file.path).await?;
+        // let entries: Vec<LogEntry> = serde_json::from_str(&content)?;
 
-            // In production, read and parse actual files:
-            // let content = std::fs::read_to_string(&file.path)?;
-            // let entries: Vec<LogEntry> = serde_json::from_str(&content)?;
+        // Simulated parsing
+        let entries = simulate_parse_file(file);
+        ctx.insert(MapStep::<InputFile, Vec<LogEntry>>::OUTPUT_KEY, entries);
 
-            // Simulated parsing
-            let entries = simulate_parse_file(file);
-            stats.files_processed += 1;
-            stats.entries_parsed += entries.len();
+        Ok(StepOutput::done())
+    }
 
-            for entry in &entries {
-                *stats.by_level.entry(entry.level.clone()).or_insert(0) += 1;
-            }
+    fn name(&self) -> StepName {
+        StepName::new("ParseFile")
+    }
+}
 
-            all_entries.extend(entries);
+// Step 2b: Collect the per-file results
+#[derive(Debug)]
+struct CollectEntriesStep;
+
+#[async_trait]
+impl Step for CollectEntriesStep {
+    async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+        let parsed = ctx
+            .remove::<Vec<Result<Vec<LogEntry>, WorkflowError>>>("parsed_files")
+            .ok_or_else(|| WorkflowError::StepError {
+                step_name: self.name(),
+                details: "Parsed files not found".to_string(),
+            })?;
+
+        let mut all_entries: Vec<LogEntry> = Vec::new();
+        let mut stats = ProcessingStats::default();
+
+        for result in parsed {
+            match result {
+                Ok(entries) => {
+                    stats.files_processed += 1;
+                    stats.entries_parsed += entries.len();
+                    for entry in &entries {
+                        *stats.by_level.entry(entry.level.clone()).or_insert(0) += 1;
+                    }
+                    all_entries.extend(entries);
+                }
+                Err(e) => {
+                    println!("  Skipping file: {}", e);
+                    stats.errors_count += 1;
+                }
+            }
         }
 
         println!(
@@ -164,7 +192,7 @@ impl Step for ParseFilesStep {
     }
 
     fn name(&self) -> StepName {
-        StepName::new("ParseFiles")
+        StepName::new("CollectEntries")
     }
 }
 
@@ -353,7 +381,21 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let workflow = Workflow::builder()
         .add_step("scan", ScanDirectoryStep)
-        .add_step("parse", ParseFilesStep)
+        .add_step(
+            "parse",
+            MapStep::<InputFile, Vec<LogEntry>>::new(
+                "ParseFiles",
+                "input_files",
+                "parsed_files",
+                ParseFileStep,
+            )
+            .concurrency(4)
+            .timeout(Duration::from_secs(10))
+            .retry_policy(RetryPolicy::fixed(2, Duration::from_millis(100)))
+            .failure_mode(MapFailureMode::CollectAll)
+            .continue_with("collect"),
+        )
+        .add_step("collect", CollectEntriesStep)
         .add_step("filter", FilterEntriesStep)
         .add_step("write_output", WriteOutputStep)
         .add_step("summary", SummaryStep)
@@ -1,7 +1,7 @@
 //! Health Check Monitoring Workflow.
 //!
 //! This example demonstrates:
-//! 1. Checking multiple service endpoints
+//! 1. Checking multiple service endpoints concurrently with a parallel group
 //! 2. Aggregating health status
 //! 3. Alerting on failures with retry logic
 //!
@@ -93,58 +93,56 @@ impl Step for LoadConfigStep {
     }
 }
 
-// Step 2: Check all services (with simulated retries)
+// Step 2: Check a single service. One instance runs per service, concurrently.
 #[derive(Debug)]
-struct CheckServicesStep;
+struct CheckServiceStep {
+    service: &'static str,
+}
 
 #[async_trait]
-impl Step for CheckServicesStep {
+impl Step for CheckServiceStep {
     async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
-        println!("Checking service health...");
-
-        let configs = ctx
+        // Branches can read everything the parent context contains.
+        let config = ctx
             .get::<Vec<ServiceConfig>>("service_configs")
+            .and_then(|configs| configs.iter().find(|c| c.name == self.service))
             .ok_or_else(|| WorkflowError::StepError {
                 step_name: self.name(),
-                details: "Service configs not found".to_string(),
+                details: format!("Service config for '{}' not found", self.service),
             })?
             .clone();
 
-        let mut results: Vec<ServiceHealth> = Vec::new();
-
-        for config in configs {
-            // In production, use reqwest with timeout:
-            // let client = reqwest::Client::new();
-            // let start = Instant::now();
-            // let response = client
-            //     .get(&config.url)
-            //     .timeout(Duration::from_millis(config.timeout_ms))
-            //     .send()
-            //     .await;
-
-            // Simulated health check results
-            let health = simulate_health_check(&config);
-            println!(
-                "  {} [{}]: {:?} ({}ms)",
-                health.name,
-                if health.status == HealthStatus::Healthy {
-                    "OK"
-                } else {
-                    "!!"
-                },
-                health.status,
-                health.response_time_ms
-            );
-            results.push(health);
-        }
+        // In production, use reqwest with timeout:
+        // let client = reqwest::Client::new();
+        // let start = Instant::now();
+        // let response = client
+        //     .get(&config.url)
+        //     .timeout(Duration::from_millis(config.timeout_ms))
+        //     .send()
+        //     .await;
+
+        // Simulated health check results
+        let health = simulate_health_check(&config);
+        println!(
+            "  {} [{}]: {:?} ({}ms)",
+            health.name,
+            if health.status == HealthStatus::Healthy {
+                "OK"
+            } else {
+                "!!"
+            },
+            health.status,
+            health.response_time_ms
+        );
 
-        ctx.insert("health_results", results);
+        // Entries written by a branch are merged into the parent context after the join.
+        ctx.insert(format!("health.{}", self.service), health);
 
-        Ok(StepOutput::next("aggregate"))
+        Ok(StepOutput::done())
     }
 
     fn name(&self) -> StepName {
-        StepName::new("CheckServices")
+        StepName::new(format!("Check {}", self.service))
     }
 }
 
@@ -177,13 +175,13 @@ impl Step for AggregateResultsStep {
     async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
         println!("Aggregating health results...");
 
-        let results = ctx
-            .get::<Vec<ServiceHealth>>("health_results")
-            .ok_or_else(|| WorkflowError::StepError {
-                step_name: self.name(),
-                details: "Health results not found".to_string(),
-            })?
-            .clone();
+        let mut results: Vec<ServiceHealth> = ctx
+            .keys()
+            .filter(|key| key.as_str().starts_with("health."))
+            .filter_map(|key| ctx.get::<ServiceHealth>(key.as_str()))
+            .cloned()
+            .collect();
+        results.sort_by(|a, b| a.name.cmp(&b.name));
 
         // Determine overall status
         let overall_status = if results.iter().any(|h| h.status == HealthStatus::Unhealthy) {
@@ -294,8 +292,8 @@ impl Step for ReportStep {
     }
 }
 
-// Implement retry for CheckServicesStep
-impl Retryable for CheckServicesStep {
+// Implement retry for CheckServiceStep
+impl Retryable for CheckServiceStep {
     fn retry_policy(&self) -> RetryPolicy {
         // 3 retries, starting at 500ms, max 5s, multiplier 2
         RetryPolicy::exponential_backoff(3, Duration::from_millis(500), Duration::from_secs(5), 2)
@@ -303,20 +301,38 @@ impl Retryable for CheckServicesStep {
     }
 }
 
-// Implement timeout for CheckServicesStep
-impl WithTimeout for CheckServicesStep {
+// Implement timeout for CheckServiceStep
+impl WithTimeout for CheckServiceStep {
     fn timeout(&self) -> Duration {
         Duration::from_secs(30)
     }
 }
 
+const SERVICES: [(&str, &str); 4] = [
+    ("check_api", "API Gateway"),
+    ("check_db", "Database"),
+    ("check_cache", "Cache"),
+    ("check_mq", "Message Queue"),
+];
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt::init();
 
-    let workflow = Workflow::builder()
-        .add_step("load_config", LoadConfigStep)
-        .add_retryable("check_services", CheckServicesStep)
+    let mut builder = Workflow::builder().add_step("load_config", LoadConfigStep);
+    for (name, service) in SERVICES {
+        builder = builder
+            .add(name, CheckServiceStep { service })
+            .retryable()
+            .with_timeout()
+            .done();
+    }
+
+    let workflow = builder
+        .add_parallel(
+            "check_services",
+            ParallelGroup::new(SERVICES.map(|(name, _)| name), "aggregate"),
+        )
         .add_step("aggregate", AggregateResultsStep)
         .add_step("alert", AlertStep)
         .add_step("report", ReportStep)
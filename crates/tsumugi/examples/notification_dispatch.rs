@@ -204,9 +204,9 @@ impl Step for DispatchStep {
         for channel in &request.channels {
             let result = dispatch_to_channel(channel, &request.recipient, &rendered);
             println!(
-                "  {} {} -> {}",
+                "  {} {:?} -> {}",
                 if result.success { "[OK]" } else { "[FAIL]" },
-                format!("{:?}", channel),
+                channel,
                 result.message_id.as_deref().unwrap_or("N/A")
             );
             results.push(result);
@@ -385,7 +385,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let workflow = Workflow::builder()
         .add_step("load", LoadRequestStep)
         .add_step("render", RenderTemplateStep)
-        .add_retryable("dispatch", DispatchStep)
+        .add("dispatch", DispatchStep)
+        .retryable()
+        .with_timeout()
+        // Stop hammering delivery services that keep failing across runs
+        .circuit_breaker(CircuitBreaker::new("delivery", 5, Duration::from_secs(60)))
         .add_step("report", ReportStep)
         .start_with("load")
         .build()?;
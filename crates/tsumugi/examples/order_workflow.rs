@@ -186,6 +186,17 @@ impl Step for PaymentProcessingStep {
     }
 }
 
+// Refund the payment if a later step (e.g. shipping) fails
+#[async_trait]
+impl Compensable for PaymentProcessingStep {
+    async fn compensate(&self, ctx: &mut Context) -> Result<(), WorkflowError> {
+        if let Some(payment) = ctx.remove::<PaymentStatus>("payment_status") {
+            println!("Refunding payment {}...", payment.transaction_id);
+        }
+        Ok(())
+    }
+}
+
 // Step 4: Shipping Arrangement
 #[derive(Debug)]
 struct ShippingArrangementStep;
@@ -324,7 +335,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let workflow = Workflow::builder()
         .add_step("order_validation", OrderValidationStep)
         .add_step("inventory_check", InventoryCheckStep)
-        .add_step("payment_processing", PaymentProcessingStep)
+        .add_compensable("payment_processing", PaymentProcessingStep)
         .add_step("shipping_arrangement", ShippingArrangementStep)
         .add_step("success_notification", SuccessNotificationStep)
         .add_step("pending_notification", PendingNotificationStep)
@@ -2,6 +2,7 @@
 //!
 //! Demonstrates:
 //! - Heterogeneous context (each type stored directly)
+//! - Typed context keys binding each key to its value type
 //! - Data validation
 //! - Conditional logic based on computed values
 
@@ -24,6 +25,11 @@ struct ProcessedData {
     category: String,
 }
 
+// Context keys - the value type is checked at compile time
+const USER_DATA: TypedKey<UserData> = TypedKey::new("user_data");
+const SCORES: TypedKey<HashMap<u64, f64>> = TypedKey::new("scores");
+const PROCESSED_DATA: TypedKey<ProcessedData> = TypedKey::new("processed_data");
+
 // Step 1: Load user data
 #[derive(Debug)]
 struct UserDataLoadStep;
@@ -38,7 +44,7 @@ impl Step for UserDataLoadStep {
             name: "John Doe".to_string(),
             age: 30,
         };
-        ctx.insert("user_data", user);
+        ctx.insert_typed(&USER_DATA, user);
 
         Ok(StepOutput::next("load_scores"))
     }
@@ -58,7 +64,7 @@ impl Step for ScoresLoadStep {
         println!("Loading scores...");
 
         let scores: HashMap<u64, f64> = HashMap::from([(1, 85.5), (2, 92.0), (3, 78.3)]);
-        ctx.insert("scores", scores);
+        ctx.insert_typed(&SCORES, scores);
 
         Ok(StepOutput::next("validate"))
     }
@@ -77,12 +83,7 @@ impl Step for DataValidationStep {
     async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
         println!("Validating data...");
 
-        let user = ctx
-            .get::<UserData>("user_data")
-            .ok_or_else(|| WorkflowError::StepError {
-                step_name: self.name(),
-                details: "User data not found".to_string(),
-            })?;
+        let user = ctx.try_get::<UserData>(USER_DATA.name())?;
 
         if user.age < 18 {
             return Err(WorkflowError::StepError {
@@ -91,12 +92,12 @@ impl Step for DataValidationStep {
             });
         }
 
-        let scores =
-            ctx.get::<HashMap<u64, f64>>("scores")
-                .ok_or_else(|| WorkflowError::StepError {
-                    step_name: self.name(),
-                    details: "Scores not found".to_string(),
-                })?;
+        let scores = ctx
+            .get_typed(&SCORES)
+            .ok_or_else(|| WorkflowError::StepError {
+                step_name: self.name(),
+                details: "Scores not found".to_string(),
+            })?;
 
         if !scores.contains_key(&user.id) {
             return Err(WorkflowError::StepError {
@@ -122,20 +123,14 @@ impl Step for DataProcessingStep {
     async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
         println!("Processing data...");
 
-        let user = ctx
-            .get::<UserData>("user_data")
+        let user = ctx.try_get::<UserData>(USER_DATA.name())?.clone();
+
+        let scores = ctx
+            .get_typed(&SCORES)
             .ok_or_else(|| WorkflowError::StepError {
                 step_name: self.name(),
-                details: "User data not found".to_string(),
-            })?
-            .clone();
-
-        let scores =
-            ctx.get::<HashMap<u64, f64>>("scores")
-                .ok_or_else(|| WorkflowError::StepError {
-                    step_name: self.name(),
-                    details: "Scores not found".to_string(),
-                })?;
+                details: "Scores not found".to_string(),
+            })?;
 
         let score = scores
             .get(&user.id)
@@ -157,7 +152,7 @@ impl Step for DataProcessingStep {
             category: category.to_string(),
         };
 
-        ctx.insert("processed_data", processed);
+        ctx.insert_typed(&PROCESSED_DATA, processed);
         Ok(StepOutput::next("notify"))
     }
 
@@ -173,12 +168,7 @@ struct NotificationStep;
 #[async_trait]
 impl Step for NotificationStep {
     async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
-        let processed =
-            ctx.get::<ProcessedData>("processed_data")
-                .ok_or_else(|| WorkflowError::StepError {
-                    step_name: self.name(),
-                    details: "Processed data not found".to_string(),
-                })?;
+        let processed = ctx.try_get::<ProcessedData>(PROCESSED_DATA.name())?;
 
         if processed.score < 80.0 {
             println!(
@@ -212,7 +202,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     match workflow.execute(&mut ctx).await {
         Ok(()) => {
-            if let Some(processed) = ctx.get::<ProcessedData>("processed_data") {
+            if let Some(processed) = ctx.get_typed(&PROCESSED_DATA) {
                 println!("\nWorkflow completed successfully");
                 println!(
                     "Result: {} - Score: {}, Category: {}",
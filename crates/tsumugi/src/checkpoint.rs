@@ -0,0 +1,365 @@
+//! Durable checkpoints for resuming interrupted runs.
+//!
+//! Available with the `checkpoint` feature.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tsumugi_core::{Context, ContextSchema, StepName, WorkflowError};
+
+/// The state of a run after its last successful step.
+///
+/// Saved by [`Workflow::resume`](crate::Workflow::resume) after every
+/// successful step and read back to continue an interrupted run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    run_id: String,
+    #[serde(with = "step_name")]
+    last_step: StepName,
+    #[serde(with = "next_step")]
+    next_step: Option<StepName>,
+    values: BTreeMap<String, serde_json::Value>,
+    #[serde(default, with = "step_names")]
+    compensable: Vec<StepName>,
+}
+
+impl Checkpoint {
+    /// Returns the id of the run.
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// Returns the last step that completed successfully.
+    pub fn last_step(&self) -> &StepName {
+        &self.last_step
+    }
+
+    /// Returns the step the run continues with, or `None` if the run has
+    /// completed.
+    pub fn next_step(&self) -> Option<&StepName> {
+        self.next_step.as_ref()
+    }
+
+    /// Returns `true` if the run completed successfully.
+    pub fn is_complete(&self) -> bool {
+        self.next_step.is_none()
+    }
+
+    /// Returns the serialized context values, keyed by context key.
+    pub fn values(&self) -> &BTreeMap<String, serde_json::Value> {
+        &self.values
+    }
+
+    /// Returns the [compensable](crate::StepBuilder::compensable) steps
+    /// completed so far, in completion order.
+    pub fn compensable(&self) -> &[StepName] {
+        &self.compensable
+    }
+}
+
+/// Durable storage for [`Checkpoint`]s, keyed by run id.
+///
+/// See [`FileCheckpointStore`] for the built-in implementation.
+#[async_trait]
+pub trait CheckpointStore: Send + Sync {
+    /// Saves `checkpoint`, replacing any earlier checkpoint of the same run.
+    async fn save(&self, checkpoint: &Checkpoint) -> Result<(), WorkflowError>;
+
+    /// Loads the latest checkpoint of a run, if there is one.
+    async fn load(&self, run_id: &str) -> Result<Option<Checkpoint>, WorkflowError>;
+
+    /// Deletes the checkpoint of a run, if there is one.
+    async fn remove(&self, run_id: &str) -> Result<(), WorkflowError>;
+}
+
+/// Stores each checkpoint as a JSON file named after its run id.
+///
+/// Checkpoints are written to a temporary file first and then renamed, so a
+/// crash while saving leaves the previous checkpoint intact. The directory is
+/// created on first save.
+#[derive(Debug, Clone)]
+pub struct FileCheckpointStore {
+    dir: PathBuf,
+}
+
+impl FileCheckpointStore {
+    /// Creates a store keeping checkpoints in `dir`.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Returns the directory checkpoints are kept in.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, run_id: &str) -> Result<PathBuf, WorkflowError> {
+        let valid = !run_id.is_empty()
+            && run_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            && !run_id.starts_with('.');
+        if !valid {
+            return Err(WorkflowError::Checkpoint(format!(
+                "invalid run id '{}': use letters, digits, '-', '_' and '.'",
+                run_id
+            )));
+        }
+        Ok(self.dir.join(format!("{}.json", run_id)))
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for FileCheckpointStore {
+    async fn save(&self, checkpoint: &Checkpoint) -> Result<(), WorkflowError> {
+        let path = self.path(&checkpoint.run_id)?;
+        let json = serde_json::to_vec_pretty(checkpoint).map_err(|e| {
+            WorkflowError::Checkpoint(format!("cannot encode '{}': {}", checkpoint.run_id, e))
+        })?;
+        let temp = path.with_extension("json.tmp");
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| io_error(&self.dir, e))?;
+        tokio::fs::write(&temp, json)
+            .await
+            .map_err(|e| io_error(&temp, e))?;
+        tokio::fs::rename(&temp, &path)
+            .await
+            .map_err(|e| io_error(&path, e))
+    }
+
+    async fn load(&self, run_id: &str) -> Result<Option<Checkpoint>, WorkflowError> {
+        let path = self.path(run_id)?;
+        let json = match tokio::fs::read(&path).await {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(io_error(&path, e)),
+        };
+        serde_json::from_slice(&json).map(Some).map_err(|e| {
+            WorkflowError::Checkpoint(format!("cannot decode {}: {}", path.display(), e))
+        })
+    }
+
+    async fn remove(&self, run_id: &str) -> Result<(), WorkflowError> {
+        let path = self.path(run_id)?;
+        match tokio::fs::remove_file(&path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(io_error(&path, e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn io_error(path: &Path, error: std::io::Error) -> WorkflowError {
+    WorkflowError::Checkpoint(format!("{}: {}", path.display(), error))
+}
+
+/// Saves checkpoints for one run of a workflow.
+pub(crate) struct Checkpointer<'a> {
+    pub(crate) run_id: &'a str,
+    pub(crate) store: &'a dyn CheckpointStore,
+    /// The [checkpointed keys](crate::WorkflowBuilder::checkpoint_key).
+    pub(crate) schema: &'a ContextSchema,
+}
+
+impl Checkpointer<'_> {
+    /// Records that `last_step` completed and the run continues with
+    /// `next_step`, with the compensable steps completed so far.
+    pub(crate) async fn save(
+        &self,
+        ctx: &Context,
+        last_step: &StepName,
+        next_step: Option<&StepName>,
+        compensable: Vec<StepName>,
+    ) -> Result<(), WorkflowError> {
+        let snapshot = ctx.to_json_with(self.schema);
+        if !snapshot.is_complete() {
+            let keys: Vec<String> = snapshot
+                .skipped()
+                .iter()
+                .map(|key| format!("'{}'", key))
+                .collect();
+            return Err(WorkflowError::Checkpoint(format!(
+                "cannot serialize keys {}",
+                keys.join(", ")
+            )));
+        }
+        let values = match snapshot.into_json() {
+            serde_json::Value::Object(object) => object.into_iter().collect(),
+            _ => BTreeMap::new(),
+        };
+        self.store
+            .save(&Checkpoint {
+                run_id: self.run_id.to_string(),
+                last_step: last_step.clone(),
+                next_step: next_step.cloned(),
+                values,
+                compensable,
+            })
+            .await
+    }
+
+    /// Deletes the checkpoint of the run.
+    pub(crate) async fn remove(&self) -> Result<(), WorkflowError> {
+        self.store.remove(self.run_id).await
+    }
+
+    /// Restores the registered keys of `checkpoint` into `ctx`.
+    ///
+    /// Values of keys that are no longer registered are ignored.
+    pub(crate) fn restore(
+        &self,
+        ctx: &mut Context,
+        checkpoint: &Checkpoint,
+    ) -> Result<(), WorkflowError> {
+        let values: serde_json::Map<String, serde_json::Value> = checkpoint
+            .values
+            .iter()
+            .filter(|(key, _)| self.schema.contains(key))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        let mut restored = Context::from_json(&serde_json::Value::Object(values), self.schema)
+            .map_err(|e| {
+                WorkflowError::Checkpoint(format!("cannot restore '{}': {}", checkpoint.run_id, e))
+            })?;
+        let keys: Vec<_> = restored.keys().cloned().collect();
+        for key in keys {
+            restored.transfer(key.as_str(), ctx, key.clone());
+        }
+        Ok(())
+    }
+}
+
+mod step_name {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use tsumugi_core::StepName;
+
+    pub(super) fn serialize<S: Serializer>(name: &StepName, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(name.as_str())
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<StepName, D::Error> {
+        String::deserialize(d).map(StepName::new)
+    }
+}
+
+mod next_step {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use tsumugi_core::StepName;
+
+    pub(super) fn serialize<S: Serializer>(
+        name: &Option<StepName>,
+        s: S,
+    ) -> Result<S::Ok, S::Error> {
+        match name {
+            Some(name) => s.serialize_some(name.as_str()),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        d: D,
+    ) -> Result<Option<StepName>, D::Error> {
+        Option::<String>::deserialize(d).map(|name| name.map(StepName::new))
+    }
+}
+
+mod step_names {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use tsumugi_core::StepName;
+
+    pub(super) fn serialize<S: Serializer>(names: &[StepName], s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(names.iter().map(StepName::as_str))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<StepName>, D::Error> {
+        Vec::<String>::deserialize(d).map(|names| names.into_iter().map(StepName::new).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkpoint(run_id: &str) -> Checkpoint {
+        Checkpoint {
+            run_id: run_id.to_string(),
+            last_step: StepName::new("extract"),
+            next_step: Some(StepName::new("load")),
+            values: BTreeMap::from([("rows".to_string(), serde_json::json!([1, 2, 3]))]),
+            compensable: vec![StepName::new("extract")],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_store_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileCheckpointStore::new(dir.path().join("checkpoints"));
+
+        assert_eq!(store.load("nightly-1").await.unwrap(), None);
+        store.save(&checkpoint("nightly-1")).await.unwrap();
+        assert_eq!(
+            store.load("nightly-1").await.unwrap(),
+            Some(checkpoint("nightly-1"))
+        );
+
+        store.remove("nightly-1").await.unwrap();
+        assert_eq!(store.load("nightly-1").await.unwrap(), None);
+        store.remove("nightly-1").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_store_rejects_path_like_run_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileCheckpointStore::new(dir.path());
+        for run_id in ["", "../escape", "a/b", ".hidden"] {
+            assert!(matches!(
+                store.load(run_id).await,
+                Err(WorkflowError::Checkpoint(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_checkpointer_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileCheckpointStore::new(dir.path());
+        let schema = ContextSchema::new()
+            .key::<Vec<u32>>("rows")
+            .key::<String>("missing");
+        let checkpointer = Checkpointer {
+            run_id: "etl-1",
+            store: &store,
+            schema: &schema,
+        };
+        let mut ctx = Context::new();
+        ctx.insert("rows", vec![1u32, 2, 3]);
+        ctx.insert("unregistered", 1u8);
+
+        let extract = StepName::new("extract");
+        checkpointer
+            .save(&ctx, &extract, None, vec![])
+            .await
+            .unwrap();
+        let checkpoint = store.load("etl-1").await.unwrap().unwrap();
+        assert_eq!(
+            checkpoint.values(),
+            &BTreeMap::from([("rows".to_string(), serde_json::json!([1, 2, 3]))])
+        );
+
+        let mut restored = Context::new();
+        checkpointer.restore(&mut restored, &checkpoint).unwrap();
+        assert_eq!(restored.get::<Vec<u32>>("rows"), Some(&vec![1, 2, 3]));
+        assert_eq!(restored.len(), 1);
+
+        ctx.insert("missing", 42u8);
+        let err = checkpointer
+            .save(&ctx, &extract, None, vec![])
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Checkpoint failed: cannot serialize keys 'missing'"
+        );
+    }
+}
@@ -0,0 +1,403 @@
+//! Circuit breakers that stop calling a failing dependency for a while.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// The state of a [`CircuitBreaker`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Attempts run normally.
+    Closed,
+    /// Attempts are rejected with [`WorkflowError::CircuitOpen`](crate::WorkflowError::CircuitOpen)
+    /// until the cool-down has passed.
+    Open,
+    /// The cool-down has passed; the next attempt is a trial that closes the
+    /// breaker on success and opens it again on failure.
+    HalfOpen,
+}
+
+impl fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CircuitState::Closed => write!(f, "closed"),
+            CircuitState::Open => write!(f, "open"),
+            CircuitState::HalfOpen => write!(f, "half-open"),
+        }
+    }
+}
+
+/// Stops running a step after repeated failures, giving a failing
+/// dependency time to recover.
+///
+/// The breaker opens after `failure_threshold` consecutive failed attempts.
+/// While open, the steps it is attached to fail immediately with
+/// [`WorkflowError::CircuitOpen`](crate::WorkflowError::CircuitOpen) without
+/// being run. Once `cool_down` has passed, a single trial attempt is let
+/// through: success closes the breaker, failure opens it again.
+///
+/// The breaker is a cheap handle; clones share the same state, so one
+/// breaker can guard several steps and workflows. Attach it with
+/// [`StepBuilder::circuit_breaker`](crate::StepBuilder::circuit_breaker), or
+/// share breakers by name with a [`CircuitBreakerRegistry`].
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// let webhook = CircuitBreaker::new("webhook", 5, Duration::from_secs(30));
+///
+/// let workflow = Workflow::builder()
+///     .add("notify", NotifyStep)
+///     .circuit_breaker(webhook.clone())
+///     .start_with("notify")
+///     .build()?;
+///
+/// assert_eq!(webhook.state(), CircuitState::Closed);
+/// ```
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    name: String,
+    failure_threshold: u32,
+    cool_down: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    trial_running: bool,
+    /// Bumped whenever the breaker opens or is reset, so that outcomes of
+    /// attempts started before are ignored.
+    generation: u64,
+}
+
+impl CircuitBreaker {
+    /// Creates a closed breaker.
+    ///
+    /// A `failure_threshold` of 0 is treated as 1.
+    pub fn new(name: impl Into<String>, failure_threshold: u32, cool_down: Duration) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                name: name.into(),
+                failure_threshold: failure_threshold.max(1),
+                cool_down,
+                state: Mutex::new(BreakerState::default()),
+            }),
+        }
+    }
+
+    /// Returns the name of the breaker.
+    pub fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    /// Returns the number of consecutive failures that opens the breaker.
+    pub fn failure_threshold(&self) -> u32 {
+        self.inner.failure_threshold
+    }
+
+    /// Returns how long the breaker stays open before allowing a trial attempt.
+    pub fn cool_down(&self) -> Duration {
+        self.inner.cool_down
+    }
+
+    /// Returns the current state of the breaker.
+    pub fn state(&self) -> CircuitState {
+        self.lock().current(self.inner.cool_down)
+    }
+
+    /// Returns the number of consecutive failed attempts.
+    pub fn consecutive_failures(&self) -> u32 {
+        self.lock().consecutive_failures
+    }
+
+    /// Closes the breaker and forgets all failures.
+    pub fn reset(&self) {
+        let mut state = self.lock();
+        let generation = state.generation.wrapping_add(1);
+        *state = BreakerState {
+            generation,
+            ..BreakerState::default()
+        };
+    }
+
+    /// Asks to run an attempt, returning `None` if the breaker rejects it.
+    pub(crate) fn acquire(&self) -> Option<Permit> {
+        let mut state = self.lock();
+        let trial = match state.current(self.inner.cool_down) {
+            CircuitState::Closed => false,
+            CircuitState::Open => return None,
+            CircuitState::HalfOpen if state.trial_running => return None,
+            CircuitState::HalfOpen => {
+                state.trial_running = true;
+                true
+            }
+        };
+        Some(Permit {
+            breaker: self.clone(),
+            trial,
+            generation: state.generation,
+            resolved: false,
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BreakerState> {
+        self.inner
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl BreakerState {
+    fn open(&mut self) {
+        self.opened_at = Some(Instant::now());
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    fn current(&self, cool_down: Duration) -> CircuitState {
+        match self.opened_at {
+            None => CircuitState::Closed,
+            Some(opened_at) if opened_at.elapsed() < cool_down => CircuitState::Open,
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+}
+
+impl fmt::Debug for CircuitBreaker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CircuitBreaker")
+            .field("name", &self.inner.name)
+            .field("failure_threshold", &self.inner.failure_threshold)
+            .field("cool_down", &self.inner.cool_down)
+            .field("state", &self.state())
+            .finish()
+    }
+}
+
+/// Permission to run one attempt through a [`CircuitBreaker`].
+///
+/// The outcome of an attempt is ignored if the breaker opened or was reset
+/// while it ran, so a late success cannot close a freshly opened breaker.
+/// Dropping a permit without recording an outcome, e.g. because the attempt
+/// was cancelled, lets another trial attempt through.
+pub(crate) struct Permit {
+    breaker: CircuitBreaker,
+    trial: bool,
+    generation: u64,
+    resolved: bool,
+}
+
+impl Permit {
+    /// Records a successful attempt, closing the breaker.
+    pub(crate) fn succeed(mut self) {
+        self.resolved = true;
+        let mut state = self.breaker.lock();
+        if state.generation == self.generation {
+            *state = BreakerState {
+                generation: self.generation,
+                ..BreakerState::default()
+            };
+        }
+    }
+
+    /// Records a failed attempt, opening the breaker if the threshold is
+    /// reached or the attempt was a trial.
+    pub(crate) fn fail(mut self) {
+        self.resolved = true;
+        let mut state = self.breaker.lock();
+        if state.generation != self.generation {
+            return;
+        }
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+        if self.trial {
+            state.trial_running = false;
+            state.open();
+        } else if state.opened_at.is_none()
+            && state.consecutive_failures >= self.breaker.inner.failure_threshold
+        {
+            state.open();
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if self.trial && !self.resolved {
+            let mut state = self.breaker.lock();
+            if state.generation == self.generation {
+                state.trial_running = false;
+            }
+        }
+    }
+}
+
+/// Circuit breakers shared by name across steps and workflows.
+///
+/// Clones share the same breakers.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// let breakers = CircuitBreakerRegistry::new();
+///
+/// let orders = Workflow::builder()
+///     .add("notify", NotifyStep)
+///     .circuit_breaker(breakers.get_or_insert("webhook", 5, Duration::from_secs(30)))
+///     .start_with("notify")
+///     .build()?;
+/// let refunds = Workflow::builder()
+///     .add("notify", NotifyStep)
+///     .circuit_breaker(breakers.get_or_insert("webhook", 5, Duration::from_secs(30)))
+///     .start_with("notify")
+///     .build()?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CircuitBreakerRegistry {
+    breakers: Arc<Mutex<HashMap<String, CircuitBreaker>>>,
+}
+
+impl CircuitBreakerRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the breaker with the given name, creating it with the given
+    /// settings if it does not exist yet.
+    ///
+    /// The settings of an existing breaker are left unchanged.
+    pub fn get_or_insert(
+        &self,
+        name: &str,
+        failure_threshold: u32,
+        cool_down: Duration,
+    ) -> CircuitBreaker {
+        self.lock()
+            .entry(name.to_string())
+            .or_insert_with(|| CircuitBreaker::new(name, failure_threshold, cool_down))
+            .clone()
+    }
+
+    /// Returns the breaker with the given name, if it exists.
+    pub fn get(&self, name: &str) -> Option<CircuitBreaker> {
+        self.lock().get(name).cloned()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, CircuitBreaker>> {
+        self.breakers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fail(breaker: &CircuitBreaker) {
+        if let Some(permit) = breaker.acquire() {
+            permit.fail();
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_opens_after_threshold() {
+        let breaker = CircuitBreaker::new("api", 2, Duration::from_secs(10));
+        fail(&breaker);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        fail(&breaker);
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert_eq!(breaker.consecutive_failures(), 2);
+        assert!(breaker.acquire().is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_success_resets_failures() {
+        let breaker = CircuitBreaker::new("api", 2, Duration::from_secs(10));
+        fail(&breaker);
+        if let Some(permit) = breaker.acquire() {
+            permit.succeed();
+        }
+        fail(&breaker);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_half_open_allows_one_trial() {
+        let breaker = CircuitBreaker::new("api", 1, Duration::from_secs(10));
+        fail(&breaker);
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+
+        let trial = breaker.acquire();
+        assert!(trial.is_some());
+        assert!(breaker.acquire().is_none());
+
+        if let Some(permit) = trial {
+            permit.fail();
+        }
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        if let Some(permit) = breaker.acquire() {
+            permit.succeed();
+        }
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_eq!(breaker.consecutive_failures(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stale_permits_are_ignored() {
+        let breaker = CircuitBreaker::new("api", 1, Duration::from_secs(10));
+        let slow_success = breaker.acquire();
+        let slow_failure = breaker.acquire();
+        fail(&breaker);
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        if let Some(permit) = slow_success {
+            permit.succeed();
+        }
+        if let Some(permit) = slow_failure {
+            permit.fail();
+        }
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert_eq!(breaker.consecutive_failures(), 1);
+
+        breaker.reset();
+        let before_reset = breaker.acquire();
+        assert!(before_reset.is_some());
+        breaker.reset();
+        if let Some(permit) = before_reset {
+            permit.fail();
+        }
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_eq!(breaker.consecutive_failures(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_dropped_trial_allows_another() {
+        let breaker = CircuitBreaker::new("api", 1, Duration::from_secs(10));
+        fail(&breaker);
+        tokio::time::advance(Duration::from_secs(10)).await;
+        drop(breaker.acquire());
+        assert!(breaker.acquire().is_some());
+    }
+
+    #[test]
+    fn test_registry_shares_by_name() {
+        let registry = CircuitBreakerRegistry::new();
+        let a = registry.get_or_insert("api", 1, Duration::from_secs(10));
+        let b = registry
+            .clone()
+            .get_or_insert("api", 5, Duration::from_secs(1));
+        assert_eq!(b.failure_threshold(), 1);
+        assert!(Arc::ptr_eq(&a.inner, &b.inner));
+        assert!(registry.get("other").is_none());
+    }
+}
@@ -0,0 +1,71 @@
+//! Saga-style compensation of completed steps after a failed run.
+
+use crate::report::CompensationReport;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+use tracing::{info, warn};
+use tsumugi_core::{Compensable, Context, StepName, WorkflowError};
+
+/// The compensable steps completed during a run, in completion order.
+///
+/// Parallel branches share one log, so steps of different branches are
+/// compensated in the reverse of the order they actually completed.
+#[derive(Default)]
+pub(crate) struct CompensationLog {
+    completed: Mutex<Vec<(StepName, Arc<dyn Compensable>)>>,
+}
+
+impl CompensationLog {
+    /// Records a successfully completed step.
+    pub(crate) fn push(&self, name: &StepName, step: Arc<dyn Compensable>) {
+        self.completed
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((name.clone(), step));
+    }
+
+    /// Returns the names of the recorded steps, in completion order.
+    #[cfg(feature = "checkpoint")]
+    pub(crate) fn names(&self) -> Vec<StepName> {
+        self.completed
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Compensates every recorded step, most recently completed first.
+    ///
+    /// Every compensation runs even if an earlier one fails; the failures are
+    /// returned as [`WorkflowError::CompensationFailed`].
+    pub(crate) async fn compensate(
+        self,
+        ctx: &mut Context,
+    ) -> (Vec<CompensationReport>, Vec<WorkflowError>) {
+        let completed = self
+            .completed
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        let mut reports = Vec::with_capacity(completed.len());
+        let mut errors = Vec::new();
+        for (name, step) in completed.into_iter().rev() {
+            info!("Compensating step '{}'", name);
+            let started = Instant::now();
+            let error = match step.compensate(ctx).await {
+                Ok(()) => None,
+                Err(e) => {
+                    warn!("Compensation of step '{}' failed: {}", name, e);
+                    errors.push(WorkflowError::CompensationFailed {
+                        step_name: name.clone(),
+                        details: e.to_string(),
+                    });
+                    Some(e)
+                }
+            };
+            reports.push(CompensationReport::new(name, started.elapsed(), error));
+        }
+        (reports, errors)
+    }
+}
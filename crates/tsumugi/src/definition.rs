@@ -0,0 +1,1198 @@
+//! Workflows defined in YAML, TOML or JSON documents.
+//!
+//! Available with the `definition` feature.
+
+use crate::entry::StepEntry;
+use crate::workflow::{Workflow, WorkflowBuilder};
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
+use tsumugi_core::{Jitter, RetryPolicy, Step, StepName, ValidationIssue, WorkflowError};
+
+type StepFactory = Arc<dyn Fn(&Map<String, Value>) -> Result<Arc<dyn Step>, String> + Send + Sync>;
+
+/// The step types a workflow definition can refer to.
+///
+/// Each type name maps to a factory that creates the step from the `config`
+/// map of its definition. Load definitions with [`Workflow::from_yaml`],
+/// [`Workflow::from_toml`] or [`Workflow::from_json`].
+///
+/// # Examples
+///
+/// ```
+/// use tsumugi::prelude::*;
+///
+/// #[derive(Debug)]
+/// struct Greet {
+///     greeting: String,
+/// }
+///
+/// #[async_trait::async_trait]
+/// impl Step for Greet {
+///     async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+///         ctx.insert("greeting", self.greeting.clone());
+///         Ok(StepOutput::done())
+///     }
+///
+///     fn name(&self) -> StepName {
+///         StepName::new("Greet")
+///     }
+/// }
+///
+/// let registry = StepRegistry::new().register("greet", |config| {
+///     match config.get("greeting").and_then(|v| v.as_str()) {
+///         Some(greeting) => Ok(Greet { greeting: greeting.to_string() }),
+///         None => Err("`greeting` must be a string"),
+///     }
+/// });
+///
+/// let workflow = Workflow::from_yaml(
+///     "
+/// start: hello
+/// steps:
+///   hello:
+///     type: greet
+///     config: { greeting: hi }
+///     timeout: 5s
+///     retry: { policy: fixed, max_retries: 2, delay: 100ms }
+///     terminal: true
+/// ",
+///     &registry,
+/// )?;
+/// assert_eq!(workflow.start_step().as_str(), "hello");
+/// # Ok::<(), DefinitionError>(())
+/// ```
+#[derive(Clone, Default)]
+pub struct StepRegistry {
+    factories: HashMap<String, StepFactory>,
+}
+
+impl StepRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a step type.
+    ///
+    /// `factory` receives the `config` map of each step of this type, or an
+    /// empty map if the definition has none. Its errors are reported at the
+    /// step's definition. Registering the same name again replaces the
+    /// factory.
+    pub fn register<S, E, F>(mut self, type_name: impl Into<String>, factory: F) -> Self
+    where
+        S: Step + 'static,
+        E: fmt::Display,
+        F: Fn(&Map<String, Value>) -> Result<S, E> + Send + Sync + 'static,
+    {
+        let factory: StepFactory = Arc::new(move |config| match factory(config) {
+            Ok(step) => Ok(Arc::new(step)),
+            Err(e) => Err(e.to_string()),
+        });
+        self.factories.insert(type_name.into(), factory);
+        self
+    }
+
+    /// Returns `true` if a step type with the given name is registered.
+    pub fn contains(&self, type_name: &str) -> bool {
+        self.factories.contains_key(type_name)
+    }
+
+    fn type_names(&self) -> String {
+        let mut names: Vec<&str> = self.factories.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names.join(", ")
+    }
+}
+
+impl fmt::Debug for StepRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<&str> = self.factories.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        f.debug_struct("StepRegistry")
+            .field("types", &names)
+            .finish()
+    }
+}
+
+/// Errors from loading a workflow definition.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum DefinitionError {
+    /// The document is malformed or a field is invalid.
+    ///
+    /// The message comes from the parser and includes the location.
+    Invalid {
+        /// What is wrong, and where.
+        message: String,
+        /// The 1-based line of the offending field, if known.
+        line: Option<usize>,
+        /// The 1-based column of the offending field, if known.
+        column: Option<usize>,
+    },
+    /// Every field and the step graph are valid, but the workflow could
+    /// not be built for another reason.
+    Workflow(WorkflowError),
+}
+
+impl DefinitionError {
+    /// Returns the 1-based line the error points to, if any.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            DefinitionError::Invalid { line, .. } => *line,
+            DefinitionError::Workflow(_) => None,
+        }
+    }
+
+    /// Returns the 1-based column the error points to, if any.
+    pub fn column(&self) -> Option<usize> {
+        match self {
+            DefinitionError::Invalid { column, .. } => *column,
+            DefinitionError::Workflow(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for DefinitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DefinitionError::Invalid { message, .. } => write!(f, "{}", message),
+            DefinitionError::Workflow(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for DefinitionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DefinitionError::Invalid { .. } => None,
+            DefinitionError::Workflow(e) => Some(e),
+        }
+    }
+}
+
+impl From<WorkflowError> for DefinitionError {
+    fn from(error: WorkflowError) -> Self {
+        DefinitionError::Workflow(error)
+    }
+}
+
+impl Workflow {
+    /// Builds a workflow from a YAML definition.
+    ///
+    /// The document names the start step and maps each step name to its
+    /// registered `type`, with optional `config`, `timeout`, `retry`, `next`,
+    /// `terminal` and `max_visits` fields. The workflow may also set
+    /// `name`, `deadline`, `retry_budget` and `max_transitions`. Durations
+    /// are written with a unit, e.g. `250ms`, `5s`, `2m` or `1h`.
+    ///
+    /// ```yaml
+    /// name: orders
+    /// start: fetch
+    /// steps:
+    ///   fetch:
+    ///     type: http_fetch
+    ///     config: { url: "https://example.com/orders" }
+    ///     timeout: 10s
+    ///     retry: { policy: exponential, max_retries: 3, initial_delay: 100ms }
+    ///     next: store
+    ///   store:
+    ///     type: store
+    ///     terminal: true
+    /// ```
+    ///
+    /// `policy` is one of `none`, `fixed` (with `delay`), `linear` (with
+    /// `initial_delay`, `increment` and optionally `max_delay`) or
+    /// `exponential` (with `initial_delay` and optionally `max_delay` and
+    /// `multiplier`); any policy but `none` takes `max_retries` and an
+    /// optional `jitter` of `full`, `equal` or `decorrelated` (with
+    /// `jitter_cap`).
+    ///
+    /// Invalid fields, unknown step types and references to undefined steps
+    /// are reported as [`DefinitionError::Invalid`] with the line of the
+    /// offending field. So are problems with the step graph, such as an
+    /// unreachable step, at the name of the step they concern.
+    pub fn from_yaml(source: &str, registry: &StepRegistry) -> Result<Workflow, DefinitionError> {
+        load::<Yaml>(source, registry)
+    }
+
+    /// Builds a workflow from a TOML definition with the fields described
+    /// in [`from_yaml`](Self::from_yaml).
+    ///
+    /// ```toml
+    /// start = "fetch"
+    ///
+    /// [steps.fetch]
+    /// type = "http_fetch"
+    /// config = { url = "https://example.com/orders" }
+    /// retry = { policy = "fixed", max_retries = 3, delay = "1s" }
+    /// next = ["store"]
+    /// ```
+    pub fn from_toml(source: &str, registry: &StepRegistry) -> Result<Workflow, DefinitionError> {
+        load::<Toml>(source, registry)
+    }
+
+    /// Builds a workflow from a JSON definition with the fields described
+    /// in [`from_yaml`](Self::from_yaml).
+    pub fn from_json(source: &str, registry: &StepRegistry) -> Result<Workflow, DefinitionError> {
+        load::<Json>(source, registry)
+    }
+}
+
+/// Reads the step names first, so that references to steps defined later
+/// in the document can be checked where they appear.
+fn load<F: Format>(source: &str, registry: &StepRegistry) -> Result<Workflow, DefinitionError> {
+    let outline: Outline = F::parse(source, PhantomData)?;
+    let names: HashSet<String> = outline.steps.into_keys().collect();
+    let definition = F::parse(
+        source,
+        DefinitionSeed {
+            registry,
+            names: &names,
+        },
+    )?;
+    match definition.into_builder().build() {
+        Ok(workflow) => Ok(workflow),
+        Err(WorkflowError::Validation(issues)) => Err(locate_issues::<F>(source, &issues)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Reports graph issues at the definition of the step each one is about.
+///
+/// Deserializers only expose positions in their errors, so each step is
+/// found by parsing the document again with [`StepLocator`], which fails at
+/// that step's name.
+fn locate_issues<F: Format>(source: &str, issues: &[ValidationIssue]) -> DefinitionError {
+    let mut position = None;
+    let mut messages = Vec::with_capacity(issues.len());
+    for issue in issues {
+        let step = match issue {
+            ValidationIssue::UnknownStep { referenced_by, .. } => referenced_by.as_ref(),
+            ValidationIssue::UnreachableStep(step_name)
+            | ValidationIssue::NoPathToCompletion(step_name)
+            | ValidationIssue::MissingInput { step_name, .. }
+            | ValidationIssue::InputTypeMismatch { step_name, .. } => Some(step_name),
+            _ => None,
+        };
+        let found = step.and_then(|step| match F::parse(source, StepLocator(step.as_str())) {
+            Err(DefinitionError::Invalid {
+                line: Some(line),
+                column,
+                ..
+            }) => Some((line, column)),
+            _ => None,
+        });
+        messages.push(match found {
+            Some((line, Some(column))) => format!("{} at line {} column {}", issue, line, column),
+            Some((line, None)) => format!("{} at line {}", issue, line),
+            None => issue.to_string(),
+        });
+        position = position.or(found);
+    }
+    DefinitionError::Invalid {
+        message: messages.join("; "),
+        line: position.map(|(line, _)| line),
+        column: position.and_then(|(_, column)| column),
+    }
+}
+
+/// A document format, parsing into [`DefinitionError::Invalid`] on error.
+trait Format {
+    fn parse<T, S>(source: &str, seed: S) -> Result<T, DefinitionError>
+    where
+        S: for<'de> DeserializeSeed<'de, Value = T>;
+}
+
+struct Yaml;
+
+impl Format for Yaml {
+    fn parse<T, S>(source: &str, seed: S) -> Result<T, DefinitionError>
+    where
+        S: for<'de> DeserializeSeed<'de, Value = T>,
+    {
+        seed.deserialize(serde_norway::Deserializer::from_str(source))
+            .map_err(|e| {
+                let location = e.location();
+                DefinitionError::Invalid {
+                    line: location.as_ref().map(|l| l.line()),
+                    column: location.as_ref().map(|l| l.column()),
+                    message: e.to_string(),
+                }
+            })
+    }
+}
+
+struct Toml;
+
+impl Format for Toml {
+    fn parse<T, S>(source: &str, seed: S) -> Result<T, DefinitionError>
+    where
+        S: for<'de> DeserializeSeed<'de, Value = T>,
+    {
+        seed.deserialize(toml::Deserializer::new(source))
+            .map_err(|e| {
+                let position = e.span().map(|span| position(source, span.start));
+                DefinitionError::Invalid {
+                    line: position.map(|(line, _)| line),
+                    column: position.map(|(_, column)| column),
+                    message: e.to_string(),
+                }
+            })
+    }
+}
+
+struct Json;
+
+impl Format for Json {
+    fn parse<T, S>(source: &str, seed: S) -> Result<T, DefinitionError>
+    where
+        S: for<'de> DeserializeSeed<'de, Value = T>,
+    {
+        let mut deserializer = serde_json::Deserializer::from_str(source);
+        seed.deserialize(&mut deserializer)
+            .and_then(|value| deserializer.end().map(|()| value))
+            .map_err(|e| DefinitionError::Invalid {
+                line: Some(e.line()).filter(|&line| line > 0),
+                column: Some(e.column()).filter(|&column| column > 0),
+                message: e.to_string(),
+            })
+    }
+}
+
+/// Returns the 1-based line and column of a byte offset.
+fn position(source: &str, offset: usize) -> (usize, usize) {
+    let before = source.get(..offset).unwrap_or(source);
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// Fails at the name of one step in the `steps` map, so that the parse
+/// error carries its position; every other field is skipped.
+struct StepLocator<'a>(&'a str);
+
+impl<'de> DeserializeSeed<'de> for StepLocator<'_> {
+    type Value = ();
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for StepLocator<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a workflow definition")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(field) = map.next_key::<String>()? {
+            if field == "steps" {
+                map.next_value_seed(StepNames(self.0))?;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The `steps` map read by [`StepLocator`].
+struct StepNames<'a>(&'a str);
+
+impl<'de> DeserializeSeed<'de> for StepNames<'_> {
+    type Value = ();
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for StepNames<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a map of step names to step definitions")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while map.next_key_seed(StepKey(self.0))?.is_some() {
+            map.next_value::<IgnoredAny>()?;
+        }
+        Ok(())
+    }
+}
+
+/// A key of the `steps` map read by [`StepLocator`].
+struct StepKey<'a>(&'a str);
+
+impl<'de> DeserializeSeed<'de> for StepKey<'_> {
+    type Value = ();
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de> Visitor<'de> for StepKey<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a step name")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<(), E> {
+        if value == self.0 {
+            Err(E::custom(format!("step '{}' is defined here", value)))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The step names of a definition; every other field is skipped.
+#[derive(Deserialize)]
+struct Outline {
+    #[serde(default)]
+    steps: HashMap<String, IgnoredAny>,
+}
+
+struct Definition {
+    name: Option<String>,
+    start: StepName,
+    deadline: Option<Duration>,
+    retry_budget: Option<u32>,
+    max_transitions: Option<usize>,
+    steps: Vec<StepDefinition>,
+}
+
+impl Definition {
+    fn into_builder(self) -> WorkflowBuilder {
+        let mut builder = WorkflowBuilder::new().start_with(self.start);
+        if let Some(name) = self.name {
+            builder = builder.name(name);
+        }
+        if let Some(deadline) = self.deadline {
+            builder = builder.deadline(deadline);
+        }
+        if let Some(retries) = self.retry_budget {
+            builder = builder.retry_budget(retries);
+        }
+        if let Some(limit) = self.max_transitions {
+            builder = builder.max_transitions(limit);
+        }
+        for step in self.steps {
+            let mut entry = StepEntry::new(step.step);
+            if let Some(timeout) = step.timeout {
+                entry.timeout = timeout;
+            }
+            if let Some(retry_policy) = step.retry_policy {
+                entry.retry_policy = retry_policy;
+            }
+            builder = builder.add_entry(step.name.clone(), entry);
+            for next in step.next {
+                builder = builder.edge(step.name.clone(), next);
+            }
+            if step.terminal {
+                builder = builder.terminal(step.name.clone());
+            }
+            if let Some(limit) = step.max_visits {
+                builder = builder.max_visits(step.name, limit);
+            }
+        }
+        builder
+    }
+}
+
+struct StepDefinition {
+    name: StepName,
+    step: Arc<dyn Step>,
+    timeout: Option<Duration>,
+    retry_policy: Option<RetryPolicy>,
+    next: Vec<StepName>,
+    terminal: bool,
+    max_visits: Option<usize>,
+}
+
+/// Sets a field read from a map, rejecting duplicates.
+fn set_once<T, E: de::Error>(slot: &mut Option<T>, value: T, field: &'static str) -> Result<(), E> {
+    if slot.is_some() {
+        return Err(E::duplicate_field(field));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+#[derive(Clone, Copy)]
+struct DefinitionSeed<'a> {
+    registry: &'a StepRegistry,
+    names: &'a HashSet<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "snake_case")]
+enum DefinitionField {
+    Name,
+    Start,
+    Deadline,
+    RetryBudget,
+    MaxTransitions,
+    Steps,
+}
+
+const DEFINITION_FIELDS: &[&str] = &[
+    "name",
+    "start",
+    "deadline",
+    "retry_budget",
+    "max_transitions",
+    "steps",
+];
+
+impl<'de> DeserializeSeed<'de> for DefinitionSeed<'_> {
+    type Value = Definition;
+
+    fn deserialize<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Definition, D::Error> {
+        deserializer.deserialize_struct("Workflow", DEFINITION_FIELDS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for DefinitionSeed<'_> {
+    type Value = Definition;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a workflow definition")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Definition, A::Error> {
+        let mut name = None;
+        let mut start = None;
+        let mut deadline = None;
+        let mut retry_budget = None;
+        let mut max_transitions = None;
+        let mut steps = None;
+        while let Some(field) = map.next_key()? {
+            match field {
+                DefinitionField::Name => set_once(&mut name, map.next_value()?, "name")?,
+                DefinitionField::Start => {
+                    let value = map.next_value_seed(StepRef(self.names))?;
+                    set_once(&mut start, value, "start")?;
+                }
+                DefinitionField::Deadline => {
+                    let value = map.next_value::<DurationValue>()?.0;
+                    set_once(&mut deadline, value, "deadline")?;
+                }
+                DefinitionField::RetryBudget => {
+                    set_once(&mut retry_budget, map.next_value()?, "retry_budget")?;
+                }
+                DefinitionField::MaxTransitions => {
+                    set_once(&mut max_transitions, map.next_value()?, "max_transitions")?;
+                }
+                DefinitionField::Steps => {
+                    let value = map.next_value_seed(StepsSeed(self))?;
+                    set_once(&mut steps, value, "steps")?;
+                }
+            }
+        }
+        Ok(Definition {
+            name,
+            start: start.ok_or_else(|| de::Error::missing_field("start"))?,
+            deadline,
+            retry_budget,
+            max_transitions,
+            steps: steps.ok_or_else(|| de::Error::missing_field("steps"))?,
+        })
+    }
+}
+
+/// Reads the `steps` map in document order.
+struct StepsSeed<'a>(DefinitionSeed<'a>);
+
+impl<'de> DeserializeSeed<'de> for StepsSeed<'_> {
+    type Value = Vec<StepDefinition>;
+
+    fn deserialize<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Vec<StepDefinition>, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for StepsSeed<'_> {
+    type Value = Vec<StepDefinition>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a map of step names to step definitions")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Vec<StepDefinition>, A::Error> {
+        let mut steps = Vec::new();
+        while let Some(name) = map.next_key::<String>()? {
+            steps.push(map.next_value_seed(StepSeed {
+                name: StepName::new(name),
+                definition: self.0,
+            })?);
+        }
+        Ok(steps)
+    }
+}
+
+struct StepSeed<'a> {
+    name: StepName,
+    definition: DefinitionSeed<'a>,
+}
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "snake_case")]
+enum StepField {
+    Type,
+    Config,
+    Timeout,
+    Retry,
+    Next,
+    Terminal,
+    MaxVisits,
+}
+
+const STEP_FIELDS: &[&str] = &[
+    "type",
+    "config",
+    "timeout",
+    "retry",
+    "next",
+    "terminal",
+    "max_visits",
+];
+
+impl<'de> DeserializeSeed<'de> for StepSeed<'_> {
+    type Value = StepDefinition;
+
+    fn deserialize<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<StepDefinition, D::Error> {
+        deserializer.deserialize_struct("Step", STEP_FIELDS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for StepSeed<'_> {
+    type Value = StepDefinition;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a step definition")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<StepDefinition, A::Error> {
+        let mut step_type = None;
+        let mut config = None;
+        let mut timeout = None;
+        let mut retry_policy = None;
+        let mut next = None;
+        let mut terminal = None;
+        let mut max_visits = None;
+        while let Some(field) = map.next_key()? {
+            match field {
+                StepField::Type => {
+                    let value = map.next_value_seed(StepType(self.definition.registry))?;
+                    set_once(&mut step_type, value, "type")?;
+                }
+                StepField::Config => set_once(&mut config, map.next_value()?, "config")?,
+                StepField::Timeout => {
+                    let value = map.next_value::<DurationValue>()?.0;
+                    set_once(&mut timeout, value, "timeout")?;
+                }
+                StepField::Retry => {
+                    let value = map.next_value::<RetryValue>()?.0;
+                    set_once(&mut retry_policy, value, "retry")?;
+                }
+                StepField::Next => {
+                    let value = map.next_value_seed(StepRefs(self.definition.names))?;
+                    set_once(&mut next, value, "next")?;
+                }
+                StepField::Terminal => set_once(&mut terminal, map.next_value()?, "terminal")?,
+                StepField::MaxVisits => {
+                    set_once(&mut max_visits, map.next_value()?, "max_visits")?;
+                }
+            }
+        }
+
+        let (type_name, factory) = step_type.ok_or_else(|| de::Error::missing_field("type"))?;
+        let step = factory(&config.unwrap_or_default()).map_err(|e| {
+            de::Error::custom(format!(
+                "invalid config for step type '{}': {}",
+                type_name, e
+            ))
+        })?;
+        Ok(StepDefinition {
+            name: self.name,
+            step,
+            timeout,
+            retry_policy,
+            next: next.unwrap_or_default(),
+            terminal: terminal.unwrap_or(false),
+            max_visits,
+        })
+    }
+}
+
+/// Reads a step type name and looks up its factory.
+struct StepType<'a>(&'a StepRegistry);
+
+impl<'de> DeserializeSeed<'de> for StepType<'_> {
+    type Value = (String, StepFactory);
+
+    fn deserialize<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de> Visitor<'de> for StepType<'_> {
+    type Value = (String, StepFactory);
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a registered step type")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        match self.0.factories.get(value) {
+            Some(factory) => Ok((value.to_string(), factory.clone())),
+            None => Err(E::custom(format!(
+                "unknown step type '{}', expected one of: {}",
+                value,
+                self.0.type_names()
+            ))),
+        }
+    }
+}
+
+/// Reads the name of a step defined in the document.
+#[derive(Clone, Copy)]
+struct StepRef<'a>(&'a HashSet<String>);
+
+impl<'de> DeserializeSeed<'de> for StepRef<'_> {
+    type Value = StepName;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<StepName, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de> Visitor<'de> for StepRef<'_> {
+    type Value = StepName;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a step name")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<StepName, E> {
+        if self.0.contains(value) {
+            Ok(StepName::new(value))
+        } else {
+            Err(E::custom(format!("unknown step '{}'", value)))
+        }
+    }
+}
+
+/// Reads one step name or a list of them.
+struct StepRefs<'a>(&'a HashSet<String>);
+
+impl<'de> DeserializeSeed<'de> for StepRefs<'_> {
+    type Value = Vec<StepName>;
+
+    fn deserialize<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Vec<StepName>, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for StepRefs<'_> {
+    type Value = Vec<StepName>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a step name or a list of step names")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Vec<StepName>, E> {
+        StepRef(self.0).visit_str(value).map(|name| vec![name])
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<StepName>, A::Error> {
+        let mut names = Vec::new();
+        while let Some(name) = seq.next_element_seed(StepRef(self.0))? {
+            names.push(name);
+        }
+        Ok(names)
+    }
+}
+
+/// A duration written with a unit, e.g. `250ms`.
+struct DurationValue(Duration);
+
+impl<'de> Deserialize<'de> for DurationValue {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct DurationVisitor;
+
+        impl<'de> Visitor<'de> for DurationVisitor {
+            type Value = DurationValue;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a duration such as \"250ms\", \"5s\", \"2m\" or \"1h\"")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<DurationValue, E> {
+                parse_duration(value)
+                    .map(DurationValue)
+                    .ok_or_else(|| E::invalid_value(de::Unexpected::Str(value), &self))
+            }
+        }
+
+        deserializer.deserialize_str(DurationVisitor)
+    }
+}
+
+fn parse_duration(text: &str) -> Option<Duration> {
+    let split = text.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+    let (number, unit) = text.split_at(split);
+    let unit_secs = match unit {
+        "ms" => return parse_scaled(number, 1, Duration::from_millis),
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        _ => return None,
+    };
+    parse_scaled(number, unit_secs, Duration::from_secs)
+}
+
+/// Parses `number` units of `scale` each, given a constructor for whole units.
+fn parse_scaled(number: &str, scale: u64, whole: fn(u64) -> Duration) -> Option<Duration> {
+    if let Ok(n) = number.parse::<u64>() {
+        return Some(whole(n.checked_mul(scale)?));
+    }
+    let n: f64 = number.parse().ok()?;
+    let unit = whole(scale).as_secs_f64();
+    Duration::try_from_secs_f64(n * unit).ok()
+}
+
+/// A validated `retry` table.
+struct RetryValue(RetryPolicy);
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RetrySpec {
+    policy: PolicyKind,
+    max_retries: Option<u32>,
+    delay: Option<DurationValue>,
+    initial_delay: Option<DurationValue>,
+    increment: Option<DurationValue>,
+    max_delay: Option<DurationValue>,
+    multiplier: Option<f64>,
+    jitter: Option<JitterKind>,
+    jitter_cap: Option<DurationValue>,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum PolicyKind {
+    None,
+    Fixed,
+    Linear,
+    Exponential,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum JitterKind {
+    Full,
+    Equal,
+    Decorrelated,
+}
+
+impl RetrySpec {
+    fn into_policy(self) -> Result<RetryPolicy, String> {
+        let kind = self.policy;
+        let name = match kind {
+            PolicyKind::None => "none",
+            PolicyKind::Fixed => "fixed",
+            PolicyKind::Linear => "linear",
+            PolicyKind::Exponential => "exponential",
+        };
+        let delays = matches!(kind, PolicyKind::Linear | PolicyKind::Exponential);
+        let fields = [
+            (
+                "max_retries",
+                self.max_retries.is_some(),
+                kind != PolicyKind::None,
+            ),
+            ("delay", self.delay.is_some(), kind == PolicyKind::Fixed),
+            ("initial_delay", self.initial_delay.is_some(), delays),
+            (
+                "increment",
+                self.increment.is_some(),
+                kind == PolicyKind::Linear,
+            ),
+            ("max_delay", self.max_delay.is_some(), delays),
+            (
+                "multiplier",
+                self.multiplier.is_some(),
+                kind == PolicyKind::Exponential,
+            ),
+            ("jitter", self.jitter.is_some(), kind != PolicyKind::None),
+        ];
+        if let Some((field, ..)) = fields.iter().find(|(_, set, applies)| *set && !applies) {
+            return Err(format!("`{}` does not apply to the {} policy", field, name));
+        }
+        let decorrelated = self.jitter == Some(JitterKind::Decorrelated);
+        let jitter_cap = match (self.jitter_cap, decorrelated) {
+            (Some(cap), true) => cap.0,
+            (Some(_), false) => {
+                return Err("`jitter_cap` only applies to decorrelated jitter".to_string())
+            }
+            (None, true) => return Err("decorrelated jitter requires `jitter_cap`".to_string()),
+            (None, false) => Duration::ZERO,
+        };
+        let required = |value: Option<DurationValue>, field: &str| {
+            value
+                .map(|v| v.0)
+                .ok_or_else(|| format!("the {} policy requires `{}`", name, field))
+        };
+        let max_retries = self
+            .max_retries
+            .ok_or_else(|| format!("the {} policy requires `max_retries`", name));
+
+        let policy = match kind {
+            PolicyKind::None => return Ok(RetryPolicy::None),
+            PolicyKind::Fixed => RetryPolicy::fixed(max_retries?, required(self.delay, "delay")?),
+            PolicyKind::Linear => {
+                let initial_delay = required(self.initial_delay, "initial_delay")?;
+                let increment = required(self.increment, "increment")?;
+                let mut policy = RetryPolicy::linear(max_retries?, initial_delay, increment);
+                if let (RetryPolicy::Linear { max_delay, .. }, Some(limit)) =
+                    (&mut policy, self.max_delay)
+                {
+                    if limit.0 < initial_delay {
+                        return Err("max_delay must be >= initial_delay".to_string());
+                    }
+                    *max_delay = limit.0;
+                }
+                policy
+            }
+            PolicyKind::Exponential => {
+                let initial_delay = required(self.initial_delay, "initial_delay")?;
+                let max_delay = self
+                    .max_delay
+                    .map_or(Duration::from_secs(60).max(initial_delay), |d| d.0);
+                let multiplier = self.multiplier.unwrap_or(2.0);
+                // Whole multipliers give the same policy as `exponential_backoff` in code.
+                if multiplier.fract() == 0.0 && (1.0..=f64::from(u32::MAX)).contains(&multiplier) {
+                    RetryPolicy::exponential_backoff(
+                        max_retries?,
+                        initial_delay,
+                        max_delay,
+                        multiplier as u32,
+                    )
+                } else {
+                    RetryPolicy::fractional_backoff(
+                        max_retries?,
+                        initial_delay,
+                        max_delay,
+                        multiplier,
+                    )
+                }
+                .map_err(|e| e.to_string())?
+            }
+        };
+        Ok(match self.jitter {
+            Some(JitterKind::Full) => policy.with_jitter(Jitter::Full),
+            Some(JitterKind::Equal) => policy.with_jitter(Jitter::Equal),
+            Some(JitterKind::Decorrelated) => {
+                policy.with_jitter(Jitter::Decorrelated { cap: jitter_cap })
+            }
+            None => policy,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for RetryValue {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct RetryVisitor;
+
+        impl<'de> Visitor<'de> for RetryVisitor {
+            type Value = RetryValue;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a retry policy")
+            }
+
+            // Validating inside the visitor makes the parser report the
+            // location of the `retry` table.
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<RetryValue, A::Error> {
+                let spec = RetrySpec::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                spec.into_policy()
+                    .map(RetryValue)
+                    .map_err(de::Error::custom)
+            }
+        }
+
+        deserializer.deserialize_map(RetryVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn retry(json: Value) -> Result<RetryPolicy, String> {
+        serde_json::from_value::<RetryValue>(json)
+            .map(|retry| retry.0)
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("250ms"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_duration("5s"), Some(Duration::from_secs(5)));
+        assert_eq!(parse_duration("1.5s"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("2m"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1h"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_duration("5"), None);
+        assert_eq!(parse_duration("5 s"), None);
+        assert_eq!(parse_duration("s"), None);
+        assert_eq!(parse_duration("5d"), None);
+    }
+
+    #[test]
+    fn test_position() {
+        let source = "a = 1\nbé = 2\n";
+        assert_eq!(position(source, 0), (1, 1));
+        assert_eq!(position(source, 6), (2, 1));
+        assert_eq!(position(source, 10), (2, 4));
+    }
+
+    #[test]
+    fn test_retry_policies() {
+        assert_eq!(
+            retry(serde_json::json!({ "policy": "none" })),
+            Ok(RetryPolicy::None)
+        );
+        assert_eq!(
+            retry(serde_json::json!({ "policy": "fixed", "max_retries": 3, "delay": "10ms" })),
+            Ok(RetryPolicy::fixed(3, Duration::from_millis(10)))
+        );
+        assert_eq!(
+            retry(serde_json::json!({
+                "policy": "linear",
+                "max_retries": 2,
+                "initial_delay": "1s",
+                "increment": "500ms",
+                "max_delay": "2s",
+            })),
+            Ok(RetryPolicy::Linear {
+                max_retries: 2,
+                initial_delay: Duration::from_secs(1),
+                increment: Duration::from_millis(500),
+                max_delay: Duration::from_secs(2),
+            })
+        );
+        assert_eq!(
+            retry(serde_json::json!({
+                "policy": "exponential",
+                "max_retries": 4,
+                "initial_delay": "100ms",
+                "jitter": "full",
+            })),
+            Ok(RetryPolicy::exponential(4, Duration::from_millis(100)).with_jitter(Jitter::Full))
+        );
+        assert_eq!(
+            retry(serde_json::json!({
+                "policy": "fixed",
+                "max_retries": 3,
+                "delay": "100ms",
+                "jitter": "decorrelated",
+                "jitter_cap": "2s",
+            })),
+            Ok(
+                RetryPolicy::fixed(3, Duration::from_millis(100)).with_jitter(
+                    Jitter::Decorrelated {
+                        cap: Duration::from_secs(2)
+                    }
+                )
+            )
+        );
+        assert_eq!(
+            retry(serde_json::json!({
+                "policy": "exponential",
+                "max_retries": 4,
+                "initial_delay": "100ms",
+                "max_delay": "5s",
+                "multiplier": 1.5,
+            })),
+            RetryPolicy::fractional_backoff(
+                4,
+                Duration::from_millis(100),
+                Duration::from_secs(5),
+                1.5
+            )
+            .map_err(|e| e.to_string())
+        );
+    }
+
+    #[test]
+    fn test_invalid_retry_policies() {
+        assert_eq!(
+            retry(serde_json::json!({ "policy": "fixed", "max_retries": 3 })),
+            Err("the fixed policy requires `delay`".to_string())
+        );
+        assert_eq!(
+            retry(serde_json::json!({ "policy": "fixed", "delay": "1s" })),
+            Err("the fixed policy requires `max_retries`".to_string())
+        );
+        assert_eq!(
+            retry(serde_json::json!({
+                "policy": "fixed",
+                "max_retries": 3,
+                "delay": "1s",
+                "multiplier": 3.0,
+            })),
+            Err("`multiplier` does not apply to the fixed policy".to_string())
+        );
+        assert_eq!(
+            retry(serde_json::json!({
+                "policy": "exponential",
+                "max_retries": 3,
+                "initial_delay": "1s",
+                "multiplier": 0.0,
+            })),
+            Err("multiplier must be greater than 0".to_string())
+        );
+        assert_eq!(
+            retry(serde_json::json!({
+                "policy": "fixed",
+                "max_retries": 3,
+                "delay": "1s",
+                "jitter": "decorrelated",
+            })),
+            Err("decorrelated jitter requires `jitter_cap`".to_string())
+        );
+        assert!(retry(serde_json::json!({ "policy": "sometimes" }))
+            .unwrap_err()
+            .starts_with("unknown variant `sometimes`"));
+    }
+}
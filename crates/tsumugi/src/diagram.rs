@@ -0,0 +1,446 @@
+//! Graphviz DOT and Mermaid renderings of the step graph.
+
+use crate::graph::NodeEdges;
+use crate::report::ExecutionReport;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::time::Duration;
+use tsumugi_core::{Jitter, RetryPolicy, StepName, StepOutput};
+
+const SUCCEEDED_COLOR: &str = "#c8e6c9";
+const FAILED_COLOR: &str = "#ffcdd2";
+const TAKEN_COLOR: &str = "#2e7d32";
+
+/// A node of the step graph with the settings shown on it.
+pub(crate) struct DiagramNode {
+    name: StepName,
+    edges: NodeEdges,
+    notes: Vec<String>,
+    parallel: bool,
+}
+
+impl DiagramNode {
+    pub(crate) fn step(
+        name: StepName,
+        edges: NodeEdges,
+        timeout: Duration,
+        retry_policy: &RetryPolicy,
+    ) -> Self {
+        let mut notes = vec![format!("timeout {:?}", timeout)];
+        notes.extend(describe_retry(retry_policy));
+        Self {
+            name,
+            edges,
+            notes,
+            parallel: false,
+        }
+    }
+
+    pub(crate) fn parallel(name: StepName, edges: NodeEdges) -> Self {
+        let join = if edges.all_branches {
+            "parallel, all branches"
+        } else {
+            "parallel"
+        };
+        Self {
+            name,
+            edges,
+            notes: vec![join.to_string()],
+            parallel: true,
+        }
+    }
+}
+
+/// Summarizes a retry policy, or returns `None` if it never retries.
+fn describe_retry(policy: &RetryPolicy) -> Option<String> {
+    let description = match policy {
+        RetryPolicy::None => return None,
+        RetryPolicy::Fixed { max_retries, delay } => {
+            format!("retry {}x, fixed {:?}", max_retries, delay)
+        }
+        RetryPolicy::Linear {
+            max_retries,
+            initial_delay,
+            increment,
+            ..
+        } => format!(
+            "retry {}x, linear {:?} +{:?}",
+            max_retries, initial_delay, increment
+        ),
+        RetryPolicy::ExponentialBackoff {
+            max_retries,
+            initial_delay,
+            multiplier,
+            ..
+        } => format!(
+            "retry {}x, exponential {:?} x{}",
+            max_retries, initial_delay, multiplier
+        ),
+        RetryPolicy::FractionalBackoff {
+            max_retries,
+            initial_delay,
+            multiplier,
+            ..
+        } => format!(
+            "retry {}x, exponential {:?} x{}",
+            max_retries, initial_delay, multiplier
+        ),
+        RetryPolicy::Jittered { policy, jitter, .. } => {
+            let jitter = match jitter {
+                Jitter::Full => "full jitter".to_string(),
+                Jitter::Equal => "equal jitter".to_string(),
+                Jitter::Decorrelated { cap } => format!("decorrelated jitter up to {:?}", cap),
+            };
+            format!("{}, {}", describe_retry(policy)?, jitter)
+        }
+        RetryPolicy::Custom { max_retries, .. } => format!("retry {}x, custom", max_retries),
+    };
+    Some(description)
+}
+
+/// What a run did at a node.
+#[derive(Clone, Copy, PartialEq)]
+enum Visit {
+    Succeeded,
+    Failed,
+}
+
+struct Edge {
+    from: usize,
+    to: usize,
+    branch: bool,
+    taken: bool,
+}
+
+/// The step graph of a workflow, optionally overlaid with a run.
+///
+/// Nodes are listed start step first, then by name. Edges are the declared
+/// transitions and branches plus any transition observed in the run.
+pub(crate) struct Diagram {
+    name: Option<String>,
+    nodes: Vec<DiagramNode>,
+    visits: Vec<Option<Visit>>,
+    edges: Vec<Edge>,
+}
+
+impl Diagram {
+    pub(crate) fn new(
+        name: Option<&str>,
+        start: &StepName,
+        mut nodes: Vec<DiagramNode>,
+        run: Option<&ExecutionReport>,
+    ) -> Self {
+        nodes.sort_by(|a, b| {
+            (&a.name != start)
+                .cmp(&(&b.name != start))
+                .then_with(|| a.name.as_str().cmp(b.name.as_str()))
+        });
+        let index: HashMap<&StepName, usize> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (&node.name, i))
+            .collect();
+
+        let mut visits = vec![None; nodes.len()];
+        let mut taken = HashSet::new();
+        for step in run.map(ExecutionReport::steps).unwrap_or_default() {
+            let Some(&from) = index.get(step.name()) else {
+                continue;
+            };
+            visits[from] = Some(if step.is_success() {
+                Visit::Succeeded
+            } else {
+                Visit::Failed
+            });
+            if let Some(&to) = step.next_step().and_then(|next| index.get(next)) {
+                taken.insert((from, to));
+            }
+        }
+
+        let mut edges: Vec<Edge> = Vec::new();
+        for (from, node) in nodes.iter().enumerate() {
+            let successors = node.edges.successors.iter().flatten();
+            let targets = successors.filter_map(|output| match output {
+                StepOutput::Continue(name) => Some((name, false)),
+                StepOutput::Complete => None,
+            });
+            for (target, branch) in targets.chain(node.edges.branches.iter().map(|b| (b, true))) {
+                let Some(&to) = index.get(target) else {
+                    continue;
+                };
+                if edges.iter().any(|e| e.from == from && e.to == to) {
+                    continue;
+                }
+                let taken = if branch {
+                    visits[from].is_some() && visits[to].is_some()
+                } else {
+                    taken.contains(&(from, to))
+                };
+                edges.push(Edge {
+                    from,
+                    to,
+                    branch,
+                    taken,
+                });
+            }
+        }
+        let mut observed: Vec<(usize, usize)> = taken
+            .into_iter()
+            .filter(|&(from, to)| !edges.iter().any(|e| e.from == from && e.to == to))
+            .collect();
+        observed.sort_unstable();
+        edges.extend(observed.into_iter().map(|(from, to)| Edge {
+            from,
+            to,
+            branch: false,
+            taken: true,
+        }));
+
+        Self {
+            name: name.map(str::to_string),
+            nodes,
+            visits,
+            edges,
+        }
+    }
+
+    pub(crate) fn to_dot(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "digraph \"{}\" {{",
+            dot_escape(self.name.as_deref().unwrap_or("workflow"))
+        );
+        out.push_str("    node [shape=box, style=rounded];\n");
+        out.push_str("    \"__start\" [shape=point];\n");
+        if let Some(start) = self.nodes.first() {
+            let _ = writeln!(
+                out,
+                "    \"__start\" -> \"{}\";",
+                dot_escape(start.name.as_str())
+            );
+        }
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            let mut label = dot_escape(node.name.as_str());
+            for note in &node.notes {
+                label.push_str("\\n");
+                label.push_str(&dot_escape(note));
+            }
+            let mut attrs = vec![format!("label=\"{}\"", label)];
+            if node.parallel {
+                attrs.push("shape=hexagon".to_string());
+            }
+            if i == 0 {
+                attrs.push("penwidth=2".to_string());
+            }
+            if let Some(visit) = self.visits[i] {
+                let color = match visit {
+                    Visit::Succeeded => SUCCEEDED_COLOR,
+                    Visit::Failed => FAILED_COLOR,
+                };
+                attrs.push("style=\"rounded,filled\"".to_string());
+                attrs.push(format!("fillcolor=\"{}\"", color));
+            }
+            let _ = writeln!(
+                out,
+                "    \"{}\" [{}];",
+                dot_escape(node.name.as_str()),
+                attrs.join(", ")
+            );
+        }
+
+        for edge in &self.edges {
+            let mut attrs = Vec::new();
+            if edge.branch {
+                attrs.push("style=dashed".to_string());
+            }
+            if edge.taken {
+                attrs.push(format!("color=\"{}\"", TAKEN_COLOR));
+                attrs.push("penwidth=2".to_string());
+            }
+            let attrs = if attrs.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", attrs.join(", "))
+            };
+            let _ = writeln!(
+                out,
+                "    \"{}\" -> \"{}\"{};",
+                dot_escape(self.nodes[edge.from].name.as_str()),
+                dot_escape(self.nodes[edge.to].name.as_str()),
+                attrs
+            );
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    pub(crate) fn to_mermaid(&self) -> String {
+        let mut out = String::from("flowchart TD\n");
+        out.push_str("    start((start))\n");
+        for (i, node) in self.nodes.iter().enumerate() {
+            let mut label = mermaid_escape(node.name.as_str());
+            for note in &node.notes {
+                label.push_str("<br/>");
+                label.push_str(&mermaid_escape(note));
+            }
+            let (open, close) = if node.parallel {
+                ("{{", "}}")
+            } else {
+                ("(", ")")
+            };
+            let _ = writeln!(out, "    n{}{}\"{}\"{}", i, open, label, close);
+        }
+
+        // Links are numbered in order for `linkStyle`; the start link is 0.
+        let mut taken = Vec::new();
+        if !self.nodes.is_empty() {
+            out.push_str("    start --> n0\n");
+        }
+        for (i, edge) in self.edges.iter().enumerate() {
+            let arrow = if edge.branch { "-.->" } else { "-->" };
+            let _ = writeln!(out, "    n{} {} n{}", edge.from, arrow, edge.to);
+            if edge.taken {
+                taken.push((i + 1).to_string());
+            }
+        }
+
+        if !self.nodes.is_empty() {
+            out.push_str("    classDef startStep stroke-width:3px\n");
+            out.push_str("    class n0 startStep\n");
+        }
+        for (visit, class, color) in [
+            (Visit::Succeeded, "succeeded", SUCCEEDED_COLOR),
+            (Visit::Failed, "failed", FAILED_COLOR),
+        ] {
+            let ids: Vec<String> = (0..self.nodes.len())
+                .filter(|&i| self.visits[i] == Some(visit))
+                .map(|i| format!("n{}", i))
+                .collect();
+            if !ids.is_empty() {
+                let _ = writeln!(out, "    classDef {} fill:{}", class, color);
+                let _ = writeln!(out, "    class {} {}", ids.join(","), class);
+            }
+        }
+        if !taken.is_empty() {
+            let _ = writeln!(
+                out,
+                "    linkStyle {} stroke:{},stroke-width:2px",
+                taken.join(","),
+                TAKEN_COLOR
+            );
+        }
+        out
+    }
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace("\r\n", "\\n")
+        .replace(['\n', '\r'], "\\n")
+}
+
+fn mermaid_escape(text: &str) -> String {
+    text.replace('"', "#quot;")
+        .replace('<', "#lt;")
+        .replace('>', "#gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edges(successors: &[&str], branches: &[&str]) -> NodeEdges {
+        NodeEdges {
+            successors: Some(successors.iter().map(|s| StepOutput::next(*s)).collect()),
+            branches: branches.iter().map(|b| StepName::new(*b)).collect(),
+            all_branches: true,
+            required: Vec::new(),
+            produced: Vec::new(),
+        }
+    }
+
+    fn step(name: &str, successors: &[&str]) -> DiagramNode {
+        DiagramNode::step(
+            StepName::new(name),
+            edges(successors, &[]),
+            Duration::from_secs(30),
+            &RetryPolicy::None,
+        )
+    }
+
+    #[test]
+    fn test_describe_retry() {
+        assert_eq!(describe_retry(&RetryPolicy::None), None);
+        assert_eq!(
+            describe_retry(&RetryPolicy::fixed(3, Duration::from_millis(10))).as_deref(),
+            Some("retry 3x, fixed 10ms")
+        );
+        assert_eq!(
+            describe_retry(
+                &RetryPolicy::exponential(2, Duration::from_millis(100)).with_jitter(Jitter::Full)
+            )
+            .as_deref(),
+            Some("retry 2x, exponential 100ms x2, full jitter")
+        );
+    }
+
+    #[test]
+    fn test_dot_escapes_names() {
+        let diagram = Diagram::new(
+            None,
+            &StepName::new("say \"hi\""),
+            vec![step("say \"hi\"", &[])],
+            None,
+        );
+        assert_eq!(
+            diagram.to_dot(),
+            "digraph \"workflow\" {\n    node [shape=box, style=rounded];\n    \"__start\" [shape=point];\n    \"__start\" -> \"say \\\"hi\\\"\";\n    \"say \\\"hi\\\"\" [label=\"say \\\"hi\\\"\\ntimeout 30s\", penwidth=2];\n}\n"
+        );
+        assert!(diagram
+            .to_mermaid()
+            .contains("n0(\"say #quot;hi#quot;<br/>timeout 30s\")"));
+    }
+
+    #[test]
+    fn test_dot_escapes_line_breaks() {
+        let diagram = Diagram::new(
+            None,
+            &StepName::new("two\nlines"),
+            vec![
+                step("two\nlines", &["crlf\r\nline"]),
+                step("crlf\r\nline", &[]),
+            ],
+            None,
+        );
+        let dot = diagram.to_dot();
+        assert!(!dot.contains('\r'));
+        assert_eq!(dot.lines().count(), 8);
+        assert!(dot.contains("\"two\\nlines\" -> \"crlf\\nline\";"));
+    }
+
+    #[test]
+    fn test_nodes_start_first_and_branches_dashed() {
+        let diagram = Diagram::new(
+            Some("checks"),
+            &StepName::new("z_start"),
+            vec![
+                step("a", &[]),
+                DiagramNode::parallel(StepName::new("group"), edges(&["a"], &["b"])),
+                step("b", &[]),
+                step("z_start", &["group"]),
+            ],
+            None,
+        );
+        let names: Vec<&str> = diagram.nodes.iter().map(|n| n.name.as_str()).collect();
+        assert_eq!(names, ["z_start", "a", "b", "group"]);
+
+        let mermaid = diagram.to_mermaid();
+        assert!(mermaid.contains("n3{{\"group<br/>parallel, all branches\"}}"));
+        assert!(mermaid.contains("n0 --> n3\n"));
+        assert!(mermaid.contains("n3 --> n1\n"));
+        assert!(mermaid.contains("n3 -.-> n2\n"));
+    }
+}
@@ -0,0 +1,481 @@
+//! Execution of a single registered step with retries, timeout and hooks.
+
+use crate::circuit::CircuitBreaker;
+use crate::listener::{Listeners, WorkflowEvent};
+use std::future::Future;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::{timeout, Instant};
+use tracing::{field, info, info_span, warn, Instrument};
+use tsumugi_core::{
+    AttemptFailure, Compensable, Context, HookType, KeySpec, RetryPolicy, Step, StepName,
+    StepOutput, WithHooks, WorkflowError,
+};
+
+pub(crate) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Decides whether a failed attempt should be retried.
+pub(crate) type RetryPredicate = Arc<dyn Fn(&WorkflowError) -> bool + Send + Sync>;
+
+/// A registered step together with its execution settings.
+pub(crate) struct StepEntry {
+    pub(crate) step: Arc<dyn Step>,
+    pub(crate) hooks: Option<Arc<dyn WithHooks>>,
+    pub(crate) timeout: Duration,
+    pub(crate) retry_policy: RetryPolicy,
+    pub(crate) retry_if: Option<RetryPredicate>,
+    pub(crate) circuit_breaker: Option<CircuitBreaker>,
+    pub(crate) compensation: Option<Arc<dyn Compensable>>,
+    pub(crate) required: Vec<KeySpec>,
+    pub(crate) produced: Vec<KeySpec>,
+}
+
+/// Limits shared by every step of a single workflow run.
+#[derive(Debug, Default)]
+pub(crate) struct RunLimits {
+    deadline: Option<Instant>,
+    retry_budget: Option<AtomicU32>,
+}
+
+impl RunLimits {
+    pub(crate) fn new(deadline: Option<Duration>, retry_budget: Option<u32>) -> Self {
+        Self {
+            // A deadline too far in the future to represent is no deadline at all.
+            deadline: deadline.and_then(|d| Instant::now().checked_add(d)),
+            retry_budget: retry_budget.map(AtomicU32::new),
+        }
+    }
+
+    /// Returns the time left until the deadline, if there is one.
+    pub(crate) fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Returns `true` if the deadline has passed.
+    pub(crate) fn deadline_exceeded(&self) -> bool {
+        self.remaining()
+            .is_some_and(|remaining| remaining.is_zero())
+    }
+
+    /// Takes one retry from the budget, returning `false` if none is left.
+    fn take_retry(&self) -> bool {
+        match &self.retry_budget {
+            Some(budget) => budget
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok(),
+            None => true,
+        }
+    }
+}
+
+tokio::task_local! {
+    static CURRENT_RUN: RunScope;
+}
+
+/// The step being run by the current task, with the limits and listeners of
+/// its run.
+///
+/// Steps that run attempts of their own, like [`MapStep`](crate::MapStep),
+/// read it so those attempts share the run's deadline and retry budget and
+/// are reported to its listeners under the registered step name.
+#[derive(Clone)]
+pub(crate) struct RunScope {
+    pub(crate) step_name: StepName,
+    pub(crate) limits: Arc<RunLimits>,
+    pub(crate) listeners: Listeners,
+}
+
+impl RunScope {
+    /// Creates a scope without limits or listeners, for steps run outside a
+    /// workflow.
+    pub(crate) fn detached(step_name: StepName) -> Self {
+        Self {
+            step_name,
+            limits: Arc::default(),
+            listeners: Listeners::default(),
+        }
+    }
+
+    /// Runs `future` with `self` as the current scope.
+    pub(crate) async fn enter<F: Future>(self, future: F) -> F::Output {
+        CURRENT_RUN.scope(self, future).await
+    }
+
+    /// Returns the current scope, if the task is running a workflow step.
+    pub(crate) fn current() -> Option<Self> {
+        CURRENT_RUN.try_with(Clone::clone).ok()
+    }
+}
+
+/// The attempts made by one run of a step.
+#[derive(Debug, Default)]
+pub(crate) struct AttemptLog {
+    pub(crate) attempts: u32,
+    pub(crate) failures: Vec<AttemptFailure>,
+}
+
+impl AttemptLog {
+    fn record(&mut self, error: WorkflowError, duration: Duration, timed_out: bool) {
+        self.failures.push(AttemptFailure {
+            error,
+            duration,
+            timed_out,
+        });
+    }
+}
+
+impl StepEntry {
+    /// Creates an entry with the default timeout and no retries or hooks.
+    pub(crate) fn new(step: Arc<dyn Step>) -> Self {
+        Self {
+            required: step.required_keys(),
+            produced: step.produced_keys(),
+            step,
+            hooks: None,
+            timeout: DEFAULT_TIMEOUT,
+            retry_policy: RetryPolicy::None,
+            retry_if: None,
+            circuit_breaker: None,
+            compensation: None,
+        }
+    }
+
+    /// Checks that the step set every context key it declares to produce.
+    pub(crate) fn verify_outputs(
+        &self,
+        step_name: &StepName,
+        ctx: &Context,
+    ) -> Result<(), WorkflowError> {
+        self.produced
+            .iter()
+            .try_for_each(|spec| spec.check(ctx))
+            .map_err(|error| WorkflowError::InvalidOutput {
+                step_name: step_name.clone(),
+                error,
+            })
+    }
+
+    /// Runs the step until it succeeds or will not be retried again.
+    ///
+    /// `step_name` is the name the step is registered under, used for
+    /// events.
+    pub(crate) async fn run(
+        &self,
+        step_name: &StepName,
+        ctx: &mut Context,
+        limits: &RunLimits,
+        log: &mut AttemptLog,
+        listeners: &Listeners,
+    ) -> StepResult {
+        let events = StepEvents {
+            step_name,
+            listeners,
+        };
+        let max_retries = self.retry_policy.max_retries();
+
+        for attempt in 0..=max_retries {
+            // Each attempt is bounded by the step timeout and the workflow deadline.
+            let (timeout_duration, bounded_by_deadline) = match limits.remaining() {
+                Some(remaining) if remaining.is_zero() => {
+                    return self
+                        .fail_step(ctx, log, &events, Some(self.deadline_error()))
+                        .await;
+                }
+                Some(remaining) if remaining < self.timeout => (remaining, true),
+                _ => (self.timeout, false),
+            };
+            let permit = match &self.circuit_breaker {
+                Some(breaker) => match breaker.acquire() {
+                    Some(permit) => Some(permit),
+                    None => {
+                        warn!(
+                            "Step '{}' not run: circuit breaker '{}' is open",
+                            self.step.name(),
+                            breaker.name()
+                        );
+                        let error = WorkflowError::CircuitOpen {
+                            step_name: self.step.name(),
+                            circuit: breaker.name().to_string(),
+                        };
+                        return self.fail_step(ctx, log, &events, Some(error)).await;
+                    }
+                },
+                None => None,
+            };
+
+            log.attempts += 1;
+            let started = Instant::now();
+            let span = info_span!(
+                "attempt",
+                step = %step_name,
+                attempt = log.attempts,
+                timeout_ms = u64::try_from(timeout_duration.as_millis()).unwrap_or(u64::MAX),
+                retry_policy = ?self.retry_policy,
+                outcome = field::Empty,
+            );
+            let result = timeout(timeout_duration, self.step.execute(ctx))
+                .instrument(span.clone())
+                .await;
+            span.record(
+                "outcome",
+                match &result {
+                    Ok(Ok(_)) => "success",
+                    Ok(Err(_)) => "error",
+                    Err(_) => "timeout",
+                },
+            );
+            if let Some(permit) = permit {
+                match &result {
+                    Ok(Ok(_)) => permit.succeed(),
+                    _ => permit.fail(),
+                }
+            }
+            let error = match result {
+                Ok(Ok(output)) => {
+                    info!("Step '{}' completed successfully", self.step.name());
+                    let next = match output {
+                        StepOutput::Continue(name) => Some(name),
+                        StepOutput::Complete => None,
+                    };
+                    if let Some(hooks) = &self.hooks {
+                        if let Err(e) = hooks.on_success(ctx).await {
+                            warn!("Step '{}' on_success hook failed", self.step.name());
+                            events.hook_failed(HookType::OnSuccess, &e).await;
+                            return StepResult::Failed(vec![hook_error(
+                                self,
+                                HookType::OnSuccess,
+                                e,
+                            )]);
+                        }
+                    }
+                    return StepResult::Success(next);
+                }
+                Ok(Err(e)) => {
+                    log.record(e.clone(), started.elapsed(), false);
+                    events.attempt_failed(log).await;
+                    e
+                }
+                Err(_) if bounded_by_deadline => {
+                    warn!("Step '{}' exceeded the workflow deadline", self.step.name());
+                    log.record(self.deadline_error(), started.elapsed(), true);
+                    events.timed_out(log, timeout_duration).await;
+                    // A single failed attempt already is the deadline error.
+                    let limit_error = (log.failures.len() > 1).then(|| self.deadline_error());
+                    return self.fail_step(ctx, log, &events, limit_error).await;
+                }
+                Err(_) => {
+                    let e = WorkflowError::Timeout {
+                        step_name: self.step.name(),
+                    };
+                    log.record(e.clone(), started.elapsed(), true);
+                    events.timed_out(log, timeout_duration).await;
+                    e
+                }
+            };
+
+            if attempt < max_retries && !self.is_retryable(&error) {
+                warn!(
+                    "Step '{}' not retried: error is not retryable",
+                    self.step.name()
+                );
+                return self.fail_step(ctx, log, &events, None).await;
+            }
+            if attempt < max_retries {
+                match self
+                    .wait_for_retry(ctx, limits, &events, attempt, &error)
+                    .await
+                {
+                    Ok(()) => continue,
+                    Err(limit_error) => {
+                        return self.fail_step(ctx, log, &events, limit_error).await
+                    }
+                }
+            }
+            warn!(
+                "Step '{}' failed after {} retries",
+                self.step.name(),
+                attempt
+            );
+            return self.fail_step(ctx, log, &events, None).await;
+        }
+
+        unreachable!("Loop should always return")
+    }
+
+    /// Runs the `on_failure` hook (if any) for a step that will not be retried.
+    ///
+    /// A step attempted more than once fails with
+    /// [`WorkflowError::RetriesExhausted`] carrying every attempt; otherwise
+    /// the error of its only attempt is returned. `limit_error` explains why
+    /// retries stopped early, if they did.
+    async fn fail_step(
+        &self,
+        ctx: &mut Context,
+        log: &AttemptLog,
+        events: &StepEvents<'_>,
+        limit_error: Option<WorkflowError>,
+    ) -> StepResult {
+        let error = match log.failures.as_slice() {
+            [] => None,
+            [failure] => Some(failure.error.clone()),
+            attempts => Some(WorkflowError::RetriesExhausted {
+                step_name: self.step.name(),
+                attempts: attempts.to_vec(),
+            }),
+        };
+        let mut errors: Vec<_> = error.into_iter().chain(limit_error).collect();
+        let hook_result = match (&self.hooks, errors.first()) {
+            (Some(hooks), Some(error)) => hooks.on_failure(ctx, error).await,
+            _ => Ok(()),
+        };
+        if let Err(e) = hook_result {
+            warn!("Step '{}' on_failure hook failed", self.step.name());
+            events.hook_failed(HookType::OnFailure, &e).await;
+            errors.push(hook_error(self, HookType::OnFailure, e));
+        }
+        StepResult::Failed(errors)
+    }
+
+    /// Logs and sleeps before the next attempt.
+    ///
+    /// Returns an error if the run's retry budget or deadline does not allow
+    /// another attempt, or `Err(None)` if the workflow is cancelled.
+    async fn wait_for_retry(
+        &self,
+        ctx: &Context,
+        limits: &RunLimits,
+        events: &StepEvents<'_>,
+        attempt: u32,
+        error: &WorkflowError,
+    ) -> Result<(), Option<WorkflowError>> {
+        let token = ctx.cancellation_token();
+        if token.is_cancelled() {
+            return Err(None);
+        }
+        let delay = self.retry_policy.delay_for_attempt(attempt);
+        if let (Some(delay), Some(remaining)) = (delay, limits.remaining()) {
+            if delay >= remaining {
+                warn!(
+                    "Step '{}' not retried: retry delay exceeds the workflow deadline",
+                    self.step.name()
+                );
+                return Err(Some(self.deadline_error()));
+            }
+        }
+        // Only a retry that will be scheduled spends the shared budget.
+        if !limits.take_retry() {
+            warn!(
+                "Step '{}' not retried: workflow retry budget exhausted",
+                self.step.name()
+            );
+            return Err(Some(WorkflowError::RetryBudgetExhausted {
+                step_name: self.step.name(),
+            }));
+        }
+
+        let reason = match error {
+            WorkflowError::Timeout { .. } => "timed out",
+            _ => "failed",
+        };
+        info!(
+            "Step '{}' {}, retrying ({}/{})",
+            self.step.name(),
+            reason,
+            attempt + 1,
+            self.retry_policy.max_retries()
+        );
+        events
+            .emit(|step_name| WorkflowEvent::RetryScheduled {
+                step_name,
+                attempt: attempt + 2,
+                delay: delay.unwrap_or_default(),
+            })
+            .await;
+        if let Some(delay) = delay {
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = token.cancelled() => {
+                    info!("Step '{}' retry abandoned: workflow cancelled", self.step.name());
+                    return Err(None);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Classifies an error with the step's predicate; permanent errors are
+    /// never retried without one.
+    fn is_retryable(&self, error: &WorkflowError) -> bool {
+        match &self.retry_if {
+            Some(predicate) => predicate(error),
+            None => !error.is_permanent(),
+        }
+    }
+
+    fn deadline_error(&self) -> WorkflowError {
+        WorkflowError::DeadlineExceeded {
+            step_name: self.step.name(),
+        }
+    }
+}
+
+/// Emits the events of one step to the workflow's listeners.
+struct StepEvents<'a> {
+    step_name: &'a StepName,
+    listeners: &'a Listeners,
+}
+
+impl StepEvents<'_> {
+    async fn emit(&self, event: impl FnOnce(StepName) -> WorkflowEvent) {
+        if !self.listeners.is_empty() {
+            self.listeners.emit(event(self.step_name.clone())).await;
+        }
+    }
+
+    /// Reports the last failure in `log`.
+    async fn attempt_failed(&self, log: &AttemptLog) {
+        if let Some(failure) = log.failures.last() {
+            self.emit(|step_name| WorkflowEvent::AttemptFailed {
+                step_name,
+                attempt: log.attempts,
+                error: failure.error.clone(),
+                duration: failure.duration,
+            })
+            .await;
+        }
+    }
+
+    async fn timed_out(&self, log: &AttemptLog, timeout: Duration) {
+        self.emit(|step_name| WorkflowEvent::StepTimedOut {
+            step_name,
+            attempt: log.attempts,
+            timeout,
+        })
+        .await;
+        self.attempt_failed(log).await;
+    }
+
+    async fn hook_failed(&self, hook_type: HookType, error: &WorkflowError) {
+        self.emit(|step_name| WorkflowEvent::HookFailed {
+            step_name,
+            hook_type,
+            error: error.clone(),
+        })
+        .await;
+    }
+}
+
+fn hook_error(entry: &StepEntry, hook_type: HookType, error: WorkflowError) -> WorkflowError {
+    WorkflowError::HookError {
+        step_name: entry.step.name(),
+        hook_type,
+        details: error.to_string(),
+    }
+}
+
+pub(crate) enum StepResult {
+    Success(Option<StepName>),
+    Failed(Vec<WorkflowError>),
+}
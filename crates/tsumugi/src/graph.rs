@@ -0,0 +1,579 @@
+//! Build-time analysis of the declared step graph.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use tsumugi_core::{ContextKey, KeySpec, StepName, StepOutput, ValidationIssue};
+
+/// The declared outgoing edges of a single node.
+pub(crate) struct NodeEdges {
+    /// Declared outputs, or `None` if the node's successors are unknown.
+    pub(crate) successors: Option<Vec<StepOutput>>,
+    /// Start steps of parallel branches, which run as separate paths.
+    pub(crate) branches: Vec<StepName>,
+    /// Whether every branch must succeed before the join step runs.
+    pub(crate) all_branches: bool,
+    /// Context keys the node expects to be set.
+    pub(crate) required: Vec<KeySpec>,
+    /// Context keys the node sets when it succeeds.
+    pub(crate) produced: Vec<KeySpec>,
+}
+
+impl NodeEdges {
+    fn targets(&self) -> impl Iterator<Item = &StepName> {
+        self.successors
+            .iter()
+            .flatten()
+            .filter_map(|output| match output {
+                StepOutput::Continue(name) => Some(name),
+                StepOutput::Complete => None,
+            })
+            .chain(&self.branches)
+    }
+}
+
+/// Checks every declared edge and returns all problems found.
+///
+/// Reachability is only checked when every step reachable from `start`
+/// declares its successors, since an undeclared step could lead anywhere.
+/// Undeclared steps are assumed to be able to complete.
+pub(crate) fn validate(
+    start: Option<&StepName>,
+    nodes: &HashMap<StepName, NodeEdges>,
+) -> Vec<ValidationIssue> {
+    let mut sorted: Vec<(&StepName, &NodeEdges)> = nodes.iter().collect();
+    sorted.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+
+    let mut issues = Vec::new();
+    for &(name, edges) in &sorted {
+        let mut seen = HashSet::new();
+        for target in edges.targets() {
+            if !nodes.contains_key(target) && seen.insert(target) {
+                issues.push(ValidationIssue::UnknownStep {
+                    step_name: target.clone(),
+                    referenced_by: Some(name.clone()),
+                });
+            }
+        }
+    }
+
+    let unreachable: HashSet<&StepName> = match start.and_then(|start| reachable(start, nodes)) {
+        Some(reached) => nodes
+            .keys()
+            .filter(|name| !reached.contains(name))
+            .collect(),
+        None => HashSet::new(),
+    };
+    let completing = can_complete(nodes);
+
+    for &(name, _) in &sorted {
+        if unreachable.contains(name) {
+            issues.push(ValidationIssue::UnreachableStep(name.clone()));
+        } else if !completing.contains(name) {
+            issues.push(ValidationIssue::NoPathToCompletion(name.clone()));
+        }
+    }
+    issues
+}
+
+/// Returns the steps reachable from `start`, or `None` if the path leads
+/// through a step whose successors are unknown.
+fn reachable<'a>(
+    start: &'a StepName,
+    nodes: &'a HashMap<StepName, NodeEdges>,
+) -> Option<HashSet<&'a StepName>> {
+    let mut reached = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(name) = queue.pop_front() {
+        let Some(edges) = nodes.get(name) else {
+            continue;
+        };
+        edges.successors.as_ref()?;
+        for target in edges.targets() {
+            if reached.insert(target) {
+                queue.push_back(target);
+            }
+        }
+    }
+    Some(reached)
+}
+
+/// Returns the steps from which some path can complete the workflow.
+fn can_complete(nodes: &HashMap<StepName, NodeEdges>) -> HashSet<&StepName> {
+    // Unknown targets are reported separately; treating them as completing
+    // avoids a second report for every step leading to them.
+    let mut completing: HashSet<&StepName> = nodes
+        .iter()
+        .filter(|(_, edges)| match &edges.successors {
+            None => true,
+            Some(outputs) => outputs.iter().any(|output| match output {
+                StepOutput::Complete => true,
+                StepOutput::Continue(target) => !nodes.contains_key(target),
+            }),
+        })
+        .map(|(name, _)| name)
+        .collect();
+
+    loop {
+        let before = completing.len();
+        for (name, edges) in nodes {
+            if completing.contains(name) {
+                continue;
+            }
+            let leads_to_completion = edges.successors.iter().flatten().any(
+                |output| matches!(output, StepOutput::Continue(target) if completing.contains(target)),
+            );
+            if leads_to_completion {
+                completing.insert(name);
+            }
+        }
+        if completing.len() == before {
+            return completing;
+        }
+    }
+}
+
+/// Context keys known to be set, with their declared types.
+type Keys = HashMap<ContextKey, KeySpec>;
+
+/// Checks that every required context key is set before its step runs.
+///
+/// When every step reachable from `start` declares its successors, a key
+/// must be set on every path leading to the step. Otherwise it is enough
+/// that some step produces the key or that it is an initial input.
+pub(crate) fn validate_keys(
+    start: &StepName,
+    nodes: &HashMap<StepName, NodeEdges>,
+    initial: &[KeySpec],
+) -> Vec<ValidationIssue> {
+    if nodes.values().all(|edges| edges.required.is_empty()) {
+        return Vec::new();
+    }
+    let initial: Keys = initial
+        .iter()
+        .map(|spec| (spec.key().clone(), spec.clone()))
+        .collect();
+    let available = match reachable(start, nodes) {
+        Some(_) => available_keys(start, nodes, initial),
+        None => {
+            // Without the full graph, types of competing producers cannot
+            // be compared either.
+            let keys: Keys = initial
+                .into_keys()
+                .chain(
+                    nodes
+                        .values()
+                        .flat_map(|edges| edges.produced.iter().map(|spec| spec.key().clone())),
+                )
+                .map(|key| (key.clone(), KeySpec::any(key)))
+                .collect();
+            nodes.keys().map(|name| (name, keys.clone())).collect()
+        }
+    };
+
+    let mut sorted: Vec<(&StepName, &NodeEdges)> = nodes.iter().collect();
+    sorted.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+
+    let mut issues = Vec::new();
+    for (name, edges) in sorted {
+        let Some(keys) = available.get(name) else {
+            continue;
+        };
+        for spec in &edges.required {
+            match keys.get(spec.key()) {
+                None => issues.push(ValidationIssue::MissingInput {
+                    step_name: name.clone(),
+                    key: spec.key().clone(),
+                }),
+                Some(set) => {
+                    if let (Some(expected), Some(found)) = (spec.type_name(), set.type_name()) {
+                        if spec.type_id() != set.type_id() {
+                            issues.push(ValidationIssue::InputTypeMismatch {
+                                step_name: name.clone(),
+                                key: spec.key().clone(),
+                                expected,
+                                found,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+    issues
+}
+
+/// Returns the keys set on every path to each step reachable from `start`.
+fn available_keys<'a>(
+    start: &'a StepName,
+    nodes: &'a HashMap<StepName, NodeEdges>,
+    initial: Keys,
+) -> HashMap<&'a StepName, Keys> {
+    // A branch ends at any step on its path that completes it.
+    let branch_ends: HashMap<&StepName, Vec<&StepName>> = nodes
+        .values()
+        .flat_map(|edges| &edges.branches)
+        .map(|branch| (branch, path_ends(branch, nodes)))
+        .collect();
+
+    // Steps without an entry have not been reached yet, which places no
+    // constraint on them.
+    let mut before: HashMap<&StepName, Keys> = HashMap::from([(start, initial)]);
+    loop {
+        let mut changed = false;
+        for (name, edges) in nodes {
+            let Some(keys) = before.get(name).cloned() else {
+                continue;
+            };
+            for branch in &edges.branches {
+                changed |= meet(&mut before, branch, &keys);
+            }
+            let after = if edges.branches.is_empty() {
+                Some(with_produced(keys, &edges.produced))
+            } else {
+                joined(keys, edges, nodes, &before, &branch_ends)
+            };
+            if let Some(after) = after {
+                for target in continue_targets(edges) {
+                    changed |= meet(&mut before, target, &after);
+                }
+            }
+        }
+        if !changed {
+            return before;
+        }
+    }
+}
+
+/// Returns the keys set after a parallel group joins, or `None` if no branch
+/// has been analysed to completion yet.
+fn joined(
+    mut keys: Keys,
+    edges: &NodeEdges,
+    nodes: &HashMap<StepName, NodeEdges>,
+    before: &HashMap<&StepName, Keys>,
+    branch_ends: &HashMap<&StepName, Vec<&StepName>>,
+) -> Option<Keys> {
+    let mut merged: Option<Keys> = None;
+    for branch in &edges.branches {
+        let mut result = None;
+        for end in branch_ends.get(branch).into_iter().flatten() {
+            if let (Some(keys), Some(node)) = (before.get(end), nodes.get(*end)) {
+                intersect(&mut result, &with_produced(keys.clone(), &node.produced));
+            }
+        }
+        let result = result?;
+        match &mut merged {
+            Some(merged) if edges.all_branches => merged.extend(result),
+            _ => {
+                intersect(&mut merged, &result);
+            }
+        }
+    }
+    keys.extend(merged?);
+    Some(keys)
+}
+
+/// Returns the steps that can complete the path starting at `start`,
+/// without following parallel branches.
+fn path_ends<'a>(
+    start: &'a StepName,
+    nodes: &'a HashMap<StepName, NodeEdges>,
+) -> Vec<&'a StepName> {
+    let mut seen = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    let mut ends = Vec::new();
+    while let Some(name) = queue.pop_front() {
+        let Some(edges) = nodes.get(name) else {
+            continue;
+        };
+        if edges
+            .successors
+            .iter()
+            .flatten()
+            .any(|output| matches!(output, StepOutput::Complete))
+        {
+            ends.push(name);
+        }
+        for target in continue_targets(edges) {
+            if seen.insert(target) {
+                queue.push_back(target);
+            }
+        }
+    }
+    ends
+}
+
+fn continue_targets(edges: &NodeEdges) -> impl Iterator<Item = &StepName> {
+    edges
+        .successors
+        .iter()
+        .flatten()
+        .filter_map(|output| match output {
+            StepOutput::Continue(name) => Some(name),
+            StepOutput::Complete => None,
+        })
+}
+
+fn with_produced(mut keys: Keys, produced: &[KeySpec]) -> Keys {
+    keys.extend(
+        produced
+            .iter()
+            .map(|spec| (spec.key().clone(), spec.clone())),
+    );
+    keys
+}
+
+/// Narrows the keys reaching `name` to those also in `incoming`, returning
+/// `true` if anything changed.
+fn meet<'a>(before: &mut HashMap<&'a StepName, Keys>, name: &'a StepName, incoming: &Keys) -> bool {
+    let mut keys = before.remove(name);
+    let changed = intersect(&mut keys, incoming);
+    before.extend(keys.map(|keys| (name, keys)));
+    changed
+}
+
+/// Keeps the keys of `keys` that are also in `other`, forgetting the type of
+/// keys set with different types. `None` stands for every key.
+fn intersect(keys: &mut Option<Keys>, other: &Keys) -> bool {
+    let Some(keys) = keys else {
+        *keys = Some(other.clone());
+        return true;
+    };
+    let before = keys.len();
+    keys.retain(|key, _| other.contains_key(key));
+    let mut changed = keys.len() != before;
+    for (key, spec) in keys.iter_mut() {
+        if spec.type_id().is_some() && other.get(key).and_then(KeySpec::type_id) != spec.type_id() {
+            *spec = KeySpec::any(key.clone());
+            changed = true;
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn declared(outputs: &[&str]) -> NodeEdges {
+        NodeEdges {
+            successors: Some(
+                outputs
+                    .iter()
+                    .map(|name| match *name {
+                        "" => StepOutput::done(),
+                        name => StepOutput::next(name),
+                    })
+                    .collect(),
+            ),
+            ..undeclared()
+        }
+    }
+
+    fn undeclared() -> NodeEdges {
+        NodeEdges {
+            successors: None,
+            branches: Vec::new(),
+            all_branches: true,
+            required: Vec::new(),
+            produced: Vec::new(),
+        }
+    }
+
+    fn keys(mut edges: NodeEdges, required: &[KeySpec], produced: &[KeySpec]) -> NodeEdges {
+        edges.required = required.to_vec();
+        edges.produced = produced.to_vec();
+        edges
+    }
+
+    fn missing(step: &str, key: &str) -> ValidationIssue {
+        ValidationIssue::MissingInput {
+            step_name: StepName::new(step),
+            key: ContextKey::new(key),
+        }
+    }
+
+    fn graph(nodes: Vec<(&str, NodeEdges)>) -> HashMap<StepName, NodeEdges> {
+        nodes
+            .into_iter()
+            .map(|(name, edges)| (StepName::new(name), edges))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_graph() {
+        let nodes = graph(vec![
+            ("a", declared(&["b", "c"])),
+            ("b", declared(&["a", ""])),
+            ("c", declared(&[""])),
+        ]);
+        assert!(validate(Some(&StepName::new("a")), &nodes).is_empty());
+    }
+
+    #[test]
+    fn test_reports_all_issues() {
+        let nodes = graph(vec![
+            ("a", declared(&["paymnet", "b"])),
+            ("b", declared(&["c"])),
+            ("c", declared(&["b"])),
+            ("orphan", declared(&[""])),
+        ]);
+        let issues = validate(Some(&StepName::new("a")), &nodes);
+        assert_eq!(
+            issues,
+            vec![
+                ValidationIssue::UnknownStep {
+                    step_name: StepName::new("paymnet"),
+                    referenced_by: Some(StepName::new("a")),
+                },
+                ValidationIssue::NoPathToCompletion(StepName::new("b")),
+                ValidationIssue::NoPathToCompletion(StepName::new("c")),
+                ValidationIssue::UnreachableStep(StepName::new("orphan")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_undeclared_steps_skip_reachability() {
+        let nodes = graph(vec![
+            ("a", declared(&["b"])),
+            ("b", undeclared()),
+            ("c", declared(&[""])),
+        ]);
+        assert!(validate(Some(&StepName::new("a")), &nodes).is_empty());
+    }
+
+    #[test]
+    fn test_branches_are_reachable() {
+        let mut group = declared(&["join"]);
+        group.branches = vec![StepName::new("x")];
+        let nodes = graph(vec![
+            ("group", group),
+            ("x", declared(&[""])),
+            ("join", declared(&[""])),
+        ]);
+        assert!(validate(Some(&StepName::new("group")), &nodes).is_empty());
+    }
+
+    #[test]
+    fn test_required_key_must_be_set_on_every_path() {
+        let order = || KeySpec::of::<String>("order");
+        let nodes = |c_produces: &[KeySpec]| {
+            graph(vec![
+                ("a", declared(&["b", "c"])),
+                ("b", keys(declared(&["d"]), &[], &[order()])),
+                ("c", keys(declared(&["d"]), &[], c_produces)),
+                ("d", keys(declared(&[""]), &[order()], &[])),
+            ])
+        };
+        let start = StepName::new("a");
+
+        assert_eq!(
+            validate_keys(&start, &nodes(&[]), &[]),
+            vec![missing("d", "order")]
+        );
+        assert!(validate_keys(&start, &nodes(&[order()]), &[]).is_empty());
+        assert!(validate_keys(&start, &nodes(&[]), &[KeySpec::any("order")]).is_empty());
+        assert_eq!(
+            validate_keys(&start, &nodes(&[KeySpec::of::<u64>("order")]), &[]),
+            Vec::new(),
+            "conflicting producers leave the type unknown"
+        );
+    }
+
+    #[test]
+    fn test_required_key_type_mismatch() {
+        let nodes = graph(vec![
+            (
+                "a",
+                keys(declared(&["b"]), &[], &[KeySpec::of::<u64>("id")]),
+            ),
+            (
+                "b",
+                keys(declared(&[""]), &[KeySpec::of::<String>("id")], &[]),
+            ),
+        ]);
+        assert_eq!(
+            validate_keys(&StepName::new("a"), &nodes, &[]),
+            vec![ValidationIssue::InputTypeMismatch {
+                step_name: StepName::new("b"),
+                key: ContextKey::new("id"),
+                expected: std::any::type_name::<String>(),
+                found: "u64",
+            }]
+        );
+    }
+
+    #[test]
+    fn test_required_keys_across_loops() {
+        // "b" loops back to itself before "c" reads what "a" produced.
+        let nodes = graph(vec![
+            ("a", keys(declared(&["b"]), &[], &[KeySpec::any("x")])),
+            ("b", keys(declared(&["b", "c"]), &[KeySpec::any("x")], &[])),
+            (
+                "c",
+                keys(
+                    declared(&[""]),
+                    &[KeySpec::any("x"), KeySpec::any("y")],
+                    &[],
+                ),
+            ),
+        ]);
+        assert_eq!(
+            validate_keys(&StepName::new("a"), &nodes, &[]),
+            vec![missing("c", "y")]
+        );
+    }
+
+    #[test]
+    fn test_parallel_join_sees_branch_keys() {
+        let nodes = |all_branches| {
+            let mut group = declared(&["join"]);
+            group.branches = vec![StepName::new("x"), StepName::new("y")];
+            group.all_branches = all_branches;
+            graph(vec![
+                ("group", group),
+                (
+                    "x",
+                    keys(
+                        declared(&[""]),
+                        &[KeySpec::any("input")],
+                        &[KeySpec::any("a")],
+                    ),
+                ),
+                ("y", keys(declared(&[""]), &[], &[KeySpec::any("b")])),
+                ("join", keys(declared(&[""]), &[KeySpec::any("a")], &[])),
+            ])
+        };
+        let start = StepName::new("group");
+        let input = [KeySpec::any("input")];
+
+        assert!(validate_keys(&start, &nodes(true), &input).is_empty());
+        assert_eq!(
+            validate_keys(&start, &nodes(false), &input),
+            vec![missing("join", "a")]
+        );
+        assert_eq!(
+            validate_keys(&start, &nodes(true), &[]),
+            vec![missing("x", "input")]
+        );
+    }
+
+    #[test]
+    fn test_undeclared_steps_only_need_a_producer() {
+        let nodes = graph(vec![
+            ("a", keys(undeclared(), &[], &[KeySpec::of::<u8>("x")])),
+            (
+                "b",
+                keys(
+                    undeclared(),
+                    &[KeySpec::of::<u64>("x"), KeySpec::any("y")],
+                    &[],
+                ),
+            ),
+        ]);
+        assert_eq!(
+            validate_keys(&StepName::new("b"), &nodes, &[]),
+            vec![missing("b", "y")]
+        );
+    }
+}
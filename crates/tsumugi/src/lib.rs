@@ -34,18 +34,56 @@
 //! }
 //! ```
 
+#[cfg(feature = "checkpoint")]
+mod checkpoint;
+mod circuit;
+mod compensation;
+#[cfg(feature = "definition")]
+mod definition;
+mod diagram;
+mod entry;
+mod graph;
+mod listener;
+mod map;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod parallel;
+mod report;
+mod sub_workflow;
 mod workflow;
 
 // Re-export core types
 pub use tsumugi_core::*;
 
 // Export workflow types
-pub use workflow::{Workflow, WorkflowBuilder};
+#[cfg(feature = "checkpoint")]
+pub use checkpoint::{Checkpoint, CheckpointStore, FileCheckpointStore};
+pub use circuit::{CircuitBreaker, CircuitBreakerRegistry, CircuitState};
+#[cfg(feature = "definition")]
+pub use definition::{DefinitionError, StepRegistry};
+pub use listener::{WorkflowEvent, WorkflowListener};
+pub use map::{MapFailureMode, MapStep};
+pub use parallel::{JoinPolicy, ParallelGroup};
+pub use report::{CompensationReport, ExecutionReport, StepReport};
+pub use sub_workflow::SubWorkflowStep;
+pub use workflow::{
+    ExecuteOptions, StepBuilder, Workflow, WorkflowBuilder, DEFAULT_MAX_TRANSITIONS,
+};
 
 /// Prelude for convenient imports.
 pub mod prelude {
     pub use crate::{
-        Context, ContextKey, HookType, RetryPolicy, Retryable, Step, StepConfig, StepName,
-        StepOutput, WithHooks, WithTimeout, Workflow, WorkflowBuilder, WorkflowError,
+        CancellationToken, CircuitBreaker, CircuitBreakerRegistry, CircuitState, Compensable,
+        Context, ContextError, ContextKey, ExecuteOptions, ExecutionReport, HookType, Jitter,
+        JoinPolicy, KeySpec, MapFailureMode, MapStep, ParallelGroup, RetryPolicy, Retryable, Step,
+        StepBuilder, StepConfig, StepName, StepOutput, SubWorkflowStep, TypedKey, ValidationIssue,
+        WithHooks, WithTimeout, Workflow, WorkflowBuilder, WorkflowError, WorkflowEvent,
+        WorkflowListener,
     };
+
+    #[cfg(feature = "checkpoint")]
+    pub use crate::{CheckpointStore, FileCheckpointStore};
+
+    #[cfg(feature = "definition")]
+    pub use crate::{DefinitionError, StepRegistry};
 }
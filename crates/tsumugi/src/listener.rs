@@ -0,0 +1,156 @@
+//! Structured events describing workflow runs.
+
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::Duration;
+use tsumugi_core::{HookType, StepName, WorkflowError};
+
+/// Something that happened during a workflow run, delivered to every
+/// [`WorkflowListener`].
+///
+/// Attempts are numbered from 1. Step names are the names the steps were
+/// registered under.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum WorkflowEvent {
+    /// A run started.
+    WorkflowStarted {
+        /// The step the run starts with.
+        start_step: StepName,
+    },
+    /// A run finished, after any compensation.
+    WorkflowFinished {
+        /// How long the run took.
+        duration: Duration,
+        /// The result of the run, as returned by
+        /// [`Workflow::execute`](crate::Workflow::execute).
+        outcome: Result<(), Vec<WorkflowError>>,
+    },
+    /// A step or parallel group started.
+    StepStarted {
+        /// The step.
+        step_name: StepName,
+    },
+    /// An attempt of a step failed or timed out.
+    AttemptFailed {
+        /// The step.
+        step_name: StepName,
+        /// The number of the failed attempt.
+        attempt: u32,
+        /// Why the attempt failed.
+        error: WorkflowError,
+        /// How long the attempt ran.
+        duration: Duration,
+    },
+    /// An attempt of a step was cut short by its timeout or the workflow
+    /// deadline. Followed by [`AttemptFailed`](Self::AttemptFailed).
+    StepTimedOut {
+        /// The step.
+        step_name: StepName,
+        /// The number of the attempt that timed out.
+        attempt: u32,
+        /// The time the attempt was allowed.
+        timeout: Duration,
+    },
+    /// A failed step will be attempted again.
+    RetryScheduled {
+        /// The step.
+        step_name: StepName,
+        /// The number of the upcoming attempt.
+        attempt: u32,
+        /// How long the engine waits before the attempt.
+        delay: Duration,
+    },
+    /// A lifecycle hook of a step failed.
+    HookFailed {
+        /// The step.
+        step_name: StepName,
+        /// Which hook failed.
+        hook_type: HookType,
+        /// The error the hook returned.
+        error: WorkflowError,
+    },
+    /// A step or parallel group succeeded.
+    StepCompleted {
+        /// The step.
+        step_name: StepName,
+        /// The step the run continues with, or `None` if the path completed.
+        next_step: Option<StepName>,
+        /// How long the step took, including retries.
+        duration: Duration,
+    },
+    /// A step or parallel group failed for good.
+    StepFailed {
+        /// The step.
+        step_name: StepName,
+        /// The errors the step failed with.
+        errors: Vec<WorkflowError>,
+        /// How long the step took, including retries.
+        duration: Duration,
+    },
+}
+
+/// Receives the [`WorkflowEvent`]s of every run of a workflow.
+///
+/// Register listeners with
+/// [`WorkflowBuilder::listener`](crate::WorkflowBuilder::listener). Events
+/// are delivered in the order they happen and each call is awaited before
+/// the run continues, so a slow listener slows the run down. Events of
+/// concurrent parallel branches may interleave.
+///
+/// # Examples
+///
+/// ```
+/// use async_trait::async_trait;
+/// use std::sync::Mutex;
+/// use tsumugi::{WorkflowEvent, WorkflowListener};
+///
+/// #[derive(Default)]
+/// struct AuditLog {
+///     lines: Mutex<Vec<String>>,
+/// }
+///
+/// #[async_trait]
+/// impl WorkflowListener for AuditLog {
+///     async fn on_event(&self, event: &WorkflowEvent) {
+///         if let WorkflowEvent::StepCompleted { step_name, .. } = event {
+///             if let Ok(mut lines) = self.lines.lock() {
+///                 lines.push(format!("{} done", step_name));
+///             }
+///         }
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait WorkflowListener: Send + Sync {
+    /// Handles one event.
+    async fn on_event(&self, event: &WorkflowEvent);
+}
+
+#[async_trait]
+impl<L: WorkflowListener + ?Sized> WorkflowListener for Arc<L> {
+    async fn on_event(&self, event: &WorkflowEvent) {
+        (**self).on_event(event).await;
+    }
+}
+
+/// The listeners of a workflow.
+#[derive(Clone, Default)]
+pub(crate) struct Listeners(Vec<Arc<dyn WorkflowListener>>);
+
+impl Listeners {
+    pub(crate) fn push(&mut self, listener: Arc<dyn WorkflowListener>) {
+        self.0.push(listener);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Delivers `event` to every listener in registration order.
+    pub(crate) async fn emit(&self, event: WorkflowEvent) {
+        for listener in &self.0 {
+            listener.on_event(&event).await;
+        }
+    }
+}
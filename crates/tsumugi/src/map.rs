@@ -0,0 +1,282 @@
+//! Map a step over a collection with bounded concurrency.
+
+use crate::circuit::CircuitBreaker;
+use crate::entry::{AttemptLog, RunScope, StepEntry, StepResult};
+use async_trait::async_trait;
+use futures_util::stream::{self, StreamExt};
+use std::any::Any;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+use tsumugi_core::{Context, ContextKey, RetryPolicy, Step, StepName, StepOutput, WorkflowError};
+
+/// What a [`MapStep`] does when an item fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MapFailureMode {
+    /// Stop at the first failed item and fail the step with its error.
+    #[default]
+    FailFast,
+    /// Process every item and record failures in the results.
+    CollectAll,
+}
+
+/// A step that runs a child step once per item of a `Vec<T>` in the context.
+///
+/// Each item runs against its own context forked from the parent: the item is
+/// available under [`MapStep::ITEM_KEY`], every parent entry can be read, and
+/// the child stores its result of type `U` under [`MapStep::OUTPUT_KEY`].
+/// Items run with the configured timeout and [`RetryPolicy`], at most
+/// [`concurrency`](Self::concurrency) at a time. Their retries count against
+/// the workflow's deadline and retry budget, and their attempts are reported
+/// to its listeners under the name the map step is registered under.
+///
+/// The results are stored as `Vec<Result<U, WorkflowError>>` under the output
+/// key, in the same order as the input items.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// let workflow = Workflow::builder()
+///     .add_step("scan", ScanStep)
+///     .add_step(
+///         "parse",
+///         MapStep::<InputFile, ParsedFile>::new("ParseFiles", "files", "parsed", ParseFileStep)
+///             .concurrency(8)
+///             .retry_policy(RetryPolicy::fixed(2, Duration::from_millis(100)))
+///             .continue_with("summary"),
+///     )
+///     .add_step("summary", SummaryStep)
+///     .start_with("scan")
+///     .build()?;
+/// ```
+pub struct MapStep<T, U> {
+    name: StepName,
+    input_key: ContextKey,
+    output_key: ContextKey,
+    entry: StepEntry,
+    concurrency: usize,
+    failure_mode: MapFailureMode,
+    next: StepOutput,
+    _marker: PhantomData<fn(T) -> U>,
+}
+
+impl<T, U> MapStep<T, U> {
+    /// The key under which each item is available to the child step.
+    pub const ITEM_KEY: &'static str = "item";
+
+    /// The key under which the child step stores its result.
+    pub const OUTPUT_KEY: &'static str = "output";
+
+    /// Creates a map step reading `Vec<T>` from `input_key` and storing the
+    /// results under `output_key`.
+    ///
+    /// By default items run one at a time, without retries, with the default
+    /// timeout, and the first failure fails the step.
+    pub fn new<S: Step + 'static>(
+        name: impl Into<StepName>,
+        input_key: impl Into<ContextKey>,
+        output_key: impl Into<ContextKey>,
+        child: S,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            input_key: input_key.into(),
+            output_key: output_key.into(),
+            entry: StepEntry::new(Arc::new(child)),
+            concurrency: 1,
+            failure_mode: MapFailureMode::FailFast,
+            next: StepOutput::Complete,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Sets how many items may run at the same time.
+    ///
+    /// A value of 0 is treated as 1.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Sets the timeout for each attempt of an item.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.entry.timeout = timeout;
+        self
+    }
+
+    /// Sets the retry policy applied to each item.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.entry.retry_policy = retry_policy;
+        self
+    }
+
+    /// Retries a failed item only if `predicate` returns `true` for its error.
+    ///
+    /// See [`StepBuilder::retry_if`](crate::StepBuilder::retry_if).
+    pub fn retry_if<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&WorkflowError) -> bool + Send + Sync + 'static,
+    {
+        self.entry.retry_if = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Guards every attempt of every item with a [`CircuitBreaker`].
+    pub fn circuit_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.entry.circuit_breaker = Some(breaker);
+        self
+    }
+
+    /// Sets what happens when an item fails.
+    pub fn failure_mode(mut self, failure_mode: MapFailureMode) -> Self {
+        self.failure_mode = failure_mode;
+        self
+    }
+
+    /// Continues with the given step once all items are processed.
+    ///
+    /// By default the map step completes the workflow.
+    pub fn continue_with(mut self, next: impl Into<StepName>) -> Self {
+        self.next = StepOutput::Continue(next.into());
+        self
+    }
+}
+
+impl<T, U> fmt::Debug for MapStep<T, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapStep")
+            .field("name", &self.name)
+            .field("input_key", &self.input_key)
+            .field("output_key", &self.output_key)
+            .field("child", &self.entry.step)
+            .field("concurrency", &self.concurrency)
+            .field("failure_mode", &self.failure_mode)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl<T, U> Step for MapStep<T, U>
+where
+    T: Any + Clone + Send + Sync,
+    U: Any + Send + Sync,
+{
+    async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+        let items = ctx
+            .get::<Vec<T>>(self.input_key.as_str())
+            .cloned()
+            .ok_or_else(|| WorkflowError::StepError {
+                step_name: self.name(),
+                details: format!(
+                    "Input '{}' not found or not a Vec<{}>",
+                    self.input_key,
+                    std::any::type_name::<T>()
+                ),
+            })?;
+
+        let total = items.len();
+        info!(
+            "Step '{}' mapping {} items (concurrency {})",
+            self.name, total, self.concurrency
+        );
+
+        let mut results: Vec<Option<Result<U, WorkflowError>>> = (0..total).map(|_| None).collect();
+        let mut first_error = None;
+        let run = RunScope::current().unwrap_or_else(|| RunScope::detached(self.name.clone()));
+        {
+            let run = &run;
+            // Joins even if this future is dropped mid-map, e.g. on a step timeout;
+            // the item contexts below are dropped first.
+            let (fork, item_ctxs) = ctx.fork_scoped(total);
+            let mut pending = stream::iter(items.into_iter().zip(item_ctxs).enumerate())
+                .map(|(index, (item, mut item_ctx))| async move {
+                    item_ctx.insert(Self::ITEM_KEY, item);
+                    (index, self.run_item(&mut item_ctx, run).await)
+                })
+                .buffer_unordered(self.concurrency);
+
+            while let Some((index, result)) = pending.next().await {
+                match result {
+                    Err(e) if self.failure_mode == MapFailureMode::FailFast => {
+                        warn!("Step '{}' item {} failed: {}", self.name, index, e);
+                        first_error = Some(e);
+                        break;
+                    }
+                    result => results[index] = Some(result),
+                }
+            }
+            // Dropping the remaining items releases their view of the parent context.
+            drop(pending);
+            fork.join(Vec::new());
+        }
+
+        if let Some(e) = first_error {
+            return Err(e);
+        }
+
+        let results: Vec<Result<U, WorkflowError>> = results.into_iter().flatten().collect();
+        let failed = results.iter().filter(|r| r.is_err()).count();
+        if failed > 0 {
+            warn!("Step '{}' finished with {} failed items", self.name, failed);
+        }
+        ctx.insert(self.output_key.clone(), results);
+
+        Ok(self.next.clone())
+    }
+
+    fn name(&self) -> StepName {
+        self.name.clone()
+    }
+
+    fn successors(&self) -> Option<Vec<StepOutput>> {
+        Some(vec![self.next.clone()])
+    }
+}
+
+impl<T, U: Any> MapStep<T, U> {
+    /// Runs one item with the deadline, retry budget and listeners of the
+    /// enclosing run.
+    async fn run_item(&self, item_ctx: &mut Context, run: &RunScope) -> Result<U, WorkflowError> {
+        if item_ctx.is_cancelled() {
+            return Err(WorkflowError::Cancelled {
+                step_name: self.entry.step.name(),
+            });
+        }
+        let mut log = AttemptLog::default();
+        match self
+            .entry
+            .run(
+                &run.step_name,
+                item_ctx,
+                &run.limits,
+                &mut log,
+                &run.listeners,
+            )
+            .await
+        {
+            StepResult::Success(_) => {
+                item_ctx
+                    .remove::<U>(Self::OUTPUT_KEY)
+                    .ok_or_else(|| WorkflowError::StepError {
+                        step_name: self.entry.step.name(),
+                        details: format!(
+                            "Output '{}' not found or not a {}",
+                            Self::OUTPUT_KEY,
+                            std::any::type_name::<U>()
+                        ),
+                    })
+            }
+            StepResult::Failed(errors) => {
+                Err(errors
+                    .into_iter()
+                    .next()
+                    .unwrap_or_else(|| WorkflowError::StepError {
+                        step_name: self.entry.step.name(),
+                        details: "Item failed".to_string(),
+                    }))
+            }
+        }
+    }
+}
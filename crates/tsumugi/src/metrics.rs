@@ -0,0 +1,114 @@
+//! Counters and histograms of workflow runs, recorded through the
+//! [`metrics`](::metrics) facade.
+//!
+//! Available with the `metrics` feature, for workflows built with
+//! [`WorkflowBuilder::metrics`](crate::WorkflowBuilder::metrics). Every
+//! metric carries a `workflow`
+//! label with the name set by
+//! [`WorkflowBuilder::name`](crate::WorkflowBuilder::name), empty if none
+//! was set, and step metrics also a `step` label with the step name.
+//!
+//! | Metric | Type | Counts |
+//! |--------|------|--------|
+//! | `tsumugi_workflow_executions_total` | counter | runs started |
+//! | `tsumugi_workflow_successes_total` | counter | runs that succeeded |
+//! | `tsumugi_workflow_failures_total` | counter | runs that failed |
+//! | `tsumugi_workflow_duration_seconds` | histogram | run durations |
+//! | `tsumugi_step_executions_total` | counter | steps started |
+//! | `tsumugi_step_successes_total` | counter | steps that succeeded |
+//! | `tsumugi_step_failures_total` | counter | steps that failed for good |
+//! | `tsumugi_step_timeouts_total` | counter | attempts that timed out |
+//! | `tsumugi_step_retries_total` | counter | retries scheduled |
+//! | `tsumugi_step_duration_seconds` | histogram | step durations, including retries |
+
+use crate::listener::{WorkflowEvent, WorkflowListener};
+use async_trait::async_trait;
+use std::time::Duration;
+use tsumugi_core::StepName;
+
+/// Records the metrics of a workflow from its events.
+///
+/// Registered by [`WorkflowBuilder::build`](crate::WorkflowBuilder::build)
+/// on workflows that opted in with
+/// [`WorkflowBuilder::metrics`](crate::WorkflowBuilder::metrics).
+pub(crate) struct MetricsListener {
+    workflow: String,
+}
+
+impl MetricsListener {
+    pub(crate) fn new(workflow: Option<&str>) -> Self {
+        Self {
+            workflow: workflow.unwrap_or_default().to_string(),
+        }
+    }
+
+    fn workflow_counter(&self, name: &'static str) {
+        ::metrics::counter!(name, "workflow" => self.workflow.clone()).increment(1);
+    }
+
+    fn step_counter(&self, name: &'static str, step_name: &StepName) {
+        ::metrics::counter!(
+            name,
+            "workflow" => self.workflow.clone(),
+            "step" => step_name.to_string(),
+        )
+        .increment(1);
+    }
+
+    fn step_duration(&self, step_name: &StepName, duration: Duration) {
+        ::metrics::histogram!(
+            "tsumugi_step_duration_seconds",
+            "workflow" => self.workflow.clone(),
+            "step" => step_name.to_string(),
+        )
+        .record(duration.as_secs_f64());
+    }
+}
+
+#[async_trait]
+impl WorkflowListener for MetricsListener {
+    async fn on_event(&self, event: &WorkflowEvent) {
+        match event {
+            WorkflowEvent::WorkflowStarted { .. } => {
+                self.workflow_counter("tsumugi_workflow_executions_total");
+            }
+            WorkflowEvent::WorkflowFinished { duration, outcome } => {
+                self.workflow_counter(match outcome {
+                    Ok(()) => "tsumugi_workflow_successes_total",
+                    Err(_) => "tsumugi_workflow_failures_total",
+                });
+                ::metrics::histogram!(
+                    "tsumugi_workflow_duration_seconds",
+                    "workflow" => self.workflow.clone(),
+                )
+                .record(duration.as_secs_f64());
+            }
+            WorkflowEvent::StepStarted { step_name } => {
+                self.step_counter("tsumugi_step_executions_total", step_name);
+            }
+            WorkflowEvent::StepTimedOut { step_name, .. } => {
+                self.step_counter("tsumugi_step_timeouts_total", step_name);
+            }
+            WorkflowEvent::RetryScheduled { step_name, .. } => {
+                self.step_counter("tsumugi_step_retries_total", step_name);
+            }
+            WorkflowEvent::StepCompleted {
+                step_name,
+                duration,
+                ..
+            } => {
+                self.step_counter("tsumugi_step_successes_total", step_name);
+                self.step_duration(step_name, *duration);
+            }
+            WorkflowEvent::StepFailed {
+                step_name,
+                duration,
+                ..
+            } => {
+                self.step_counter("tsumugi_step_failures_total", step_name);
+                self.step_duration(step_name, *duration);
+            }
+            WorkflowEvent::AttemptFailed { .. } | WorkflowEvent::HookFailed { .. } => {}
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! Parallel fan-out / fan-in step groups.
+
+use tsumugi_core::{StepName, WorkflowError};
+
+/// How many branches of a [`ParallelGroup`] must succeed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JoinPolicy {
+    /// Every branch must succeed.
+    #[default]
+    All,
+    /// One successful branch is enough.
+    Any,
+    /// At least the given number of branches must succeed.
+    AtLeast(usize),
+}
+
+impl JoinPolicy {
+    /// Returns the number of successful branches required out of `branches`.
+    pub fn required(&self, branches: usize) -> usize {
+        match self {
+            JoinPolicy::All => branches,
+            JoinPolicy::Any => 1,
+            JoinPolicy::AtLeast(n) => *n,
+        }
+    }
+}
+
+/// A group of branches that run concurrently and join into a single step.
+///
+/// Register a group with [`WorkflowBuilder::add_parallel`](crate::WorkflowBuilder::add_parallel)
+/// and transition to it like any other step. Each branch starts at the named
+/// step and follows its [`StepOutput`](crate::StepOutput)s until it completes.
+///
+/// Branches run against contexts forked from the parent [`Context`](crate::Context):
+/// they can read every parent entry, and the entries they insert are merged
+/// back in branch order once the group finishes. As soon as the
+/// [`JoinPolicy`] is satisfied (or can no longer be satisfied), the remaining
+/// branches are dropped and the workflow continues with the join step.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// let workflow = Workflow::builder()
+///     .add_step("check_api", CheckApi)
+///     .add_step("check_db", CheckDb)
+///     .add_step("aggregate", Aggregate)
+///     .add_parallel(
+///         "checks",
+///         ParallelGroup::new(["check_api", "check_db"], "aggregate").policy(JoinPolicy::Any),
+///     )
+///     .start_with("checks")
+///     .build()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct ParallelGroup {
+    pub(crate) branches: Vec<StepName>,
+    pub(crate) join: StepName,
+    pub(crate) policy: JoinPolicy,
+}
+
+impl ParallelGroup {
+    /// Creates a group running `branches` concurrently, then continuing with `join`.
+    pub fn new<I, N>(branches: I, join: impl Into<StepName>) -> Self
+    where
+        I: IntoIterator<Item = N>,
+        N: Into<StepName>,
+    {
+        Self {
+            branches: branches.into_iter().map(Into::into).collect(),
+            join: join.into(),
+            policy: JoinPolicy::All,
+        }
+    }
+
+    /// Sets the join policy. Defaults to [`JoinPolicy::All`].
+    pub fn policy(mut self, policy: JoinPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Returns the start steps of the branches.
+    pub fn branches(&self) -> &[StepName] {
+        &self.branches
+    }
+
+    /// Returns the step that runs after the branches have joined.
+    pub fn join_step(&self) -> &StepName {
+        &self.join
+    }
+
+    /// Returns the join policy.
+    pub fn join_policy(&self) -> JoinPolicy {
+        self.policy
+    }
+
+    pub(crate) fn validate(&self, name: &StepName) -> Result<(), WorkflowError> {
+        if self.branches.is_empty() {
+            return Err(WorkflowError::Configuration(format!(
+                "Parallel group '{}' must have at least one branch",
+                name
+            )));
+        }
+        let required = self.policy.required(self.branches.len());
+        if required == 0 || required > self.branches.len() {
+            return Err(WorkflowError::Configuration(format!(
+                "Parallel group '{}' requires {} successful branches but has {}",
+                name,
+                required,
+                self.branches.len()
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join_policy_required() {
+        assert_eq!(JoinPolicy::All.required(3), 3);
+        assert_eq!(JoinPolicy::Any.required(3), 1);
+        assert_eq!(JoinPolicy::AtLeast(2).required(3), 2);
+    }
+
+    #[test]
+    fn test_group_validation() {
+        let name = StepName::new("group");
+        assert!(ParallelGroup::new(["a", "b"], "join")
+            .validate(&name)
+            .is_ok());
+        assert!(ParallelGroup::new(Vec::<&str>::new(), "join")
+            .validate(&name)
+            .is_err());
+        assert!(ParallelGroup::new(["a", "b"], "join")
+            .policy(JoinPolicy::AtLeast(3))
+            .validate(&name)
+            .is_err());
+        assert!(ParallelGroup::new(["a"], "join")
+            .policy(JoinPolicy::AtLeast(0))
+            .validate(&name)
+            .is_err());
+    }
+}
@@ -0,0 +1,315 @@
+//! Structured reports of workflow runs.
+
+use std::fmt;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+use tsumugi_core::{AttemptFailure, StepName, WorkflowError};
+
+/// A summary of a single workflow run, returned by [`Workflow::run`](crate::Workflow::run).
+///
+/// Produced on success and on failure. Steps are listed in the order they
+/// started; steps of parallel branches are interleaved in that order.
+#[derive(Debug, Clone)]
+pub struct ExecutionReport {
+    run_id: String,
+    steps: Vec<StepReport>,
+    started_at: SystemTime,
+    elapsed: Duration,
+    outcome: Result<(), Vec<WorkflowError>>,
+    compensations: Vec<CompensationReport>,
+}
+
+impl ExecutionReport {
+    pub(crate) fn new(
+        run_id: String,
+        steps: Vec<StepReport>,
+        started_at: SystemTime,
+        elapsed: Duration,
+        outcome: Result<(), Vec<WorkflowError>>,
+        compensations: Vec<CompensationReport>,
+    ) -> Self {
+        Self {
+            run_id,
+            steps,
+            started_at,
+            elapsed,
+            outcome,
+            compensations,
+        }
+    }
+
+    /// Creates a report for a run that ended before any step started.
+    #[cfg(feature = "checkpoint")]
+    pub(crate) fn not_run(run_id: &str, outcome: Result<(), Vec<WorkflowError>>) -> Self {
+        Self::new(
+            run_id.to_string(),
+            Vec::new(),
+            SystemTime::now(),
+            Duration::ZERO,
+            outcome,
+            Vec::new(),
+        )
+    }
+
+    /// Returns the id of the run, as recorded on its tracing span.
+    ///
+    /// Set with [`ExecuteOptions::run_id`](crate::ExecuteOptions::run_id),
+    /// or generated for each run otherwise.
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// Returns the reports of all steps that started, in start order.
+    pub fn steps(&self) -> &[StepReport] {
+        &self.steps
+    }
+
+    /// Returns the names of the steps that started, in start order.
+    pub fn path(&self) -> impl Iterator<Item = &StepName> {
+        self.steps.iter().map(StepReport::name)
+    }
+
+    /// Returns the report of the first run of the given step, if it started.
+    pub fn step(&self, name: &str) -> Option<&StepReport> {
+        self.steps.iter().find(|step| step.name.as_str() == name)
+    }
+
+    /// Returns the wall-clock time the run started.
+    pub fn started_at(&self) -> SystemTime {
+        self.started_at
+    }
+
+    /// Returns the total duration of the run.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Returns `true` if the workflow completed successfully.
+    pub fn is_success(&self) -> bool {
+        self.outcome.is_ok()
+    }
+
+    /// Returns the errors that failed the run, or an empty slice on success.
+    pub fn errors(&self) -> &[WorkflowError] {
+        match &self.outcome {
+            Ok(()) => &[],
+            Err(errors) => errors,
+        }
+    }
+
+    /// Returns the compensations run after the workflow failed, in the order
+    /// they ran.
+    ///
+    /// Empty if the run succeeded or no completed step was
+    /// [compensable](tsumugi_core::Compensable).
+    pub fn compensations(&self) -> &[CompensationReport] {
+        &self.compensations
+    }
+
+    /// Returns the final outcome of the run.
+    pub fn outcome(&self) -> &Result<(), Vec<WorkflowError>> {
+        &self.outcome
+    }
+
+    /// Converts the report into the result returned by [`Workflow::execute`](crate::Workflow::execute).
+    pub fn into_result(self) -> Result<(), Vec<WorkflowError>> {
+        self.outcome
+    }
+}
+
+impl fmt::Display for ExecutionReport {
+    /// Formats a one-line summary, e.g.
+    /// `succeeded in 12ms: fetch (2 attempts, 8ms) -> store (1 attempt, 3ms)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = if self.is_success() {
+            "succeeded"
+        } else {
+            "failed"
+        };
+        write!(f, "{} in {:?}", status, self.elapsed)?;
+        for (index, step) in self.steps.iter().enumerate() {
+            let separator = if index == 0 { ": " } else { " -> " };
+            write!(f, "{}{}", separator, step)?;
+        }
+        for (index, compensation) in self.compensations.iter().enumerate() {
+            let separator = if index == 0 { "; compensated: " } else { ", " };
+            write!(f, "{}{}", separator, compensation)?;
+        }
+        Ok(())
+    }
+}
+
+/// The record of one step within an [`ExecutionReport`].
+#[derive(Debug, Clone)]
+pub struct StepReport {
+    name: StepName,
+    started_at: SystemTime,
+    finished_at: SystemTime,
+    attempts: u32,
+    failures: Vec<AttemptFailure>,
+    succeeded: bool,
+    next_step: Option<StepName>,
+}
+
+impl StepReport {
+    /// Returns the name the step is registered under.
+    pub fn name(&self) -> &StepName {
+        &self.name
+    }
+
+    /// Returns the wall-clock time the step started.
+    pub fn started_at(&self) -> SystemTime {
+        self.started_at
+    }
+
+    /// Returns the wall-clock time the step finished.
+    pub fn finished_at(&self) -> SystemTime {
+        self.finished_at
+    }
+
+    /// Returns how long the step ran, including retry delays.
+    pub fn duration(&self) -> Duration {
+        self.finished_at
+            .duration_since(self.started_at)
+            .unwrap_or_default()
+    }
+
+    /// Returns how many times the step was attempted.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Returns each failed attempt, in order.
+    pub fn failures(&self) -> &[AttemptFailure] {
+        &self.failures
+    }
+
+    /// Returns `true` if the step succeeded.
+    pub fn is_success(&self) -> bool {
+        self.succeeded
+    }
+
+    /// Returns the step the run continued with, or `None` if the step
+    /// failed or its path completed.
+    pub fn next_step(&self) -> Option<&StepName> {
+        self.next_step.as_ref()
+    }
+}
+
+impl fmt::Display for StepReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = if self.attempts == 1 { "" } else { "s" };
+        write!(
+            f,
+            "{} ({} attempt{}, {:?}",
+            self.name,
+            self.attempts,
+            plural,
+            self.duration()
+        )?;
+        if !self.succeeded {
+            write!(f, ", failed")?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// The record of one compensation within an [`ExecutionReport`].
+#[derive(Debug, Clone)]
+pub struct CompensationReport {
+    name: StepName,
+    duration: Duration,
+    error: Option<WorkflowError>,
+}
+
+impl CompensationReport {
+    pub(crate) fn new(name: StepName, duration: Duration, error: Option<WorkflowError>) -> Self {
+        Self {
+            name,
+            duration,
+            error,
+        }
+    }
+
+    /// Returns the name of the compensated step.
+    pub fn name(&self) -> &StepName {
+        &self.name
+    }
+
+    /// Returns how long the compensation ran.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Returns the error the compensation failed with, if it failed.
+    pub fn error(&self) -> Option<&WorkflowError> {
+        self.error.as_ref()
+    }
+
+    /// Returns `true` if the compensation succeeded.
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+impl fmt::Display for CompensationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({:?}", self.name, self.duration)?;
+        if self.error.is_some() {
+            write!(f, ", failed")?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Collects step reports while a workflow runs.
+///
+/// Parallel branches share one recorder, so slots are reserved when a step
+/// starts to keep the report in start order.
+#[derive(Debug, Default)]
+pub(crate) struct Recorder {
+    steps: Mutex<Vec<StepReport>>,
+}
+
+impl Recorder {
+    /// Records the start of a step and returns its slot.
+    pub(crate) fn start(&self, name: &StepName) -> usize {
+        let now = SystemTime::now();
+        let mut steps = self.steps.lock().unwrap_or_else(PoisonError::into_inner);
+        steps.push(StepReport {
+            name: name.clone(),
+            started_at: now,
+            finished_at: now,
+            attempts: 0,
+            failures: Vec::new(),
+            succeeded: false,
+            next_step: None,
+        });
+        steps.len() - 1
+    }
+
+    /// Records the end of the step in `slot`.
+    pub(crate) fn finish(
+        &self,
+        slot: usize,
+        attempts: u32,
+        failures: Vec<AttemptFailure>,
+        succeeded: bool,
+        next_step: Option<StepName>,
+    ) {
+        let mut steps = self.steps.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(step) = steps.get_mut(slot) {
+            step.finished_at = SystemTime::now();
+            step.attempts = attempts;
+            step.failures = failures;
+            step.succeeded = succeeded;
+            step.next_step = next_step;
+        }
+    }
+
+    pub(crate) fn into_steps(self) -> Vec<StepReport> {
+        self.steps
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -0,0 +1,209 @@
+//! Running a workflow as a step of another workflow.
+
+use crate::workflow::Workflow;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tracing::warn;
+use tsumugi_core::{Context, ContextKey, Step, StepName, StepOutput, WorkflowError};
+
+/// A step that runs a nested [`Workflow`].
+///
+/// By default the nested workflow runs against the parent [`Context`], so it
+/// sees and modifies the same entries. With [`isolated`](Self::isolated) it
+/// runs against a fresh context instead: entries listed with
+/// [`input`](Self::input) are moved into it (and moved back afterwards), and
+/// entries listed with [`output`](Self::output) are moved out of it once the
+/// nested workflow succeeds.
+///
+/// Errors from the nested workflow are wrapped in
+/// [`WorkflowError::SubWorkflow`], whose message shows the path from this
+/// step down to the failing child step.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// let billing = Arc::new(
+///     Workflow::builder()
+///         .add_step("charge", ChargeStep)
+///         .start_with("charge")
+///         .build()?,
+/// );
+///
+/// let workflow = Workflow::builder()
+///     .add_step("validate", ValidateStep)
+///     .add_step(
+///         "billing",
+///         SubWorkflowStep::new("Billing", billing)
+///             .isolated()
+///             .input("order", "order")
+///             .output("receipt", "billing_receipt")
+///             .continue_with("ship"),
+///     )
+///     .add_step("ship", ShipStep)
+///     .start_with("validate")
+///     .build()?;
+/// ```
+#[derive(Debug)]
+pub struct SubWorkflowStep {
+    name: StepName,
+    workflow: Arc<Workflow>,
+    isolated: bool,
+    inputs: Vec<(ContextKey, ContextKey)>,
+    outputs: Vec<(ContextKey, ContextKey)>,
+    next: StepOutput,
+}
+
+impl SubWorkflowStep {
+    /// Creates a step running `workflow` against the parent context.
+    ///
+    /// Pass an `Arc<Workflow>` to reuse the same workflow in several places.
+    pub fn new(name: impl Into<StepName>, workflow: impl Into<Arc<Workflow>>) -> Self {
+        Self {
+            name: name.into(),
+            workflow: workflow.into(),
+            isolated: false,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            next: StepOutput::Complete,
+        }
+    }
+
+    /// Runs the nested workflow against its own context.
+    pub fn isolated(mut self) -> Self {
+        self.isolated = true;
+        self
+    }
+
+    /// Moves the parent entry `parent_key` into the isolated context as `child_key`.
+    ///
+    /// The entry is moved back once the nested workflow finishes or is
+    /// abandoned, e.g. on a timeout, unless it was removed or is mapped as an
+    /// output.
+    pub fn input(
+        mut self,
+        parent_key: impl Into<ContextKey>,
+        child_key: impl Into<ContextKey>,
+    ) -> Self {
+        self.inputs.push((parent_key.into(), child_key.into()));
+        self
+    }
+
+    /// Moves the entry `child_key` of the isolated context into the parent as `parent_key`.
+    pub fn output(
+        mut self,
+        child_key: impl Into<ContextKey>,
+        parent_key: impl Into<ContextKey>,
+    ) -> Self {
+        self.outputs.push((child_key.into(), parent_key.into()));
+        self
+    }
+
+    /// Continues with the given step once the nested workflow succeeds.
+    ///
+    /// By default the step completes the parent workflow.
+    pub fn continue_with(mut self, next: impl Into<StepName>) -> Self {
+        self.next = StepOutput::Continue(next.into());
+        self
+    }
+
+    async fn execute_isolated(&self, ctx: &mut Context) -> Result<(), WorkflowError> {
+        let mut child = Context::new();
+        child.set_cancellation_token(ctx.cancellation_token().clone());
+        let mut run = IsolatedRun {
+            parent: ctx,
+            child,
+            inputs: &[],
+        };
+
+        for (index, (parent_key, child_key)) in self.inputs.iter().enumerate() {
+            if !run
+                .parent
+                .transfer(parent_key.as_str(), &mut run.child, child_key.clone())
+            {
+                return Err(WorkflowError::StepError {
+                    step_name: self.name(),
+                    details: format!("Input '{}' not found", parent_key),
+                });
+            }
+            run.inputs = &self.inputs[..=index];
+        }
+
+        let result = self.workflow.execute(&mut run.child).await;
+
+        let mut missing = Vec::new();
+        if result.is_ok() {
+            for (child_key, parent_key) in &self.outputs {
+                if !run
+                    .child
+                    .transfer(child_key.as_str(), run.parent, parent_key.clone())
+                {
+                    missing.push(child_key.as_str());
+                }
+            }
+        }
+        drop(run);
+
+        result.map_err(|errors| self.wrap(errors))?;
+        if !missing.is_empty() {
+            return Err(WorkflowError::StepError {
+                step_name: self.name(),
+                details: format!("Outputs not produced: {}", missing.join(", ")),
+            });
+        }
+        Ok(())
+    }
+
+    fn wrap(&self, errors: Vec<WorkflowError>) -> WorkflowError {
+        warn!(
+            "Sub-workflow step '{}' failed with {} errors",
+            self.name,
+            errors.len()
+        );
+        WorkflowError::SubWorkflow {
+            step_name: self.name(),
+            errors,
+        }
+    }
+}
+
+/// The context of an isolated run.
+///
+/// Moves the inputs moved so far back into the parent when dropped, so they
+/// survive a nested workflow that fails or is abandoned, e.g. on a timeout.
+struct IsolatedRun<'a> {
+    parent: &'a mut Context,
+    child: Context,
+    inputs: &'a [(ContextKey, ContextKey)],
+}
+
+impl Drop for IsolatedRun<'_> {
+    fn drop(&mut self) {
+        for (parent_key, child_key) in self.inputs {
+            self.child
+                .transfer(child_key.as_str(), self.parent, parent_key.clone());
+        }
+    }
+}
+
+#[async_trait]
+impl Step for SubWorkflowStep {
+    async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+        if self.isolated {
+            self.execute_isolated(ctx).await?;
+        } else {
+            self.workflow
+                .execute(ctx)
+                .await
+                .map_err(|errors| self.wrap(errors))?;
+        }
+        Ok(self.next.clone())
+    }
+
+    fn name(&self) -> StepName {
+        self.name.clone()
+    }
+
+    fn successors(&self) -> Option<Vec<StepOutput>> {
+        Some(vec![self.next.clone()])
+    }
+}
@@ -1,41 +1,162 @@
 //! Workflow engine for executing steps.
 
-use std::collections::HashMap;
+#[cfg(feature = "checkpoint")]
+use crate::checkpoint::{CheckpointStore, Checkpointer};
+use crate::circuit::CircuitBreaker;
+use crate::compensation::CompensationLog;
+use crate::diagram::{Diagram, DiagramNode};
+use crate::entry::{
+    AttemptLog, RetryPredicate, RunLimits, RunScope, StepEntry, StepResult, DEFAULT_TIMEOUT,
+};
+use crate::graph::{self, NodeEdges};
+use crate::listener::{Listeners, WorkflowEvent, WorkflowListener};
+use crate::parallel::ParallelGroup;
+use crate::report::{ExecutionReport, Recorder};
+use futures_util::future::BoxFuture;
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, VecDeque};
 use std::fmt;
-use std::time::Duration;
-use tokio::time::timeout;
-use tracing::{info, warn};
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{field, info, info_span, warn, Instrument, Span};
 use tsumugi_core::{
-    Context, Retryable, Step, StepConfig, StepName, StepOutput, WithTimeout, WorkflowError,
+    Compensable, Context, KeySpec, RetryPolicy, Retryable, Step, StepConfig, StepName, StepOutput,
+    ValidationIssue, WithHooks, WithTimeout, WorkflowError,
 };
 
+/// Default maximum number of step transitions along one execution path.
+pub const DEFAULT_MAX_TRANSITIONS: usize = 10_000;
+
+/// Number of recently visited steps reported when a loop limit is exceeded.
+const RECENT_STEPS: usize = 10;
+
+/// Without the `checkpoint` feature no run is ever checkpointed.
+#[cfg(not(feature = "checkpoint"))]
+type Checkpointer<'a> = std::marker::PhantomData<&'a ()>;
+
 /// A workflow engine that executes a series of steps.
 pub struct Workflow {
-    steps: HashMap<StepName, StepEntry>,
+    name: Option<String>,
+    steps: HashMap<StepName, Node>,
     start_step: StepName,
+    deadline: Option<Duration>,
+    retry_budget: Option<u32>,
+    max_transitions: usize,
+    visit_limits: HashMap<StepName, usize>,
+    edges: HashMap<StepName, Vec<StepOutput>>,
+    verify_outputs: bool,
+    listeners: Listeners,
+    #[cfg(feature = "checkpoint")]
+    checkpoint_schema: tsumugi_core::ContextSchema,
 }
 
-struct StepEntry {
-    step: Box<dyn Step>,
-    timeout: Duration,
-    retry_policy: tsumugi_core::RetryPolicy,
+enum Node {
+    Step(StepEntry),
+    Parallel(ParallelGroup),
+}
+
+impl Node {
+    /// Combines the node's own successors with edges declared on the builder.
+    fn edges(&self, declared: Option<&Vec<StepOutput>>) -> NodeEdges {
+        let (own, branches, all_branches) = match self {
+            Node::Step(entry) => (entry.step.successors(), Vec::new(), false),
+            Node::Parallel(group) => (
+                Some(vec![StepOutput::Continue(group.join.clone())]),
+                group.branches.clone(),
+                group.policy.required(group.branches.len()) == group.branches.len(),
+            ),
+        };
+        let (required, produced) = match self {
+            Node::Step(entry) => (entry.required.clone(), entry.produced.clone()),
+            Node::Parallel(_) => (Vec::new(), Vec::new()),
+        };
+        let successors = match (own, declared) {
+            (Some(mut own), Some(declared)) => {
+                for output in declared {
+                    if !own.contains(output) {
+                        own.push(output.clone());
+                    }
+                }
+                Some(own)
+            }
+            (own, declared) => own.or_else(|| declared.cloned()),
+        };
+        NodeEdges {
+            successors,
+            branches,
+            all_branches,
+            required,
+            produced,
+        }
+    }
 }
 
 impl fmt::Debug for Workflow {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Workflow")
+            .field("name", &self.name)
             .field("steps", &self.steps.keys().collect::<Vec<_>>())
             .field("start_step", &self.start_step)
+            .field("deadline", &self.deadline)
+            .field("retry_budget", &self.retry_budget)
+            .field("max_transitions", &self.max_transitions)
+            .field("visit_limits", &self.visit_limits)
             .finish()
     }
 }
 
+/// Per-call overrides for [`Workflow::execute_with`].
+///
+/// Unset options fall back to the values configured on the [`WorkflowBuilder`].
+#[derive(Debug, Clone, Default)]
+pub struct ExecuteOptions {
+    deadline: Option<Duration>,
+    retry_budget: Option<u32>,
+    run_id: Option<String>,
+}
+
+impl ExecuteOptions {
+    /// Creates options that use the workflow's own settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Overrides the workflow deadline for this run.
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Overrides the workflow retry budget for this run.
+    pub fn retry_budget(mut self, retries: u32) -> Self {
+        self.retry_budget = Some(retries);
+        self
+    }
+
+    /// Sets the id recorded on the run's tracing span and report.
+    ///
+    /// Use this to correlate a run with ids from elsewhere, such as a
+    /// request id. Defaults to a random id.
+    pub fn run_id(mut self, run_id: impl Into<String>) -> Self {
+        self.run_id = Some(run_id.into());
+        self
+    }
+}
+
 impl Workflow {
     /// Creates a new workflow builder.
     pub fn builder() -> WorkflowBuilder {
         WorkflowBuilder::new()
     }
 
+    /// Returns the name of the workflow, if one was set.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
     /// Returns the name of the start step.
     pub fn start_step(&self) -> &StepName {
         &self.start_step
@@ -56,211 +177,802 @@ impl Workflow {
         self.steps.len()
     }
 
+    /// Returns the deadline applied to each run, if any.
+    pub fn deadline(&self) -> Option<Duration> {
+        self.deadline
+    }
+
+    /// Returns the maximum number of retries allowed across a run, if limited.
+    pub fn retry_budget(&self) -> Option<u32> {
+        self.retry_budget
+    }
+
+    /// Returns the maximum number of step transitions along one execution path.
+    pub fn max_transitions(&self) -> usize {
+        self.max_transitions
+    }
+
+    /// Returns the declared successors of a step, if known.
+    ///
+    /// Combines [`Step::successors`] with edges declared on the builder.
+    pub fn successors(&self, name: &str) -> Option<Vec<StepOutput>> {
+        self.steps
+            .get_key_value(name)
+            .and_then(|(name, node)| node.edges(self.edges.get(name)).successors)
+    }
+
+    /// Returns `true` if declared step outputs are checked after every step.
+    ///
+    /// See [`WorkflowBuilder::verify_outputs`].
+    pub fn verify_outputs(&self) -> bool {
+        self.verify_outputs
+    }
+
+    /// Returns the circuit breaker with the given name, if a step uses it.
+    ///
+    /// Use [`CircuitBreaker::state`] to inspect it.
+    pub fn circuit_breaker(&self, name: &str) -> Option<&CircuitBreaker> {
+        self.steps.values().find_map(|node| match node {
+            Node::Step(entry) => entry
+                .circuit_breaker
+                .as_ref()
+                .filter(|breaker| breaker.name() == name),
+            Node::Parallel(_) => None,
+        })
+    }
+
+    /// Returns the visit limit of the given step, if one is set.
+    pub fn max_visits(&self, name: &str) -> Option<usize> {
+        self.visit_limits.get(name).copied()
+    }
+
+    /// Renders the step graph in Graphviz DOT format.
+    ///
+    /// Edges are the transitions declared with [`Step::successors`] or
+    /// [`WorkflowBuilder::edge`], and the branches of parallel groups
+    /// (dashed). The start step is drawn bold behind an entry point, and
+    /// each step is annotated with its timeout and retry policy.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tsumugi::prelude::*;
+    /// # #[derive(Debug)]
+    /// # struct Fetch;
+    /// # #[async_trait::async_trait]
+    /// # impl Step for Fetch {
+    /// #     async fn execute(&self, _ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+    /// #         Ok(StepOutput::done())
+    /// #     }
+    /// #     fn name(&self) -> StepName {
+    /// #         StepName::new("Fetch")
+    /// #     }
+    /// # }
+    /// let workflow = Workflow::builder()
+    ///     .add_step("fetch", Fetch)
+    ///     .terminal("fetch")
+    ///     .start_with("fetch")
+    ///     .build()?;
+    ///
+    /// let dot = workflow.to_dot();
+    /// assert!(dot.contains("\"fetch\" [label=\"fetch\\ntimeout 30s\", penwidth=2];"));
+    /// # Ok::<(), WorkflowError>(())
+    /// ```
+    pub fn to_dot(&self) -> String {
+        self.diagram(None).to_dot()
+    }
+
+    /// Renders the step graph like [`to_dot`](Self::to_dot), overlaid with
+    /// a run.
+    ///
+    /// Steps that succeeded are filled green and steps that failed red, and
+    /// the transitions taken are drawn bold green. Transitions the run took
+    /// that were not declared are added, so a graph of undeclared steps can
+    /// be drawn from observed runs.
+    pub fn to_dot_with_run(&self, report: &ExecutionReport) -> String {
+        self.diagram(Some(report)).to_dot()
+    }
+
+    /// Renders the step graph as a Mermaid flowchart.
+    ///
+    /// Shows the same graph as [`to_dot`](Self::to_dot), for embedding in
+    /// Markdown.
+    pub fn to_mermaid(&self) -> String {
+        self.diagram(None).to_mermaid()
+    }
+
+    /// Renders the step graph as a Mermaid flowchart overlaid with a run,
+    /// like [`to_dot_with_run`](Self::to_dot_with_run).
+    pub fn to_mermaid_with_run(&self, report: &ExecutionReport) -> String {
+        self.diagram(Some(report)).to_mermaid()
+    }
+
+    fn diagram(&self, run: Option<&ExecutionReport>) -> Diagram {
+        let nodes = self
+            .steps
+            .iter()
+            .map(|(name, node)| {
+                let edges = node.edges(self.edges.get(name));
+                match node {
+                    Node::Step(entry) => {
+                        DiagramNode::step(name.clone(), edges, entry.timeout, &entry.retry_policy)
+                    }
+                    Node::Parallel(_) => DiagramNode::parallel(name.clone(), edges),
+                }
+            })
+            .collect();
+        Diagram::new(self.name.as_deref(), &self.start_step, nodes, run)
+    }
+
     /// Executes the workflow starting from the configured start step.
+    ///
+    /// Execution stops before the next step once the context's
+    /// [`CancellationToken`](tsumugi_core::CancellationToken) is cancelled;
+    /// the running step is not interrupted, but retries are abandoned and
+    /// [`WorkflowError::Cancelled`] is returned.
+    ///
+    /// The workflow [deadline](WorkflowBuilder::deadline) and
+    /// [retry budget](WorkflowBuilder::retry_budget) apply if configured.
+    /// Runaway loops are stopped by the
+    /// [transition limit](WorkflowBuilder::max_transitions) and
+    /// [visit limits](WorkflowBuilder::max_visits).
+    ///
+    /// If the run fails, every [compensable](StepBuilder::compensable) step
+    /// that completed is compensated in reverse order, and failed
+    /// compensations are added to the returned errors.
+    ///
+    /// Each run is recorded as a `workflow_run` tracing span carrying the
+    /// workflow name and run id, with a `step` span per step and an
+    /// `attempt` span per attempt nested inside; every span records its
+    /// `outcome` when it ends.
+    ///
+    /// Use [`run`](Self::run) to also get an [`ExecutionReport`].
     pub async fn execute(&self, ctx: &mut Context) -> Result<(), Vec<WorkflowError>> {
-        let mut current_step = Some(self.start_step.clone());
-        let mut errors = Vec::new();
+        self.execute_with(ctx, ExecuteOptions::new()).await
+    }
 
-        while let Some(step_name) = current_step {
-            let entry = match self.steps.get(&step_name) {
-                Some(s) => s,
-                None => {
-                    errors.push(WorkflowError::StepNotFound(step_name));
-                    break;
-                }
-            };
+    /// Executes the workflow with per-call [`ExecuteOptions`].
+    ///
+    /// The deadline bounds every attempt and retry sleep of every step; when
+    /// it passes, the running step fails with
+    /// [`WorkflowError::DeadlineExceeded`]. Once the retry budget is used up,
+    /// a failing step is not retried and
+    /// [`WorkflowError::RetryBudgetExhausted`] is reported after its error.
+    pub async fn execute_with(
+        &self,
+        ctx: &mut Context,
+        options: ExecuteOptions,
+    ) -> Result<(), Vec<WorkflowError>> {
+        self.run_with(ctx, options).await.into_result()
+    }
+
+    /// Executes the workflow like [`execute`](Self::execute) and returns an
+    /// [`ExecutionReport`] describing the run, whether it succeeded or not.
+    pub async fn run(&self, ctx: &mut Context) -> ExecutionReport {
+        self.run_with(ctx, ExecuteOptions::new()).await
+    }
 
-            match self.execute_step_with_retry(entry, ctx).await {
-                StepResult::Success(next) => {
-                    current_step = next;
+    /// Executes the workflow with per-call [`ExecuteOptions`] and returns an
+    /// [`ExecutionReport`].
+    pub async fn run_with(&self, ctx: &mut Context, options: ExecuteOptions) -> ExecutionReport {
+        let start = self.start_step.clone();
+        self.run_root(ctx, options, start, CompensationLog::default(), None)
+            .await
+    }
+
+    /// Resumes the run `run_id` from its last checkpoint in `store`.
+    ///
+    /// Restores the [checkpointed keys](WorkflowBuilder::checkpoint_key) into
+    /// `ctx` and continues with the step after the last one that completed.
+    /// Without a checkpoint the run starts from the start step, so the same
+    /// call both starts and resumes a run. A run that already completed is
+    /// not run again.
+    ///
+    /// A checkpoint is saved after every successful step. Steps inside
+    /// parallel branches are not checkpointed individually; a parallel
+    /// group is checkpointed once its branches have joined. Failing to save
+    /// a checkpoint fails the run with [`WorkflowError::Checkpoint`].
+    ///
+    /// The checkpoint also records the [compensable](StepBuilder::compensable)
+    /// steps completed so far, so a resumed run that fails compensates the
+    /// steps completed before the resume too; their compensations see the
+    /// context as restored from the checkpoint. A failed run that
+    /// compensated any step deletes its checkpoint, so the next call starts
+    /// the run over.
+    ///
+    /// Requires the `checkpoint` feature.
+    ///
+    /// ```rust,ignore
+    /// let store = FileCheckpointStore::new("/var/lib/etl/checkpoints");
+    /// let workflow = Workflow::builder()
+    ///     .add_step("extract", ExtractStep)
+    ///     .add_step("transform", TransformStep)
+    ///     .add_step("load", LoadStep)
+    ///     .checkpoint_key::<Vec<Row>>("rows")
+    ///     .start_with("extract")
+    ///     .build()?;
+    ///
+    /// // After a crash, the same call continues where the run stopped.
+    /// let report = workflow.resume("nightly-2024-06-01", &store, &mut ctx).await;
+    /// ```
+    #[cfg(feature = "checkpoint")]
+    pub async fn resume(
+        &self,
+        run_id: &str,
+        store: &dyn CheckpointStore,
+        ctx: &mut Context,
+    ) -> ExecutionReport {
+        let checkpointer = Checkpointer {
+            run_id,
+            store,
+            schema: &self.checkpoint_schema,
+        };
+        let checkpoint = match store.load(run_id).await {
+            Ok(checkpoint) => checkpoint,
+            Err(e) => return ExecutionReport::not_run(run_id, Err(vec![e])),
+        };
+        let compensations = CompensationLog::default();
+        let start = match checkpoint {
+            None => self.start_step.clone(),
+            Some(checkpoint) => {
+                if let Err(e) = checkpointer.restore(ctx, &checkpoint) {
+                    return ExecutionReport::not_run(run_id, Err(vec![e]));
                 }
-                StepResult::Failed(step_errors) => {
-                    errors.extend(step_errors);
-                    current_step = None;
+                let Some(next) = checkpoint.next_step() else {
+                    info!("Run '{}' already completed", run_id);
+                    return ExecutionReport::not_run(run_id, Ok(()));
+                };
+                info!(
+                    "Resuming run '{}' after step '{}'",
+                    run_id,
+                    checkpoint.last_step()
+                );
+                // Steps that are no longer compensable have nothing to undo.
+                for name in checkpoint.compensable() {
+                    if let Some(Node::Step(entry)) = self.steps.get(name) {
+                        if let Some(step) = &entry.compensation {
+                            compensations.push(name, step.clone());
+                        }
+                    }
                 }
+                next.clone()
             }
+        };
+        let options = ExecuteOptions::new().run_id(run_id);
+        self.run_root(ctx, options, start, compensations, Some(&checkpointer))
+            .await
+    }
+
+    /// Runs the root path from `start`, compensating if it fails.
+    ///
+    /// `compensations` holds the compensable steps completed before `start`.
+    async fn run_root(
+        &self,
+        ctx: &mut Context,
+        options: ExecuteOptions,
+        start: StepName,
+        compensations: CompensationLog,
+        checkpoint: Option<&Checkpointer<'_>>,
+    ) -> ExecutionReport {
+        let run_id = options.run_id.clone().unwrap_or_else(new_run_id);
+        let span = info_span!(
+            "workflow_run",
+            workflow = field::Empty,
+            run_id = %run_id,
+            outcome = field::Empty,
+        );
+        if let Some(name) = &self.name {
+            span.record("workflow", name.as_str());
         }
+        self.run_root_in_span(ctx, options, start, compensations, checkpoint, run_id)
+            .instrument(span)
+            .await
+    }
 
-        if errors.is_empty() {
-            Ok(())
-        } else {
-            Err(errors)
+    async fn run_root_in_span(
+        &self,
+        ctx: &mut Context,
+        options: ExecuteOptions,
+        start: StepName,
+        compensations: CompensationLog,
+        checkpoint: Option<&Checkpointer<'_>>,
+        run_id: String,
+    ) -> ExecutionReport {
+        let started_at = SystemTime::now();
+        let clock = Instant::now();
+        self.listeners
+            .emit(WorkflowEvent::WorkflowStarted {
+                start_step: start.clone(),
+            })
+            .await;
+        let state = RunState {
+            limits: Arc::new(RunLimits::new(
+                options.deadline.or(self.deadline),
+                options.retry_budget.or(self.retry_budget),
+            )),
+            recorder: Recorder::default(),
+            compensations,
+        };
+        let mut outcome = self.run_from(start, ctx, &state, checkpoint).await;
+        let mut compensations = Vec::new();
+        if let Err(errors) = &mut outcome {
+            let (reports, compensation_errors) = state.compensations.compensate(ctx).await;
+            compensations = reports;
+            errors.extend(compensation_errors);
+
+            // Resuming would skip the steps that were just undone.
+            #[cfg(feature = "checkpoint")]
+            if let (Some(checkpointer), false) = (checkpoint, compensations.is_empty()) {
+                if let Err(e) = checkpointer.remove().await {
+                    warn!("Removing the checkpoint of run '{}' failed: {}", run_id, e);
+                    errors.push(e);
+                }
+            }
         }
+        let elapsed = clock.elapsed();
+        Span::current().record("outcome", outcome_name(outcome.is_ok()));
+        if !self.listeners.is_empty() {
+            self.listeners
+                .emit(WorkflowEvent::WorkflowFinished {
+                    duration: elapsed,
+                    outcome: outcome.clone(),
+                })
+                .await;
+        }
+        ExecutionReport::new(
+            run_id,
+            state.recorder.into_steps(),
+            started_at,
+            elapsed,
+            outcome,
+            compensations,
+        )
     }
 
-    async fn execute_step_with_retry(&self, entry: &StepEntry, ctx: &mut Context) -> StepResult {
-        let max_retries = entry.retry_policy.max_retries();
-        let timeout_duration = entry.timeout;
-
-        for attempt in 0..=max_retries {
-            match timeout(timeout_duration, entry.step.execute(ctx)).await {
-                Ok(Ok(output)) => {
-                    info!("Step '{}' completed successfully", entry.step.name());
-                    let next = match output {
-                        StepOutput::Continue(name) => Some(name),
-                        StepOutput::Complete => None,
-                    };
-                    // Note: hook calling would require trait object casting which is complex
-                    // For now, we skip hooks for basic Step implementations
-                    return StepResult::Success(next);
+    /// Runs steps from `start` until one completes the path or fails.
+    ///
+    /// Only the root path is checkpointed.
+    #[cfg_attr(not(feature = "checkpoint"), allow(unused_variables))]
+    fn run_from<'a>(
+        &'a self,
+        start: StepName,
+        ctx: &'a mut Context,
+        state: &'a RunState,
+        checkpoint: Option<&'a Checkpointer<'a>>,
+    ) -> BoxFuture<'a, Result<(), Vec<WorkflowError>>> {
+        Box::pin(async move {
+            let mut current_step = Some(start);
+            let mut errors = Vec::new();
+            let mut path = PathTracker::new(self);
+
+            while let Some(step_name) = current_step {
+                if ctx.is_cancelled() {
+                    info!("Workflow cancelled before step '{}'", step_name);
+                    errors.push(WorkflowError::Cancelled { step_name });
+                    break;
+                }
+                if state.limits.deadline_exceeded() {
+                    warn!("Workflow deadline exceeded before step '{}'", step_name);
+                    errors.push(WorkflowError::DeadlineExceeded { step_name });
+                    break;
                 }
-                Ok(Err(e)) => {
-                    if attempt < max_retries {
-                        self.log_and_wait_for_retry(entry, attempt, "failed").await;
-                        continue;
+                if let Err(error) = path.enter(&step_name) {
+                    warn!("Workflow stopped before step '{}': {}", step_name, error);
+                    errors.push(error);
+                    break;
+                }
+
+                let Some(node) = self.steps.get(&step_name) else {
+                    errors.push(WorkflowError::StepNotFound(step_name));
+                    break;
+                };
+                let slot = state.recorder.start(&step_name);
+                self.listeners
+                    .emit(WorkflowEvent::StepStarted {
+                        step_name: step_name.clone(),
+                    })
+                    .await;
+                let step_started = Instant::now();
+                let mut log = AttemptLog::default();
+                let span = info_span!(
+                    "step",
+                    step = %step_name,
+                    outcome = field::Empty,
+                    next_step = field::Empty,
+                );
+                let result = async {
+                    match node {
+                        Node::Step(entry) => {
+                            let scope = RunScope {
+                                step_name: step_name.clone(),
+                                limits: state.limits.clone(),
+                                listeners: self.listeners.clone(),
+                            };
+                            let run = entry.run(
+                                &step_name,
+                                ctx,
+                                &state.limits,
+                                &mut log,
+                                &self.listeners,
+                            );
+                            match scope.enter(run).await {
+                                StepResult::Success(next) if self.verify_outputs => {
+                                    match entry.verify_outputs(&step_name, ctx) {
+                                        Ok(()) => StepResult::Success(next),
+                                        Err(e) => {
+                                            warn!("{}", e);
+                                            StepResult::Failed(vec![e])
+                                        }
+                                    }
+                                }
+                                result => result,
+                            }
+                        }
+                        Node::Parallel(group) => {
+                            log.attempts = 1;
+                            self.execute_parallel(&step_name, group, ctx, state).await
+                        }
+                    }
+                }
+                .instrument(span.clone())
+                .await;
+                let succeeded = matches!(result, StepResult::Success(_));
+                let next_step = match &result {
+                    StepResult::Success(next) => next.clone(),
+                    _ => None,
+                };
+                span.record("outcome", outcome_name(succeeded));
+                if let Some(next) = &next_step {
+                    span.record("next_step", next.as_str());
+                }
+                if let (true, Node::Step(entry)) = (succeeded, node) {
+                    if let Some(step) = &entry.compensation {
+                        state.compensations.push(&step_name, step.clone());
+                    }
+                }
+                state
+                    .recorder
+                    .finish(slot, log.attempts, log.failures, succeeded, next_step);
+                if !self.listeners.is_empty() {
+                    let step_name = step_name.clone();
+                    let duration = step_started.elapsed();
+                    self.listeners
+                        .emit(match &result {
+                            StepResult::Success(next) => WorkflowEvent::StepCompleted {
+                                step_name,
+                                next_step: next.clone(),
+                                duration,
+                            },
+                            StepResult::Failed(errors) => WorkflowEvent::StepFailed {
+                                step_name,
+                                errors: errors.clone(),
+                                duration,
+                            },
+                        })
+                        .await;
+                }
+
+                #[cfg(feature = "checkpoint")]
+                if let (Some(checkpointer), StepResult::Success(next)) = (checkpoint, &result) {
+                    let compensable = state.compensations.names();
+                    if let Err(e) = checkpointer
+                        .save(ctx, &step_name, next.as_ref(), compensable)
+                        .await
+                    {
+                        warn!("Checkpoint after step '{}' failed: {}", step_name, e);
+                        errors.push(e);
+                        break;
                     }
-                    warn!(
-                        "Step '{}' failed after {} retries",
-                        entry.step.name(),
-                        attempt
-                    );
-                    return StepResult::Failed(vec![e]);
                 }
-                Err(_) => {
-                    let timeout_error = WorkflowError::Timeout {
-                        step_name: entry.step.name(),
-                    };
-                    if attempt < max_retries {
-                        self.log_and_wait_for_retry(entry, attempt, "timed out")
-                            .await;
-                        continue;
+
+                match result {
+                    StepResult::Success(next) => {
+                        current_step = next;
+                    }
+                    StepResult::Failed(step_errors) => {
+                        errors.extend(step_errors);
+                        if ctx.is_cancelled() && !errors.iter().any(is_cancelled) {
+                            errors.push(WorkflowError::Cancelled { step_name });
+                        }
+                        current_step = None;
                     }
-                    warn!(
-                        "Step '{}' timed out after {} retries",
-                        entry.step.name(),
-                        attempt
-                    );
-                    return StepResult::Failed(vec![timeout_error]);
                 }
             }
-        }
 
-        unreachable!("Loop should always return")
+            if errors.is_empty() {
+                Ok(())
+            } else {
+                Err(errors)
+            }
+        })
     }
 
-    async fn log_and_wait_for_retry(&self, entry: &StepEntry, attempt: u32, reason: &str) {
-        let max_retries = entry.retry_policy.max_retries();
+    async fn execute_parallel(
+        &self,
+        name: &StepName,
+        group: &ParallelGroup,
+        ctx: &mut Context,
+        state: &RunState,
+    ) -> StepResult {
+        let total = group.branches.len();
+        let required = group.policy.required(total);
         info!(
-            "Step '{}' {}, retrying ({}/{})",
-            entry.step.name(),
-            reason,
-            attempt + 1,
-            max_retries
+            "Parallel group '{}' starting {} branches ({} required)",
+            name, total, required
         );
-        if let Some(delay) = entry.retry_policy.delay_for_attempt(attempt) {
-            tokio::time::sleep(delay).await;
+
+        // Joins even if the run is dropped mid-group; the branches below are dropped first.
+        let (fork, branch_ctxs) = ctx.fork_scoped(total);
+        let mut pending: FuturesUnordered<_> = branch_ctxs
+            .into_iter()
+            .zip(&group.branches)
+            .enumerate()
+            .map(|(index, (mut branch_ctx, start))| async move {
+                let result = self
+                    .run_from(start.clone(), &mut branch_ctx, state, None)
+                    .await;
+                (index, branch_ctx, result)
+            })
+            .collect();
+
+        let mut succeeded = Vec::new();
+        let mut failed = 0;
+        let mut errors = Vec::new();
+        while let Some((index, branch_ctx, result)) = pending.next().await {
+            match result {
+                Ok(()) => succeeded.push((index, branch_ctx)),
+                Err(branch_errors) => {
+                    failed += 1;
+                    errors.extend(branch_errors);
+                }
+            }
+            if succeeded.len() >= required || failed > total - required {
+                break;
+            }
+        }
+        // Dropping the remaining branches releases their view of the parent context.
+        drop(pending);
+
+        let success = succeeded.len() >= required;
+        succeeded.sort_by_key(|(index, _)| *index);
+        fork.join(succeeded.into_iter().map(|(_, branch_ctx)| branch_ctx));
+
+        if success {
+            info!("Parallel group '{}' joined into '{}'", name, group.join);
+            StepResult::Success(Some(group.join.clone()))
+        } else {
+            warn!(
+                "Parallel group '{}' failed: {} of {} branches failed",
+                name, failed, total
+            );
+            StepResult::Failed(errors)
+        }
+    }
+}
+
+/// State shared by every path of a single run.
+struct RunState {
+    limits: Arc<RunLimits>,
+    recorder: Recorder,
+    compensations: CompensationLog,
+}
+
+/// Tracks the steps visited along one execution path to stop runaway loops.
+///
+/// Each parallel branch is its own path with its own counts.
+struct PathTracker<'a> {
+    workflow: &'a Workflow,
+    transitions: Option<usize>,
+    visits: HashMap<StepName, usize>,
+    recent: VecDeque<StepName>,
+}
+
+impl<'a> PathTracker<'a> {
+    fn new(workflow: &'a Workflow) -> Self {
+        Self {
+            workflow,
+            transitions: None,
+            visits: HashMap::new(),
+            recent: VecDeque::with_capacity(RECENT_STEPS),
+        }
+    }
+
+    /// Records a visit to `step_name`, failing if a limit is exceeded.
+    fn enter(&mut self, step_name: &StepName) -> Result<(), WorkflowError> {
+        if self.recent.len() == RECENT_STEPS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(step_name.clone());
+
+        // The first step of a path is not a transition.
+        let transitions = self.transitions.map_or(0, |n| n + 1);
+        self.transitions = Some(transitions);
+        if transitions > self.workflow.max_transitions {
+            return Err(WorkflowError::TransitionLimitExceeded {
+                limit: self.workflow.max_transitions,
+                recent_steps: self.recent.iter().cloned().collect(),
+            });
+        }
+
+        let visits = self.visits.entry(step_name.clone()).or_insert(0);
+        *visits += 1;
+        match self.workflow.visit_limits.get(step_name) {
+            Some(&limit) if *visits > limit => Err(WorkflowError::VisitLimitExceeded {
+                step_name: step_name.clone(),
+                limit,
+                recent_steps: self.recent.iter().cloned().collect(),
+            }),
+            _ => Ok(()),
         }
     }
 }
 
-enum StepResult {
-    Success(Option<StepName>),
-    Failed(Vec<WorkflowError>),
+fn is_cancelled(error: &WorkflowError) -> bool {
+    matches!(error, WorkflowError::Cancelled { .. })
+}
+
+/// The value of the `outcome` field of run and step spans.
+fn outcome_name(succeeded: bool) -> &'static str {
+    if succeeded {
+        "success"
+    } else {
+        "failure"
+    }
+}
+
+/// Generates a random run id of 16 hex digits.
+fn new_run_id() -> String {
+    static RUNS: AtomicU64 = AtomicU64::new(0);
+    let run = RUNS.fetch_add(1, Ordering::Relaxed);
+    format!("{:016x}", RandomState::new().hash_one(run))
 }
 
 /// Builder for constructing [`Workflow`] instances.
 #[derive(Default)]
 pub struct WorkflowBuilder {
-    steps: HashMap<StepName, StepEntry>,
+    name: Option<String>,
+    steps: HashMap<StepName, Node>,
     start_step: Option<StepName>,
+    deadline: Option<Duration>,
+    retry_budget: Option<u32>,
+    max_transitions: Option<usize>,
+    visit_limits: HashMap<StepName, usize>,
+    edges: HashMap<StepName, Vec<StepOutput>>,
+    initial_keys: Vec<KeySpec>,
+    verify_outputs: Option<bool>,
+    listeners: Listeners,
+    #[cfg(feature = "metrics")]
+    metrics: bool,
+    #[cfg(feature = "checkpoint")]
+    checkpoint_schema: tsumugi_core::ContextSchema,
 }
 
 impl WorkflowBuilder {
     /// Creates a new empty workflow builder.
     pub fn new() -> Self {
         Self {
+            name: None,
             steps: HashMap::new(),
             start_step: None,
+            deadline: None,
+            retry_budget: None,
+            max_transitions: None,
+            visit_limits: HashMap::new(),
+            edges: HashMap::new(),
+            initial_keys: Vec::new(),
+            verify_outputs: None,
+            listeners: Listeners::default(),
+            #[cfg(feature = "metrics")]
+            metrics: false,
+            #[cfg(feature = "checkpoint")]
+            checkpoint_schema: tsumugi_core::ContextSchema::new(),
         }
     }
 
+    /// Adds a step and returns a [`StepBuilder`] for configuring it.
+    ///
+    /// Retry policy, timeout and hooks can be layered in any combination
+    /// before the step is registered:
+    ///
+    /// ```rust,ignore
+    /// let workflow = Workflow::builder()
+    ///     .add("fetch", FetchStep)
+    ///     .retryable()
+    ///     .with_timeout()
+    ///     .with_hooks()
+    ///     .add("store", StoreStep)
+    ///     .timeout(Duration::from_secs(5))
+    ///     .start_with("fetch")
+    ///     .build()?;
+    /// ```
+    pub fn add<S: Step + 'static>(self, name: impl Into<StepName>, step: S) -> StepBuilder<S> {
+        StepBuilder::new(self, name.into(), step)
+    }
+
     /// Adds a step with an explicit name.
-    pub fn add_step<S: Step + 'static>(mut self, name: impl Into<StepName>, step: S) -> Self {
-        let step_name = name.into();
-        self.steps.insert(
-            step_name,
-            StepEntry {
-                step: Box::new(step),
-                timeout: Duration::from_secs(30),
-                retry_policy: tsumugi_core::RetryPolicy::None,
-            },
-        );
-        self
+    pub fn add_step<S: Step + 'static>(self, name: impl Into<StepName>, step: S) -> Self {
+        self.add(name, step).done()
     }
 
     /// Adds a retryable step with an explicit name.
-    pub fn add_retryable<S: Retryable + 'static>(
-        mut self,
+    pub fn add_retryable<S: Retryable + 'static>(self, name: impl Into<StepName>, step: S) -> Self {
+        self.add(name, step).retryable().done()
+    }
+
+    /// Adds a step that implements the WithHooks trait.
+    ///
+    /// `on_success` runs after a successful attempt and `on_failure` runs once
+    /// retries are exhausted. A failing hook is reported as
+    /// [`WorkflowError::HookError`].
+    pub fn add_with_hooks<S: WithHooks + 'static>(
+        self,
         name: impl Into<StepName>,
         step: S,
     ) -> Self {
-        let step_name = name.into();
-        let retry_policy = step.retry_policy();
-        self.steps.insert(
-            step_name,
-            StepEntry {
-                step: Box::new(step),
-                timeout: Duration::from_secs(30),
-                retry_policy,
-            },
-        );
-        self
+        self.add(name, step).with_hooks().done()
+    }
+
+    /// Adds a step that implements the Compensable trait.
+    ///
+    /// See [`StepBuilder::compensable`].
+    pub fn add_compensable<S: Compensable + 'static>(
+        self,
+        name: impl Into<StepName>,
+        step: S,
+    ) -> Self {
+        self.add(name, step).compensable().done()
     }
 
     /// Adds a step with custom timeout.
     pub fn add_with_timeout<S: Step + 'static>(
-        mut self,
+        self,
         name: impl Into<StepName>,
         step: S,
         timeout: Duration,
     ) -> Self {
-        let step_name = name.into();
-        self.steps.insert(
-            step_name,
-            StepEntry {
-                step: Box::new(step),
-                timeout,
-                retry_policy: tsumugi_core::RetryPolicy::None,
-            },
-        );
-        self
+        self.add(name, step).timeout(timeout).done()
     }
 
     /// Adds a step that implements WithTimeout trait.
     pub fn add_with_timeout_trait<S: WithTimeout + 'static>(
-        mut self,
+        self,
         name: impl Into<StepName>,
         step: S,
     ) -> Self {
-        let step_name = name.into();
-        let timeout = step.timeout();
-        self.steps.insert(
-            step_name,
-            StepEntry {
-                step: Box::new(step),
-                timeout,
-                retry_policy: tsumugi_core::RetryPolicy::None,
-            },
-        );
-        self
+        self.add(name, step).with_timeout().done()
     }
 
     /// Adds a fully configured step.
     pub fn add_configured<S: Step + 'static>(
-        mut self,
+        self,
         name: impl Into<StepName>,
         step: S,
         config: StepConfig,
     ) -> Self {
-        let step_name = name.into();
-        self.steps.insert(
-            step_name,
-            StepEntry {
-                step: Box::new(step),
-                timeout: config.timeout.unwrap_or(Duration::from_secs(30)),
-                retry_policy: config.retry_policy,
-            },
-        );
+        self.add(name, step).config(config).done()
+    }
+
+    /// Adds a group of branches that run concurrently and join into one step.
+    ///
+    /// See [`ParallelGroup`] for how branch contexts are merged.
+    pub fn add_parallel(mut self, name: impl Into<StepName>, group: ParallelGroup) -> Self {
+        self.steps.insert(name.into(), Node::Parallel(group));
+        self
+    }
+
+    /// Adds a step whose type is only known at runtime.
+    #[cfg(feature = "definition")]
+    pub(crate) fn add_entry(mut self, name: StepName, entry: StepEntry) -> Self {
+        self.steps.insert(name, Node::Step(entry));
+        self
+    }
+
+    /// Names the workflow.
+    ///
+    /// The name is recorded on the `workflow_run` tracing span of every run
+    /// and labels the workflow's metrics when they are enabled.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
         self
     }
 
@@ -270,9 +982,131 @@ impl WorkflowBuilder {
         self
     }
 
+    /// Sets a deadline for the whole run, measured from the start of execution.
+    ///
+    /// Can be overridden per call with [`ExecuteOptions::deadline`].
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Caps the total number of retries across all steps of a run.
+    ///
+    /// Can be overridden per call with [`ExecuteOptions::retry_budget`].
+    pub fn retry_budget(mut self, retries: u32) -> Self {
+        self.retry_budget = Some(retries);
+        self
+    }
+
+    /// Sets the maximum number of step transitions along one execution path.
+    ///
+    /// Guards against steps that accidentally point at each other. Exceeding
+    /// the limit fails the run with [`WorkflowError::TransitionLimitExceeded`].
+    /// Defaults to [`DEFAULT_MAX_TRANSITIONS`].
+    pub fn max_transitions(mut self, limit: usize) -> Self {
+        self.max_transitions = Some(limit);
+        self
+    }
+
+    /// Limits how often a step may run along one execution path.
+    ///
+    /// Use this to bound intentional loops. Exceeding the limit fails the run
+    /// with [`WorkflowError::VisitLimitExceeded`].
+    pub fn max_visits(mut self, step_name: impl Into<StepName>, limit: usize) -> Self {
+        self.visit_limits.insert(step_name.into(), limit);
+        self
+    }
+
+    /// Declares that step `from` may continue with step `to`.
+    ///
+    /// Use this for steps that do not implement [`Step::successors`]; edges
+    /// are added to any successors the step declares itself.
+    pub fn edge(mut self, from: impl Into<StepName>, to: impl Into<StepName>) -> Self {
+        self.declare(from.into(), StepOutput::Continue(to.into()));
+        self
+    }
+
+    /// Declares that the given step may complete the workflow.
+    pub fn terminal(mut self, step_name: impl Into<StepName>) -> Self {
+        self.declare(step_name.into(), StepOutput::Complete);
+        self
+    }
+
+    /// Registers a listener receiving the [`WorkflowEvent`]s of every run.
+    ///
+    /// Listeners are called in registration order, and each call is awaited
+    /// before the run continues. Pass an `Arc` to keep a handle to the
+    /// listener.
+    pub fn listener(mut self, listener: impl WorkflowListener + 'static) -> Self {
+        self.listeners.push(Arc::new(listener));
+        self
+    }
+
+    /// Records counters and histograms of every run through the
+    /// [`metrics`](::metrics) facade.
+    ///
+    /// Metrics are labelled with the workflow's [name](Self::name). Nested
+    /// workflows record their own metrics only if they opt in too.
+    /// Requires the `metrics` feature.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self) -> Self {
+        self.metrics = true;
+        self
+    }
+
+    /// Declares a context key the caller sets before executing the workflow.
+    ///
+    /// Initial keys satisfy the [required keys](Step::required_keys) of
+    /// every step.
+    pub fn initial_key(mut self, key: impl Into<KeySpec>) -> Self {
+        self.initial_keys.push(key.into());
+        self
+    }
+
+    /// Checks after every successful step that it set the context keys it
+    /// [declares to produce](Step::produced_keys).
+    ///
+    /// A step breaking its declaration fails with
+    /// [`WorkflowError::InvalidOutput`]. Enabled by default in debug builds.
+    pub fn verify_outputs(mut self, enabled: bool) -> Self {
+        self.verify_outputs = Some(enabled);
+        self
+    }
+
+    /// Includes the context value under `key` in every checkpoint.
+    ///
+    /// Only registered keys are saved by [`Workflow::resume`] and restored
+    /// when a run is resumed; a key missing from the context is skipped.
+    /// Requires the `checkpoint` feature.
+    #[cfg(feature = "checkpoint")]
+    pub fn checkpoint_key<T>(mut self, key: impl Into<tsumugi_core::ContextKey>) -> Self
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned + std::any::Any + Send + Sync,
+    {
+        self.checkpoint_schema = self.checkpoint_schema.key::<T>(key);
+        self
+    }
+
+    fn declare(&mut self, from: StepName, output: StepOutput) {
+        let outputs = self.edges.entry(from).or_default();
+        if !outputs.contains(&output) {
+            outputs.push(output);
+        }
+    }
+
     /// Builds the workflow.
+    ///
+    /// Fails with [`WorkflowError::Configuration`] without a start step or
+    /// with a misconfigured parallel group, and with
+    /// [`WorkflowError::StepNotFound`] if the start step, a parallel branch
+    /// or join, or a step with a visit limit does not exist.
+    ///
+    /// Then validates every declared transition and the
+    /// [required keys](Step::required_keys) of every step, and returns all
+    /// problems at once as [`WorkflowError::Validation`]. Steps without
+    /// declared successors are only checked at runtime.
     pub fn build(self) -> Result<Workflow, WorkflowError> {
-        let start_step = self.start_step.ok_or_else(|| {
+        let start_step = self.start_step.clone().ok_or_else(|| {
             WorkflowError::Configuration("Start step must be specified".to_string())
         })?;
 
@@ -280,17 +1114,268 @@ impl WorkflowBuilder {
             return Err(WorkflowError::StepNotFound(start_step));
         }
 
+        let mut names: Vec<&StepName> = self.steps.keys().collect();
+        names.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        for name in names {
+            if let Some(Node::Parallel(group)) = self.steps.get(name) {
+                group.validate(name)?;
+                for step in group.branches.iter().chain([&group.join]) {
+                    if !self.steps.contains_key(step) {
+                        return Err(WorkflowError::StepNotFound(step.clone()));
+                    }
+                }
+            }
+        }
+
+        let mut limited: Vec<&StepName> = self.visit_limits.keys().collect();
+        limited.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        if let Some(name) = limited
+            .into_iter()
+            .find(|name| !self.steps.contains_key(*name))
+        {
+            return Err(WorkflowError::StepNotFound(name.clone()));
+        }
+
+        let mut issues = Vec::new();
+        let mut declared: Vec<&StepName> = self.edges.keys().collect();
+        declared.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        for name in declared {
+            if !self.steps.contains_key(name) {
+                issues.push(ValidationIssue::UnknownStep {
+                    step_name: name.clone(),
+                    referenced_by: None,
+                });
+            }
+        }
+
+        let nodes: HashMap<StepName, NodeEdges> = self
+            .steps
+            .iter()
+            .map(|(name, node)| (name.clone(), node.edges(self.edges.get(name))))
+            .collect();
+        issues.extend(graph::validate(Some(&start_step), &nodes));
+        issues.extend(graph::validate_keys(
+            &start_step,
+            &nodes,
+            &self.initial_keys,
+        ));
+        if !issues.is_empty() {
+            return Err(WorkflowError::Validation(issues));
+        }
+
+        #[cfg_attr(not(feature = "metrics"), allow(unused_mut))]
+        let mut listeners = self.listeners;
+        #[cfg(feature = "metrics")]
+        if self.metrics {
+            listeners.push(Arc::new(crate::metrics::MetricsListener::new(
+                self.name.as_deref(),
+            )));
+        }
+
         Ok(Workflow {
+            name: self.name,
             steps: self.steps,
             start_step,
+            deadline: self.deadline,
+            retry_budget: self.retry_budget,
+            max_transitions: self.max_transitions.unwrap_or(DEFAULT_MAX_TRANSITIONS),
+            visit_limits: self.visit_limits,
+            edges: self.edges,
+            verify_outputs: self.verify_outputs.unwrap_or(cfg!(debug_assertions)),
+            listeners,
+            #[cfg(feature = "checkpoint")]
+            checkpoint_schema: self.checkpoint_schema,
         })
     }
 }
 
+/// Builder for configuring a single step, returned by [`WorkflowBuilder::add`].
+///
+/// Options can be combined freely; the step is registered when the builder
+/// is finished with [`done`](Self::done) or when the next workflow-level
+/// method ([`add`](Self::add), [`add_step`](Self::add_step),
+/// [`start_with`](Self::start_with), [`build`](Self::build)) is called.
+pub struct StepBuilder<S> {
+    workflow: WorkflowBuilder,
+    name: StepName,
+    step: Arc<S>,
+    hooks: Option<Arc<dyn WithHooks>>,
+    timeout: Duration,
+    retry_policy: RetryPolicy,
+    retry_if: Option<RetryPredicate>,
+    circuit_breaker: Option<CircuitBreaker>,
+    compensation: Option<Arc<dyn Compensable>>,
+    required: Vec<KeySpec>,
+    produced: Vec<KeySpec>,
+}
+
+impl<S: Step + 'static> StepBuilder<S> {
+    fn new(workflow: WorkflowBuilder, name: StepName, step: S) -> Self {
+        Self {
+            workflow,
+            name,
+            required: step.required_keys(),
+            produced: step.produced_keys(),
+            step: Arc::new(step),
+            hooks: None,
+            timeout: DEFAULT_TIMEOUT,
+            retry_policy: RetryPolicy::None,
+            retry_if: None,
+            circuit_breaker: None,
+            compensation: None,
+        }
+    }
+
+    /// Declares a context key this step requires, in addition to its
+    /// [`Step::required_keys`].
+    pub fn requires(mut self, key: impl Into<KeySpec>) -> Self {
+        self.required.push(key.into());
+        self
+    }
+
+    /// Declares a context key this step produces, in addition to its
+    /// [`Step::produced_keys`].
+    pub fn produces(mut self, key: impl Into<KeySpec>) -> Self {
+        self.produced.push(key.into());
+        self
+    }
+
+    /// Sets the timeout for each attempt of this step.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the retry policy for this step.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Retries a failed attempt only if `predicate` returns `true` for its error.
+    ///
+    /// Timeouts are passed as [`WorkflowError::Timeout`]. Without a predicate,
+    /// every error except a [permanent](WorkflowError::permanent) one is retried.
+    pub fn retry_if<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&WorkflowError) -> bool + Send + Sync + 'static,
+    {
+        self.retry_if = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Guards every attempt of this step with a [`CircuitBreaker`].
+    ///
+    /// While the breaker is open the step fails with
+    /// [`WorkflowError::CircuitOpen`] without being run.
+    pub fn circuit_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.circuit_breaker = Some(breaker);
+        self
+    }
+
+    /// Applies a [`StepConfig`].
+    ///
+    /// A `None` timeout falls back to the default of 30 seconds.
+    pub fn config(self, config: StepConfig) -> Self {
+        self.timeout(config.timeout.unwrap_or(DEFAULT_TIMEOUT))
+            .retry_policy(config.retry_policy)
+    }
+
+    /// Registers the step and returns the workflow builder.
+    pub fn done(mut self) -> WorkflowBuilder {
+        self.workflow.steps.insert(
+            self.name,
+            Node::Step(StepEntry {
+                step: self.step,
+                hooks: self.hooks,
+                timeout: self.timeout,
+                retry_policy: self.retry_policy,
+                retry_if: self.retry_if,
+                circuit_breaker: self.circuit_breaker,
+                compensation: self.compensation,
+                required: self.required,
+                produced: self.produced,
+            }),
+        );
+        self.workflow
+    }
+
+    /// Registers this step and starts configuring the next one.
+    pub fn add<T: Step + 'static>(self, name: impl Into<StepName>, step: T) -> StepBuilder<T> {
+        self.done().add(name, step)
+    }
+
+    /// Registers this step and adds the next one with default settings.
+    pub fn add_step<T: Step + 'static>(
+        self,
+        name: impl Into<StepName>,
+        step: T,
+    ) -> WorkflowBuilder {
+        self.done().add_step(name, step)
+    }
+
+    /// Registers this step and sets the start step by name.
+    pub fn start_with(self, step_name: impl Into<StepName>) -> WorkflowBuilder {
+        self.done().start_with(step_name)
+    }
+
+    /// Registers this step and builds the workflow.
+    pub fn build(self) -> Result<Workflow, WorkflowError> {
+        self.done().build()
+    }
+}
+
+impl<S: Retryable + 'static> StepBuilder<S> {
+    /// Uses the retry policy provided by [`Retryable::retry_policy`] and
+    /// classifies errors with [`Retryable::is_retryable`].
+    pub fn retryable(self) -> Self {
+        let retry_policy = self.step.retry_policy();
+        let step = self.step.clone();
+        self.retry_policy(retry_policy)
+            .retry_if(move |error| step.is_retryable(error))
+    }
+}
+
+impl<S: WithTimeout + 'static> StepBuilder<S> {
+    /// Uses the timeout provided by [`WithTimeout::timeout`].
+    pub fn with_timeout(self) -> Self {
+        let timeout = WithTimeout::timeout(self.step.as_ref());
+        self.timeout(timeout)
+    }
+}
+
+impl<S: WithHooks + 'static> StepBuilder<S> {
+    /// Calls the [`WithHooks`] callbacks of this step.
+    ///
+    /// `on_success` runs after a successful attempt and `on_failure` runs once
+    /// retries are exhausted. A failing hook is reported as
+    /// [`WorkflowError::HookError`].
+    pub fn with_hooks(mut self) -> Self {
+        let hooks: Arc<dyn WithHooks> = self.step.clone();
+        self.hooks = Some(hooks);
+        self
+    }
+}
+
+impl<S: Compensable + 'static> StepBuilder<S> {
+    /// Calls [`Compensable::compensate`] if this step completed and a later
+    /// step fails.
+    ///
+    /// Compensations run in reverse order of completion once the run has
+    /// failed; a failing compensation is reported as
+    /// [`WorkflowError::CompensationFailed`] and does not stop the others.
+    pub fn compensable(mut self) -> Self {
+        let step: Arc<dyn Compensable> = self.step.clone();
+        self.compensation = Some(step);
+        self
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use async_trait::async_trait;
+    use tsumugi_core::StepOutput;
 
     #[derive(Debug)]
     struct SuccessStep;
@@ -237,3 +237,3030 @@ async fn test_heterogeneous_context() {
     // Wrong type returns None
     assert_eq!(ctx.get::<String>("int_val"), None);
 }
+
+#[derive(Debug)]
+struct HookedStep {
+    fail: bool,
+    fail_hook: bool,
+}
+
+#[async_trait]
+impl Step for HookedStep {
+    async fn execute(&self, _ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+        if self.fail {
+            return Err(WorkflowError::StepError {
+                step_name: self.name(),
+                details: "step failed".to_string(),
+            });
+        }
+        Ok(StepOutput::done())
+    }
+
+    fn name(&self) -> StepName {
+        StepName::new("HookedStep")
+    }
+}
+
+#[async_trait]
+impl WithHooks for HookedStep {
+    async fn on_success(&self, ctx: &mut Context) -> Result<(), WorkflowError> {
+        if self.fail_hook {
+            return Err(WorkflowError::StepError {
+                step_name: self.name(),
+                details: "audit log unavailable".to_string(),
+            });
+        }
+        ctx.insert("on_success", true);
+        Ok(())
+    }
+
+    async fn on_failure(
+        &self,
+        ctx: &mut Context,
+        error: &WorkflowError,
+    ) -> Result<(), WorkflowError> {
+        if self.fail_hook {
+            return Err(WorkflowError::StepError {
+                step_name: self.name(),
+                details: "audit log unavailable".to_string(),
+            });
+        }
+        ctx.insert("on_failure", error.to_string());
+        Ok(())
+    }
+}
+
+async fn run_hooked(step: HookedStep) -> (Context, Result<(), Vec<WorkflowError>>) {
+    let mut ctx = Context::new();
+    let result = match Workflow::builder()
+        .add_with_hooks("hooked", step)
+        .start_with("hooked")
+        .build()
+    {
+        Ok(workflow) => workflow.execute(&mut ctx).await,
+        Err(e) => Err(vec![e]),
+    };
+    (ctx, result)
+}
+
+#[tokio::test]
+async fn test_on_success_hook_runs() {
+    let (ctx, result) = run_hooked(HookedStep {
+        fail: false,
+        fail_hook: false,
+    })
+    .await;
+
+    assert!(result.is_ok());
+    assert_eq!(ctx.get::<bool>("on_success"), Some(&true));
+    assert!(!ctx.contains_key("on_failure"));
+}
+
+#[tokio::test]
+async fn test_on_failure_hook_runs() {
+    let (ctx, result) = run_hooked(HookedStep {
+        fail: true,
+        fail_hook: false,
+    })
+    .await;
+
+    assert!(result.is_err());
+    assert!(!ctx.contains_key("on_success"));
+    assert_eq!(
+        ctx.get::<String>("on_failure").map(|s| s.as_str()),
+        Some("Step failed: HookedStep, details: step failed")
+    );
+}
+
+#[tokio::test]
+async fn test_on_success_hook_error_reported() {
+    let (_ctx, result) = run_hooked(HookedStep {
+        fail: false,
+        fail_hook: true,
+    })
+    .await;
+
+    let errors = result.unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(
+        &errors[0],
+        WorkflowError::HookError { step_name, hook_type: HookType::OnSuccess, .. }
+            if step_name.as_str() == "HookedStep"
+    ));
+}
+
+#[tokio::test]
+async fn test_on_failure_hook_error_reported() {
+    let (_ctx, result) = run_hooked(HookedStep {
+        fail: true,
+        fail_hook: true,
+    })
+    .await;
+
+    let errors = result.unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert!(matches!(&errors[0], WorkflowError::StepError { .. }));
+    assert!(matches!(
+        &errors[1],
+        WorkflowError::HookError {
+            hook_type: HookType::OnFailure,
+            ..
+        }
+    ));
+}
+
+#[derive(Debug)]
+struct FlakySlowStep {
+    attempts: Arc<AtomicU32>,
+}
+
+#[async_trait]
+impl Step for FlakySlowStep {
+    async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+        if self.attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        }
+        ctx.insert("success", true);
+        Ok(StepOutput::done())
+    }
+
+    fn name(&self) -> StepName {
+        StepName::new("FlakySlowStep")
+    }
+}
+
+impl Retryable for FlakySlowStep {
+    fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy::fixed(1, Duration::from_millis(10))
+    }
+}
+
+impl WithTimeout for FlakySlowStep {
+    fn timeout(&self) -> Duration {
+        Duration::from_millis(50)
+    }
+}
+
+#[tokio::test]
+async fn test_step_builder_combines_retry_and_timeout() {
+    let attempts = Arc::new(AtomicU32::new(0));
+    let workflow = Workflow::builder()
+        .add(
+            "flaky",
+            FlakySlowStep {
+                attempts: attempts.clone(),
+            },
+        )
+        .retryable()
+        .with_timeout()
+        .start_with("flaky")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let result = workflow.execute(&mut ctx).await;
+
+    assert!(result.is_ok());
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    assert_eq!(ctx.get::<bool>("success"), Some(&true));
+}
+
+#[tokio::test]
+async fn test_step_builder_chains_steps() {
+    let workflow = Workflow::builder()
+        .add("step1", Step1)
+        .timeout(Duration::from_secs(1))
+        .add("step2", Step2)
+        .retry_policy(RetryPolicy::fixed(2, Duration::from_millis(10)))
+        .start_with("step1")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    workflow.execute(&mut ctx).await.expect("workflow failed");
+
+    assert!(ctx.contains_key("step1"));
+    assert!(ctx.contains_key("step2"));
+}
+
+#[derive(Debug)]
+struct BranchStep {
+    key: &'static str,
+    next: Option<&'static str>,
+    fail: bool,
+    barrier: Option<Arc<tokio::sync::Barrier>>,
+}
+
+impl BranchStep {
+    fn new(key: &'static str) -> Self {
+        Self {
+            key,
+            next: None,
+            fail: false,
+            barrier: None,
+        }
+    }
+}
+
+#[async_trait]
+impl Step for BranchStep {
+    async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+        if let Some(barrier) = &self.barrier {
+            barrier.wait().await;
+        }
+        if self.fail {
+            return Err(WorkflowError::StepError {
+                step_name: self.name(),
+                details: format!("{} failed", self.key),
+            });
+        }
+        let base = ctx.get::<u32>("base").copied().unwrap_or(0);
+        ctx.insert(self.key, base + 1);
+        Ok(match self.next {
+            Some(next) => StepOutput::next(next),
+            None => StepOutput::done(),
+        })
+    }
+
+    fn name(&self) -> StepName {
+        StepName::new(self.key)
+    }
+}
+
+#[tokio::test]
+async fn test_parallel_branches_run_concurrently_and_merge() {
+    // Both branches wait on the same barrier, so they only finish if they run concurrently.
+    let barrier = Arc::new(tokio::sync::Barrier::new(2));
+    let workflow = Workflow::builder()
+        .add(
+            "a",
+            BranchStep {
+                barrier: Some(barrier.clone()),
+                next: Some("a2"),
+                ..BranchStep::new("a")
+            },
+        )
+        .timeout(Duration::from_secs(1))
+        .add_step("a2", BranchStep::new("a2"))
+        .add(
+            "b",
+            BranchStep {
+                barrier: Some(barrier),
+                ..BranchStep::new("b")
+            },
+        )
+        .timeout(Duration::from_secs(1))
+        .add_step("join", BranchStep::new("join"))
+        .add_parallel("fan_out", ParallelGroup::new(["a", "b"], "join"))
+        .start_with("fan_out")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    ctx.insert("base", 10u32);
+    workflow.execute(&mut ctx).await.expect("workflow failed");
+
+    assert_eq!(ctx.get::<u32>("a"), Some(&11));
+    assert_eq!(ctx.get::<u32>("a2"), Some(&11));
+    assert_eq!(ctx.get::<u32>("b"), Some(&11));
+    assert_eq!(ctx.get::<u32>("join"), Some(&11));
+    assert_eq!(ctx.get_mut::<u32>("base").copied(), Some(10));
+}
+
+#[tokio::test]
+async fn test_dropped_parallel_group_leaves_the_context_writable() {
+    // The barrier waits for a third party that never comes, so both branches hang.
+    let barrier = Arc::new(tokio::sync::Barrier::new(3));
+    let hanging = |key| BranchStep {
+        barrier: Some(barrier.clone()),
+        ..BranchStep::new(key)
+    };
+    let workflow = Workflow::builder()
+        .add_step("a", hanging("a"))
+        .add_step("b", hanging("b"))
+        .add_step("join", BranchStep::new("join"))
+        .add_parallel("fan_out", ParallelGroup::new(["a", "b"], "join"))
+        .start_with("fan_out")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    ctx.insert("base", 10u32);
+    let run = tokio::time::timeout(Duration::from_millis(50), workflow.execute(&mut ctx)).await;
+    assert!(run.is_err());
+
+    assert_eq!(ctx.get_mut::<u32>("base").copied(), Some(10));
+    assert_eq!(ctx.remove::<u32>("base"), Some(10));
+    assert!(ctx.is_empty());
+}
+
+#[tokio::test]
+async fn test_parallel_any_policy_tolerates_failures() {
+    let workflow = Workflow::builder()
+        .add_step(
+            "bad",
+            BranchStep {
+                fail: true,
+                ..BranchStep::new("bad")
+            },
+        )
+        .add_step("good", BranchStep::new("good"))
+        .add_step("join", BranchStep::new("join"))
+        .add_parallel(
+            "fan_out",
+            ParallelGroup::new(["bad", "good"], "join").policy(JoinPolicy::Any),
+        )
+        .start_with("fan_out")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    workflow.execute(&mut ctx).await.expect("workflow failed");
+
+    assert!(ctx.contains_key("good"));
+    assert!(ctx.contains_key("join"));
+}
+
+#[tokio::test]
+async fn test_parallel_at_least_policy_reports_branch_errors() {
+    let failing = |key| BranchStep {
+        fail: true,
+        ..BranchStep::new(key)
+    };
+    let workflow = Workflow::builder()
+        .add_step("x", failing("x"))
+        .add_step("y", failing("y"))
+        .add_step("z", BranchStep::new("z"))
+        .add_step("join", BranchStep::new("join"))
+        .add_parallel(
+            "fan_out",
+            ParallelGroup::new(["x", "y", "z"], "join").policy(JoinPolicy::AtLeast(2)),
+        )
+        .start_with("fan_out")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let errors = workflow.execute(&mut ctx).await.unwrap_err();
+
+    assert_eq!(errors.len(), 2);
+    assert!(errors
+        .iter()
+        .all(|e| matches!(e, WorkflowError::StepError { .. })));
+    assert!(!ctx.contains_key("join"));
+}
+
+#[tokio::test]
+async fn test_parallel_group_validation() {
+    let result = Workflow::builder()
+        .add_step("a", BranchStep::new("a"))
+        .add_parallel("fan_out", ParallelGroup::new(["a", "missing"], "a"))
+        .start_with("fan_out")
+        .build();
+
+    assert!(matches!(result, Err(WorkflowError::StepNotFound(name)) if name.as_str() == "missing"));
+}
+
+#[derive(Debug, Default)]
+struct SquareStep {
+    in_flight: Arc<AtomicU32>,
+    max_in_flight: Arc<AtomicU32>,
+    flaky_attempts: Arc<AtomicU32>,
+}
+
+#[async_trait]
+impl Step for SquareStep {
+    async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+        let item = *ctx
+            .get::<u64>(MapStep::<u64, u64>::ITEM_KEY)
+            .ok_or_else(|| WorkflowError::StepError {
+                step_name: self.name(),
+                details: "item not found".to_string(),
+            })?;
+        let offset = *ctx.get::<u64>("offset").unwrap_or(&0);
+
+        let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+        // Item 3 fails on its first attempt only; item 4 always fails.
+        if item == 3 && self.flaky_attempts.fetch_add(1, Ordering::SeqCst) == 0 || item == 4 {
+            return Err(WorkflowError::StepError {
+                step_name: self.name(),
+                details: format!("item {} failed", item),
+            });
+        }
+        ctx.insert(MapStep::<u64, u64>::OUTPUT_KEY, item * item + offset);
+        Ok(StepOutput::done())
+    }
+
+    fn name(&self) -> StepName {
+        StepName::new("Square")
+    }
+}
+
+fn map_workflow(step: MapStep<u64, u64>) -> Result<Workflow, WorkflowError> {
+    Workflow::builder()
+        .add_step("map", step.continue_with("after"))
+        .add_step("after", BranchStep::new("after"))
+        .start_with("map")
+        .build()
+}
+
+#[tokio::test]
+async fn test_map_step_collects_results_with_bounded_concurrency() {
+    let max_in_flight = Arc::new(AtomicU32::new(0));
+    let child = SquareStep {
+        max_in_flight: max_in_flight.clone(),
+        ..SquareStep::default()
+    };
+    let workflow = map_workflow(
+        MapStep::new("squares", "numbers", "squared", child)
+            .concurrency(2)
+            .retry_policy(RetryPolicy::fixed(1, Duration::from_millis(1)))
+            .failure_mode(MapFailureMode::CollectAll),
+    )
+    .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    ctx.insert("numbers", vec![1u64, 2, 3, 4, 5]);
+    ctx.insert("offset", 100u64);
+    workflow.execute(&mut ctx).await.expect("workflow failed");
+
+    let results = ctx
+        .get::<Vec<Result<u64, WorkflowError>>>("squared")
+        .expect("results");
+    let values: Vec<Option<u64>> = results.iter().map(|r| r.as_ref().ok().copied()).collect();
+    assert_eq!(
+        values,
+        vec![Some(101), Some(104), Some(109), None, Some(125)]
+    );
+    assert!(max_in_flight.load(Ordering::SeqCst) <= 2);
+    assert!(ctx.contains_key("after"));
+    // The input stays available to later steps.
+    assert_eq!(ctx.get::<Vec<u64>>("numbers").map(Vec::len), Some(5));
+}
+
+#[tokio::test]
+async fn test_map_step_fail_fast() {
+    let workflow = map_workflow(MapStep::new(
+        "squares",
+        "numbers",
+        "squared",
+        SquareStep::default(),
+    ))
+    .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    ctx.insert("numbers", vec![1u64, 4, 5]);
+    let errors = workflow.execute(&mut ctx).await.unwrap_err();
+
+    assert!(
+        matches!(&errors[0], WorkflowError::StepError { details, .. } if details == "item 4 failed")
+    );
+    assert!(!ctx.contains_key("squared"));
+    assert!(!ctx.contains_key("after"));
+}
+
+#[tokio::test]
+async fn test_map_step_missing_input() {
+    let workflow = map_workflow(MapStep::new(
+        "squares",
+        "numbers",
+        "squared",
+        SquareStep::default(),
+    ))
+    .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let errors = workflow.execute(&mut ctx).await.unwrap_err();
+
+    assert!(
+        matches!(&errors[0], WorkflowError::StepError { step_name, .. } if step_name.as_str() == "squares")
+    );
+}
+
+#[tokio::test]
+async fn test_map_items_share_the_run_limits_and_listeners() {
+    let log = Arc::new(EventLog::default());
+    let workflow = Workflow::builder()
+        .add_step(
+            "map",
+            MapStep::<u64, u64>::new("squares", "numbers", "squared", SquareStep::default())
+                .retry_policy(RetryPolicy::fixed(5, Duration::from_millis(1))),
+        )
+        .start_with("map")
+        .retry_budget(2)
+        .listener(log.clone())
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    ctx.insert("numbers", vec![4u64]);
+    let errors = workflow.execute(&mut ctx).await.unwrap_err();
+
+    assert!(matches!(
+        &errors[..],
+        [WorkflowError::RetriesExhausted { attempts, .. }] if attempts.len() == 3
+    ));
+    let events = log.take();
+    let retries = events.iter().filter(|e| e.contains(" in ")).count();
+    assert_eq!(retries, 2);
+    assert!(events.contains(&"map: attempt 3 failed".to_string()));
+}
+
+/// Hangs on its first call and squares the item on every later one.
+#[derive(Debug, Default)]
+struct HangOnceStep {
+    calls: Arc<AtomicU32>,
+    next: Option<&'static str>,
+}
+
+#[async_trait]
+impl Step for HangOnceStep {
+    async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+        if self.calls.fetch_add(1, Ordering::SeqCst) == 0 {
+            std::future::pending::<()>().await;
+        }
+        let item = *ctx.get::<u64>(MapStep::<u64, u64>::ITEM_KEY).unwrap_or(&0);
+        ctx.insert(MapStep::<u64, u64>::OUTPUT_KEY, item * item);
+        Ok(match self.next {
+            Some(next) => StepOutput::next(next),
+            None => StepOutput::done(),
+        })
+    }
+
+    fn name(&self) -> StepName {
+        StepName::new("HangOnce")
+    }
+}
+
+#[derive(Debug)]
+struct IncrementStep;
+
+#[async_trait]
+impl Step for IncrementStep {
+    async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+        let counter = ctx
+            .get_mut::<u32>("counter")
+            .ok_or_else(|| WorkflowError::StepError {
+                step_name: self.name(),
+                details: "counter is not writable".to_string(),
+            })?;
+        *counter += 1;
+        Ok(StepOutput::done())
+    }
+
+    fn name(&self) -> StepName {
+        StepName::new("Increment")
+    }
+}
+
+#[tokio::test]
+async fn test_timed_out_map_step_leaves_the_context_writable() {
+    let workflow = Workflow::builder()
+        .add(
+            "map",
+            MapStep::<u64, u64>::new("squares", "numbers", "squared", HangOnceStep::default())
+                .continue_with("increment"),
+        )
+        .timeout(Duration::from_millis(100))
+        .retry_policy(RetryPolicy::fixed(1, Duration::from_millis(1)))
+        .add_step("increment", IncrementStep)
+        .start_with("map")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    ctx.insert("numbers", vec![2u64, 3]);
+    ctx.insert("counter", 1u32);
+    workflow.execute(&mut ctx).await.expect("workflow failed");
+
+    assert_eq!(ctx.get::<u32>("counter"), Some(&2));
+    assert_eq!(ctx.remove::<u32>("counter"), Some(2));
+    assert!(ctx.get_mut::<Vec<u64>>("numbers").is_some());
+}
+
+#[derive(Debug)]
+struct DoubleStep;
+
+#[async_trait]
+impl Step for DoubleStep {
+    async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+        let value = *ctx
+            .get::<u32>("value")
+            .ok_or_else(|| WorkflowError::StepError {
+                step_name: self.name(),
+                details: "value not found".to_string(),
+            })?;
+        ctx.insert("doubled", value * 2);
+        ctx.insert("saw_secret", ctx.contains_key("secret"));
+        Ok(StepOutput::done())
+    }
+
+    fn name(&self) -> StepName {
+        StepName::new("Double")
+    }
+}
+
+fn doubling_workflow() -> Result<Arc<Workflow>, WorkflowError> {
+    Workflow::builder()
+        .add_step("double", DoubleStep)
+        .start_with("double")
+        .build()
+        .map(Arc::new)
+}
+
+#[tokio::test]
+async fn test_sub_workflow_shares_parent_context() {
+    let workflow = Workflow::builder()
+        .add_step(
+            "nested",
+            SubWorkflowStep::new("Nested", doubling_workflow().expect("valid workflow"))
+                .continue_with("after"),
+        )
+        .add_step("after", BranchStep::new("after"))
+        .start_with("nested")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    ctx.insert("value", 21u32);
+    ctx.insert("secret", true);
+    workflow.execute(&mut ctx).await.expect("workflow failed");
+
+    assert_eq!(ctx.get::<u32>("doubled"), Some(&42));
+    assert_eq!(ctx.get::<bool>("saw_secret"), Some(&true));
+    assert!(ctx.contains_key("after"));
+}
+
+#[tokio::test]
+async fn test_sub_workflow_isolated_with_key_mapping() {
+    let workflow = Workflow::builder()
+        .add_step(
+            "nested",
+            SubWorkflowStep::new("Nested", doubling_workflow().expect("valid workflow"))
+                .isolated()
+                .input("amount", "value")
+                .output("doubled", "total")
+                .output("saw_secret", "saw_secret"),
+        )
+        .start_with("nested")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    ctx.insert("amount", 5u32);
+    ctx.insert("secret", true);
+    workflow.execute(&mut ctx).await.expect("workflow failed");
+
+    assert_eq!(ctx.get::<u32>("total"), Some(&10));
+    assert_eq!(ctx.get::<u32>("amount"), Some(&5));
+    assert_eq!(ctx.get::<bool>("saw_secret"), Some(&false));
+    assert!(!ctx.contains_key("value"));
+    assert!(!ctx.contains_key("doubled"));
+}
+
+#[tokio::test]
+async fn test_sub_workflow_error_shows_path() {
+    let inner = Workflow::builder()
+        .add_step(
+            "leaf",
+            HookedStep {
+                fail: true,
+                fail_hook: false,
+            },
+        )
+        .start_with("leaf")
+        .build()
+        .expect("valid workflow");
+    let middle = Workflow::builder()
+        .add_step("inner", SubWorkflowStep::new("Inner", inner))
+        .start_with("inner")
+        .build()
+        .expect("valid workflow");
+    let workflow = Workflow::builder()
+        .add_step("middle", SubWorkflowStep::new("Middle", middle))
+        .start_with("middle")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let errors = workflow.execute(&mut ctx).await.unwrap_err();
+
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(
+        &errors[0],
+        WorkflowError::SubWorkflow { step_name, errors }
+            if step_name.as_str() == "Middle" && errors.len() == 1
+    ));
+    assert_eq!(
+        errors[0].to_string(),
+        "Middle -> Inner -> Step failed: HookedStep, details: step failed"
+    );
+}
+
+#[tokio::test]
+async fn test_timed_out_isolated_sub_workflow_keeps_its_inputs() {
+    let nested = Workflow::builder()
+        .add_step(
+            "hang",
+            HangOnceStep {
+                next: Some("double"),
+                ..HangOnceStep::default()
+            },
+        )
+        .add_step("double", DoubleStep)
+        .start_with("hang")
+        .build()
+        .expect("valid workflow");
+    let workflow = Workflow::builder()
+        .add(
+            "nested",
+            SubWorkflowStep::new("Nested", nested)
+                .isolated()
+                .input("number", "value")
+                .output("doubled", "result"),
+        )
+        .timeout(Duration::from_millis(100))
+        .retry_policy(RetryPolicy::fixed(1, Duration::from_millis(1)))
+        .start_with("nested")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    ctx.insert("number", 21u32);
+    workflow.execute(&mut ctx).await.expect("workflow failed");
+
+    assert_eq!(ctx.get::<u32>("result"), Some(&42));
+    assert_eq!(ctx.get::<u32>("number"), Some(&21));
+}
+
+#[tokio::test]
+async fn test_sub_workflow_missing_input() {
+    let workflow = Workflow::builder()
+        .add_step(
+            "nested",
+            SubWorkflowStep::new("Nested", doubling_workflow().expect("valid workflow"))
+                .isolated()
+                .input("present", "value")
+                .input("absent", "other"),
+        )
+        .start_with("nested")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    ctx.insert("present", 1u32);
+    let errors = workflow.execute(&mut ctx).await.unwrap_err();
+
+    assert!(
+        matches!(&errors[0], WorkflowError::StepError { details, .. } if details == "Input 'absent' not found")
+    );
+    assert_eq!(ctx.get::<u32>("present"), Some(&1));
+}
+
+#[derive(Debug)]
+struct CancelSelfStep;
+
+#[async_trait]
+impl Step for CancelSelfStep {
+    async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+        ctx.cancellation_token().cancel();
+        ctx.insert("cancel_self", true);
+        Ok(StepOutput::next("step2"))
+    }
+
+    fn name(&self) -> StepName {
+        StepName::new("CancelSelf")
+    }
+}
+
+#[derive(Debug)]
+struct CooperativeStep;
+
+#[async_trait]
+impl Step for CooperativeStep {
+    async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+        let token = ctx.cancellation_token().clone();
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(10)) => Ok(StepOutput::done()),
+            _ = token.cancelled() => {
+                ctx.insert("cleaned_up", true);
+                Err(WorkflowError::Cancelled { step_name: self.name() })
+            }
+        }
+    }
+
+    fn name(&self) -> StepName {
+        StepName::new("Cooperative")
+    }
+}
+
+fn cancel_after(ctx: &Context, delay: Duration) {
+    let token = ctx.cancellation_token().clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        token.cancel();
+    });
+}
+
+#[tokio::test]
+async fn test_cancelled_before_start() {
+    let workflow = Workflow::builder()
+        .add_step("step1", Step1)
+        .add_step("step2", Step2)
+        .start_with("step1")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    ctx.cancellation_token().cancel();
+    let errors = workflow.execute(&mut ctx).await.unwrap_err();
+
+    assert!(
+        matches!(&errors[..], [WorkflowError::Cancelled { step_name }] if step_name.as_str() == "step1")
+    );
+    assert!(ctx.is_empty());
+}
+
+#[tokio::test]
+async fn test_cancellation_stops_before_next_step() {
+    let workflow = Workflow::builder()
+        .add_step("cancel", CancelSelfStep)
+        .add_step("step2", Step2)
+        .start_with("cancel")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let errors = workflow.execute(&mut ctx).await.unwrap_err();
+
+    assert!(
+        matches!(&errors[..], [WorkflowError::Cancelled { step_name }] if step_name.as_str() == "step2")
+    );
+    assert_eq!(ctx.get::<bool>("cancel_self"), Some(&true));
+    assert!(!ctx.contains_key("step2"));
+}
+
+#[tokio::test]
+async fn test_running_step_observes_cancellation() {
+    let workflow = Workflow::builder()
+        .add("cooperative", CooperativeStep)
+        .timeout(Duration::from_secs(30))
+        .retry_policy(RetryPolicy::fixed(3, Duration::from_millis(10)))
+        .start_with("cooperative")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    cancel_after(&ctx, Duration::from_millis(20));
+    let errors = tokio::time::timeout(Duration::from_secs(5), workflow.execute(&mut ctx))
+        .await
+        .expect("workflow should stop promptly")
+        .unwrap_err();
+
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(&errors[0], WorkflowError::Cancelled { .. }));
+    assert_eq!(ctx.get::<bool>("cleaned_up"), Some(&true));
+}
+
+#[tokio::test]
+async fn test_cancellation_interrupts_retry_delay() {
+    let attempts = Arc::new(AtomicU32::new(0));
+    let workflow = Workflow::builder()
+        .add(
+            "retry",
+            RetryableStep {
+                attempts: attempts.clone(),
+                fail_until: 10,
+            },
+        )
+        .retry_policy(RetryPolicy::fixed(5, Duration::from_secs(10)))
+        .start_with("retry")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    cancel_after(&ctx, Duration::from_millis(20));
+    let errors = tokio::time::timeout(Duration::from_secs(5), workflow.execute(&mut ctx))
+        .await
+        .expect("workflow should stop promptly")
+        .unwrap_err();
+
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    assert!(matches!(&errors[0], WorkflowError::StepError { .. }));
+    assert!(matches!(
+        errors.last(),
+        Some(WorkflowError::Cancelled { step_name }) if step_name.as_str() == "retry"
+    ));
+}
+
+#[derive(Debug)]
+struct DelayStep {
+    delay: Duration,
+    next: &'static str,
+}
+
+#[async_trait]
+impl Step for DelayStep {
+    async fn execute(&self, _ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+        tokio::time::sleep(self.delay).await;
+        Ok(StepOutput::next(self.next))
+    }
+
+    fn name(&self) -> StepName {
+        StepName::new("DelayStep")
+    }
+}
+
+#[tokio::test]
+async fn test_deadline_spans_steps() {
+    let workflow = Workflow::builder()
+        .add_step(
+            "delay",
+            DelayStep {
+                delay: Duration::from_millis(30),
+                next: "slow",
+            },
+        )
+        .add_step("slow", SlowStep)
+        .start_with("delay")
+        .deadline(Duration::from_millis(100))
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let errors = tokio::time::timeout(Duration::from_secs(5), workflow.execute(&mut ctx))
+        .await
+        .expect("deadline should stop the workflow")
+        .unwrap_err();
+
+    assert!(
+        matches!(&errors[..], [WorkflowError::DeadlineExceeded { step_name }] if step_name.as_str() == "SlowStep")
+    );
+}
+
+#[tokio::test]
+async fn test_deadline_override_per_execute() {
+    let workflow = Workflow::builder()
+        .add_step("slow", SlowStep)
+        .start_with("slow")
+        .deadline(Duration::from_secs(60))
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let options = ExecuteOptions::new().deadline(Duration::from_millis(50));
+    let errors = tokio::time::timeout(
+        Duration::from_secs(5),
+        workflow.execute_with(&mut ctx, options),
+    )
+    .await
+    .expect("override should stop the workflow")
+    .unwrap_err();
+
+    assert!(matches!(
+        &errors[..],
+        [WorkflowError::DeadlineExceeded { .. }]
+    ));
+}
+
+#[tokio::test]
+async fn test_deadline_cuts_retry_delay() {
+    let attempts = Arc::new(AtomicU32::new(0));
+    let workflow = Workflow::builder()
+        .add(
+            "retry",
+            RetryableStep {
+                attempts: attempts.clone(),
+                fail_until: 10,
+            },
+        )
+        .retry_policy(RetryPolicy::fixed(5, Duration::from_secs(10)))
+        .start_with("retry")
+        .deadline(Duration::from_secs(1))
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let errors = tokio::time::timeout(Duration::from_secs(5), workflow.execute(&mut ctx))
+        .await
+        .expect("workflow should not sleep past the deadline")
+        .unwrap_err();
+
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    assert!(matches!(&errors[0], WorkflowError::StepError { .. }));
+    assert!(matches!(
+        errors.last(),
+        Some(WorkflowError::DeadlineExceeded { step_name }) if step_name.as_str() == "RetryableStep"
+    ));
+}
+
+#[tokio::test]
+async fn test_retry_budget_caps_retries() {
+    let attempts = Arc::new(AtomicU32::new(0));
+    let workflow = Workflow::builder()
+        .add_retryable(
+            "retry",
+            RetryableStep {
+                attempts: attempts.clone(),
+                fail_until: 10,
+            },
+        )
+        .start_with("retry")
+        .retry_budget(2)
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let errors = workflow.execute(&mut ctx).await.unwrap_err();
+
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    assert!(matches!(
+        &errors[..],
+        [
+            WorkflowError::RetriesExhausted { attempts, .. },
+            WorkflowError::RetryBudgetExhausted { step_name },
+        ] if attempts.len() == 3 && step_name.as_str() == "RetryableStep"
+    ));
+}
+
+#[tokio::test]
+async fn test_retry_budget_override_per_execute() {
+    let attempts = Arc::new(AtomicU32::new(0));
+    let workflow = Workflow::builder()
+        .add_retryable(
+            "retry",
+            RetryableStep {
+                attempts: attempts.clone(),
+                fail_until: 10,
+            },
+        )
+        .start_with("retry")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let options = ExecuteOptions::new().retry_budget(0);
+    let errors = workflow.execute_with(&mut ctx, options).await.unwrap_err();
+
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    assert!(matches!(
+        errors.last(),
+        Some(WorkflowError::RetryBudgetExhausted { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_retry_cut_by_deadline_keeps_the_budget() {
+    let attempts = Arc::new(AtomicU32::new(0));
+    let workflow = Workflow::builder()
+        .add(
+            "doomed",
+            BranchStep {
+                fail: true,
+                ..BranchStep::new("doomed")
+            },
+        )
+        .retry_policy(RetryPolicy::fixed(1, Duration::from_secs(10)))
+        .add_step(
+            "delay",
+            DelayStep {
+                delay: Duration::from_millis(20),
+                next: "retry",
+            },
+        )
+        .add(
+            "retry",
+            RetryableStep {
+                attempts: attempts.clone(),
+                fail_until: 1,
+            },
+        )
+        .retry_policy(RetryPolicy::fixed(1, Duration::from_millis(1)))
+        .add_step("join", BranchStep::new("join"))
+        .add_parallel(
+            "fan_out",
+            ParallelGroup::new(["doomed", "delay"], "join").policy(JoinPolicy::Any),
+        )
+        .start_with("fan_out")
+        .deadline(Duration::from_secs(1))
+        .retry_budget(1)
+        .build()
+        .expect("valid workflow");
+
+    // The first branch gives up on its retry because of the deadline, so the
+    // only retry in the budget is left for the second one.
+    let mut ctx = Context::new();
+    workflow.execute(&mut ctx).await.expect("workflow failed");
+
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    assert!(ctx.contains_key("join"));
+}
+
+#[derive(Debug)]
+struct PingPongStep {
+    next: &'static str,
+}
+
+#[async_trait]
+impl Step for PingPongStep {
+    async fn execute(&self, _ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+        Ok(StepOutput::next(self.next))
+    }
+
+    fn name(&self) -> StepName {
+        StepName::new("PingPong")
+    }
+}
+
+#[derive(Debug)]
+struct CountdownStep;
+
+#[async_trait]
+impl Step for CountdownStep {
+    async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+        let remaining = ctx.get::<u32>("remaining").copied().unwrap_or(0);
+        if remaining == 0 {
+            return Ok(StepOutput::done());
+        }
+        ctx.insert("remaining", remaining - 1);
+        Ok(StepOutput::next("countdown"))
+    }
+
+    fn name(&self) -> StepName {
+        StepName::new("Countdown")
+    }
+}
+
+fn ping_pong_builder() -> WorkflowBuilder {
+    Workflow::builder()
+        .add_step("ping", PingPongStep { next: "pong" })
+        .add_step("pong", PingPongStep { next: "ping" })
+        .start_with("ping")
+}
+
+#[tokio::test]
+async fn test_transition_limit_stops_cycle() {
+    let workflow = ping_pong_builder()
+        .max_transitions(5)
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let errors = workflow.execute(&mut ctx).await.unwrap_err();
+
+    let expected = ["ping", "pong", "ping", "pong", "ping", "pong", "ping"];
+    assert!(matches!(
+        &errors[..],
+        [WorkflowError::TransitionLimitExceeded { limit: 5, recent_steps }]
+            if recent_steps.iter().map(|s| s.as_str()).eq(expected)
+    ));
+}
+
+#[tokio::test]
+async fn test_default_transition_limit_reports_recent_steps() {
+    let workflow = ping_pong_builder().build().expect("valid workflow");
+    assert_eq!(workflow.max_transitions(), tsumugi::DEFAULT_MAX_TRANSITIONS);
+
+    let mut ctx = Context::new();
+    let errors = workflow.execute(&mut ctx).await.unwrap_err();
+
+    assert!(matches!(
+        &errors[..],
+        [WorkflowError::TransitionLimitExceeded { limit, recent_steps }]
+            if *limit == tsumugi::DEFAULT_MAX_TRANSITIONS && recent_steps.len() == 10
+    ));
+}
+
+#[tokio::test]
+async fn test_visit_limit_bounds_intentional_loop() {
+    let workflow = Workflow::builder()
+        .add_step("countdown", CountdownStep)
+        .start_with("countdown")
+        .max_visits("countdown", 3)
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    ctx.insert("remaining", 2u32);
+    assert!(workflow.execute(&mut ctx).await.is_ok());
+
+    let mut ctx = Context::new();
+    ctx.insert("remaining", 5u32);
+    let errors = workflow.execute(&mut ctx).await.unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [WorkflowError::VisitLimitExceeded { step_name, limit: 3, recent_steps }]
+            if step_name.as_str() == "countdown" && recent_steps.len() == 4
+    ));
+    assert_eq!(ctx.get::<u32>("remaining"), Some(&2));
+}
+
+#[tokio::test]
+async fn test_start_step_errors() {
+    let result = Workflow::builder().add_step("step1", Step1).build();
+    assert!(matches!(
+        result,
+        Err(WorkflowError::Configuration(details)) if details == "Start step must be specified"
+    ));
+
+    let result = Workflow::builder()
+        .add_step("step1", Step1)
+        .start_with("missing")
+        .build();
+    assert!(matches!(result, Err(WorkflowError::StepNotFound(name)) if name.as_str() == "missing"));
+}
+
+#[tokio::test]
+async fn test_visit_limit_for_unknown_step() {
+    let result = Workflow::builder()
+        .add_step("step1", Step1)
+        .start_with("step1")
+        .max_visits("missing", 1)
+        .build();
+
+    assert!(matches!(result, Err(WorkflowError::StepNotFound(name)) if name.as_str() == "missing"));
+}
+
+#[derive(Debug)]
+struct DeclaredStep {
+    next: &'static str,
+}
+
+#[async_trait]
+impl Step for DeclaredStep {
+    async fn execute(&self, _ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+        Ok(StepOutput::next(self.next))
+    }
+
+    fn name(&self) -> StepName {
+        StepName::new("DeclaredStep")
+    }
+
+    fn successors(&self) -> Option<Vec<StepOutput>> {
+        Some(vec![StepOutput::next(self.next)])
+    }
+}
+
+#[tokio::test]
+async fn test_declared_successors_are_validated() {
+    let result = Workflow::builder()
+        .add_step("order", DeclaredStep { next: "paymnet" })
+        .add_step("payment", Step2)
+        .start_with("order")
+        .build();
+
+    assert!(matches!(
+        result,
+        Err(WorkflowError::Validation(issues)) if issues == [
+            ValidationIssue::UnknownStep {
+                step_name: StepName::new("paymnet"),
+                referenced_by: Some(StepName::new("order")),
+            },
+            ValidationIssue::UnreachableStep(StepName::new("payment")),
+        ]
+    ));
+}
+
+#[tokio::test]
+async fn test_builder_edges_report_all_issues() {
+    let result = Workflow::builder()
+        .add_step("step1", Step1)
+        .add_step("step2", Step2)
+        .add_step("ping", PingPongStep { next: "pong" })
+        .add_step("pong", PingPongStep { next: "ping" })
+        .edge("step1", "ping")
+        .edge("ping", "pong")
+        .edge("pong", "ping")
+        .terminal("step2")
+        .edge("missing", "step2")
+        .start_with("step1")
+        .build();
+
+    assert!(matches!(
+        result,
+        Err(WorkflowError::Validation(issues)) if issues == [
+            ValidationIssue::UnknownStep {
+                step_name: StepName::new("missing"),
+                referenced_by: None,
+            },
+            ValidationIssue::NoPathToCompletion(StepName::new("ping")),
+            ValidationIssue::NoPathToCompletion(StepName::new("pong")),
+            ValidationIssue::NoPathToCompletion(StepName::new("step1")),
+            ValidationIssue::UnreachableStep(StepName::new("step2")),
+        ]
+    ));
+}
+
+#[tokio::test]
+async fn test_declared_graph_builds_and_runs() {
+    let workflow = Workflow::builder()
+        .add_step("step1", Step1)
+        .add_step("step2", Step2)
+        .edge("step1", "step2")
+        .terminal("step2")
+        .start_with("step1")
+        .build()
+        .expect("valid workflow");
+
+    assert_eq!(
+        workflow.successors("step1"),
+        Some(vec![StepOutput::next("step2")])
+    );
+    assert_eq!(workflow.successors("step2"), Some(vec![StepOutput::done()]));
+
+    let mut ctx = Context::new();
+    assert!(workflow.execute(&mut ctx).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_required_keys_are_validated() {
+    let builder = || {
+        Workflow::builder()
+            .add("step1", Step1)
+            .produces(KeySpec::of::<String>("step1"))
+            .add("step2", Step2)
+            .requires(KeySpec::of::<String>("step1"))
+            .requires("order")
+            .done()
+            .edge("step1", "step2")
+            .terminal("step2")
+            .start_with("step1")
+    };
+
+    assert!(matches!(
+        builder().build(),
+        Err(WorkflowError::Validation(issues)) if issues == [
+            ValidationIssue::MissingInput {
+                step_name: StepName::new("step2"),
+                key: ContextKey::new("order"),
+            },
+        ]
+    ));
+    assert!(builder().initial_key("order").build().is_ok());
+}
+
+#[tokio::test]
+async fn test_verify_outputs_checks_declared_keys() {
+    let workflow = |verify| {
+        Workflow::builder()
+            .add("step2", Step2)
+            .produces(KeySpec::of::<u32>("step2"))
+            .done()
+            .verify_outputs(verify)
+            .start_with("step2")
+            .build()
+            .expect("valid workflow")
+    };
+    assert!(workflow(true).verify_outputs());
+
+    let mut ctx = Context::new();
+    let errors = workflow(true).execute(&mut ctx).await.unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [WorkflowError::InvalidOutput {
+            step_name,
+            error: ContextError::TypeMismatch { found: "alloc::string::String", .. },
+        }] if step_name.as_str() == "step2"
+    ));
+
+    let mut ctx = Context::new();
+    assert!(workflow(false).execute(&mut ctx).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_run_reports_successful_path() {
+    let attempts = Arc::new(AtomicU32::new(0));
+    let workflow = Workflow::builder()
+        .add_step(
+            "delay",
+            DelayStep {
+                delay: Duration::from_millis(20),
+                next: "retry",
+            },
+        )
+        .add_retryable(
+            "retry",
+            RetryableStep {
+                attempts: attempts.clone(),
+                fail_until: 2,
+            },
+        )
+        .start_with("delay")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let report = workflow.run(&mut ctx).await;
+
+    assert!(report.is_success());
+    assert!(report.errors().is_empty());
+    assert!(report.path().map(|s| s.as_str()).eq(["delay", "retry"]));
+    assert!(report.elapsed() >= Duration::from_millis(20));
+
+    let delay = report.step("delay").expect("delay step reported");
+    assert_eq!(delay.attempts(), 1);
+    assert!(delay.duration() >= Duration::from_millis(20));
+    assert!(delay.finished_at() <= report.steps()[1].started_at());
+
+    let retry = report.step("retry").expect("retry step reported");
+    assert!(retry.is_success());
+    assert_eq!(retry.attempts(), 3);
+    assert_eq!(retry.failures().len(), 2);
+    assert!(retry
+        .failures()
+        .iter()
+        .all(|f| !f.timed_out && matches!(f.error, WorkflowError::StepError { .. })));
+}
+
+#[tokio::test]
+async fn test_run_reports_failure() {
+    let workflow = Workflow::builder()
+        .add_step("step1", Step1)
+        .add("step2", SlowStep)
+        .timeout(Duration::from_millis(20))
+        .retry_policy(RetryPolicy::fixed(1, Duration::from_millis(1)))
+        .start_with("step1")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let report = workflow.run(&mut ctx).await;
+
+    assert!(!report.is_success());
+    assert!(matches!(
+        report.errors(),
+        [WorkflowError::RetriesExhausted { attempts, .. }] if attempts.len() == 2
+    ));
+    assert!(report.path().map(|s| s.as_str()).eq(["step1", "step2"]));
+
+    let step2 = report.step("step2").expect("step2 reported");
+    assert!(!step2.is_success());
+    assert_eq!(step2.attempts(), 2);
+    assert_eq!(step2.failures().len(), 2);
+    assert!(step2.failures().iter().all(|f| f.timed_out));
+
+    let summary = report.to_string();
+    assert!(summary.starts_with("failed in "));
+    assert!(summary.contains("step1 (1 attempt, "));
+    assert!(summary.contains("step2 (2 attempts, "));
+    assert!(summary.ends_with(", failed)"));
+    assert!(report.into_result().is_err());
+}
+
+#[tokio::test]
+async fn test_run_reports_parallel_branches() {
+    let workflow = Workflow::builder()
+        .add_step("a", BranchStep::new("a"))
+        .add_step("b", BranchStep::new("b"))
+        .add_step("join", BranchStep::new("join"))
+        .add_parallel("fan_out", ParallelGroup::new(["a", "b"], "join"))
+        .start_with("fan_out")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let report = workflow.run(&mut ctx).await;
+
+    assert!(report.is_success());
+    let path: Vec<_> = report.path().map(|s| s.as_str()).collect();
+    assert_eq!(path.len(), 4);
+    assert_eq!(path[0], "fan_out");
+    assert_eq!(path[3], "join");
+}
+
+#[tokio::test]
+async fn test_retries_exhausted_keeps_every_attempt() {
+    let attempts = Arc::new(AtomicU32::new(0));
+    let workflow = Workflow::builder()
+        .add_retryable(
+            "retry",
+            RetryableStep {
+                attempts: attempts.clone(),
+                fail_until: 10,
+            },
+        )
+        .start_with("retry")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let errors = workflow.execute(&mut ctx).await.unwrap_err();
+
+    let [WorkflowError::RetriesExhausted {
+        step_name,
+        attempts,
+    }] = &errors[..]
+    else {
+        unreachable!("unexpected errors: {:?}", errors);
+    };
+    assert_eq!(step_name.as_str(), "RetryableStep");
+    assert_eq!(attempts.len(), 4);
+    for (index, attempt) in attempts.iter().enumerate() {
+        assert!(!attempt.timed_out);
+        assert!(matches!(
+            &attempt.error,
+            WorkflowError::StepError { details, .. } if *details == format!("Attempt {} failed", index + 1)
+        ));
+    }
+}
+
+#[derive(Debug)]
+struct SlowThenFailStep {
+    attempts: Arc<AtomicU32>,
+}
+
+#[async_trait]
+impl Step for SlowThenFailStep {
+    async fn execute(&self, _ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+        if self.attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        }
+        Err(WorkflowError::StepError {
+            step_name: self.name(),
+            details: "refused".to_string(),
+        })
+    }
+
+    fn name(&self) -> StepName {
+        StepName::new("SlowThenFail")
+    }
+}
+
+#[tokio::test]
+async fn test_retries_exhausted_mixes_timeouts_and_failures() {
+    let workflow = Workflow::builder()
+        .add(
+            "flaky",
+            SlowThenFailStep {
+                attempts: Arc::new(AtomicU32::new(0)),
+            },
+        )
+        .timeout(Duration::from_millis(30))
+        .retry_policy(RetryPolicy::fixed(1, Duration::from_millis(1)))
+        .start_with("flaky")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let errors = workflow.execute(&mut ctx).await.unwrap_err();
+
+    assert!(matches!(
+        &errors[..],
+        [WorkflowError::RetriesExhausted { attempts, .. }]
+            if matches!(
+                &attempts[..],
+                [first, second]
+                    if first.timed_out
+                        && matches!(first.error, WorkflowError::Timeout { .. })
+                        && first.duration >= Duration::from_millis(30)
+                        && !second.timed_out
+                        && matches!(second.error, WorkflowError::StepError { .. })
+            )
+    ));
+}
+
+#[derive(Debug)]
+struct ClassifiedStep {
+    attempts: Arc<AtomicU32>,
+    permanent: bool,
+}
+
+#[async_trait]
+impl Step for ClassifiedStep {
+    async fn execute(&self, _ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+        self.attempts.fetch_add(1, Ordering::SeqCst);
+        let error = WorkflowError::StepError {
+            step_name: self.name(),
+            details: "invalid record".to_string(),
+        };
+        Err(if self.permanent {
+            error.permanent()
+        } else {
+            error
+        })
+    }
+
+    fn name(&self) -> StepName {
+        StepName::new("Classified")
+    }
+}
+
+impl Retryable for ClassifiedStep {
+    fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy::fixed(3, Duration::from_millis(1))
+    }
+
+    fn is_retryable(&self, error: &WorkflowError) -> bool {
+        !matches!(error, WorkflowError::StepError { details, .. } if details.starts_with("invalid"))
+    }
+}
+
+#[tokio::test]
+async fn test_permanent_error_skips_retries() {
+    let attempts = Arc::new(AtomicU32::new(0));
+    let workflow = Workflow::builder()
+        .add(
+            "classified",
+            ClassifiedStep {
+                attempts: attempts.clone(),
+                permanent: true,
+            },
+        )
+        .retry_policy(RetryPolicy::fixed(3, Duration::from_millis(1)))
+        .start_with("classified")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let errors = workflow.execute(&mut ctx).await.unwrap_err();
+
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    assert!(matches!(&errors[..], [error] if error.is_permanent()));
+}
+
+#[tokio::test]
+async fn test_retryable_classifier_skips_retries() {
+    let attempts = Arc::new(AtomicU32::new(0));
+    let workflow = Workflow::builder()
+        .add_retryable(
+            "classified",
+            ClassifiedStep {
+                attempts: attempts.clone(),
+                permanent: false,
+            },
+        )
+        .start_with("classified")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let errors = workflow.execute(&mut ctx).await.unwrap_err();
+
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    assert!(matches!(&errors[..], [WorkflowError::StepError { .. }]));
+}
+
+#[tokio::test]
+async fn test_retry_if_predicate() {
+    let attempts = Arc::new(AtomicU32::new(0));
+    let workflow = Workflow::builder()
+        .add(
+            "slow",
+            SlowThenFailStep {
+                attempts: attempts.clone(),
+            },
+        )
+        .timeout(Duration::from_millis(20))
+        .retry_policy(RetryPolicy::fixed(3, Duration::from_millis(1)))
+        .retry_if(|error| matches!(error, WorkflowError::Timeout { .. }))
+        .start_with("slow")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let errors = workflow.execute(&mut ctx).await.unwrap_err();
+
+    // The timeout is retried, the following failure is not.
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    assert!(matches!(
+        &errors[..],
+        [WorkflowError::RetriesExhausted { attempts, .. }] if attempts.len() == 2
+    ));
+}
+
+#[derive(Debug)]
+struct WebhookStep {
+    calls: Arc<AtomicU32>,
+    healthy: Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl WebhookStep {
+    fn down(calls: &Arc<AtomicU32>) -> Self {
+        Self {
+            calls: calls.clone(),
+            healthy: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+        }
+    }
+}
+
+#[async_trait]
+impl Step for WebhookStep {
+    async fn execute(&self, _ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        if self.healthy.load(Ordering::SeqCst) {
+            Ok(StepOutput::done())
+        } else {
+            Err(WorkflowError::StepError {
+                step_name: self.name(),
+                details: "503 Service Unavailable".to_string(),
+            })
+        }
+    }
+
+    fn name(&self) -> StepName {
+        StepName::new("Webhook")
+    }
+}
+
+#[tokio::test]
+async fn test_circuit_breaker_opens_after_failures() {
+    let calls = Arc::new(AtomicU32::new(0));
+    let workflow = Workflow::builder()
+        .add("notify", WebhookStep::down(&calls))
+        .retry_policy(RetryPolicy::fixed(5, Duration::from_millis(1)))
+        .circuit_breaker(CircuitBreaker::new("webhook", 2, Duration::from_secs(3600)))
+        .start_with("notify")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let errors = workflow.execute(&mut ctx).await.unwrap_err();
+
+    // Retries stop as soon as the breaker opens.
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    assert!(matches!(
+        &errors[..],
+        [
+            WorkflowError::RetriesExhausted { attempts, .. },
+            WorkflowError::CircuitOpen { circuit, .. },
+        ] if attempts.len() == 2 && circuit == "webhook"
+    ));
+
+    let breaker = workflow.circuit_breaker("webhook").expect("breaker");
+    assert_eq!(breaker.state(), CircuitState::Open);
+    assert_eq!(breaker.consecutive_failures(), 2);
+
+    let mut ctx = Context::new();
+    let report = workflow.run(&mut ctx).await;
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    assert!(matches!(
+        report.errors(),
+        [WorkflowError::CircuitOpen { .. }]
+    ));
+    assert_eq!(report.step("notify").map(|step| step.attempts()), Some(0));
+}
+
+#[tokio::test]
+async fn test_circuit_breaker_half_opens_after_cool_down() {
+    let calls = Arc::new(AtomicU32::new(0));
+    let step = WebhookStep::down(&calls);
+    let healthy = step.healthy.clone();
+    let workflow = Workflow::builder()
+        .add("notify", step)
+        .circuit_breaker(CircuitBreaker::new("webhook", 1, Duration::from_millis(50)))
+        .start_with("notify")
+        .build()
+        .expect("valid workflow");
+    let breaker = workflow
+        .circuit_breaker("webhook")
+        .expect("breaker")
+        .clone();
+
+    let mut ctx = Context::new();
+    assert!(workflow.execute(&mut ctx).await.is_err());
+    assert_eq!(breaker.state(), CircuitState::Open);
+
+    healthy.store(true, Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    assert_eq!(breaker.state(), CircuitState::HalfOpen);
+
+    let mut ctx = Context::new();
+    assert!(workflow.execute(&mut ctx).await.is_ok());
+    assert_eq!(breaker.state(), CircuitState::Closed);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_circuit_breaker_shared_by_name() {
+    let breakers = CircuitBreakerRegistry::new();
+    let calls = Arc::new(AtomicU32::new(0));
+    let build = |step| {
+        Workflow::builder()
+            .add("notify", step)
+            .circuit_breaker(breakers.get_or_insert("webhook", 1, Duration::from_secs(3600)))
+            .start_with("notify")
+            .build()
+            .expect("valid workflow")
+    };
+    let orders = build(WebhookStep::down(&calls));
+    let refunds = build(WebhookStep::down(&calls));
+
+    let mut ctx = Context::new();
+    assert!(orders.execute(&mut ctx).await.is_err());
+
+    let mut ctx = Context::new();
+    let errors = refunds.execute(&mut ctx).await.unwrap_err();
+    assert!(matches!(&errors[..], [WorkflowError::CircuitOpen { .. }]));
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        breakers.get("webhook").map(|breaker| breaker.state()),
+        Some(CircuitState::Open)
+    );
+}
+
+#[derive(Debug)]
+struct LedgerStep {
+    name: &'static str,
+    next: Option<&'static str>,
+    ledger: Arc<std::sync::Mutex<Vec<String>>>,
+    refuse_undo: bool,
+}
+
+impl LedgerStep {
+    fn new(
+        name: &'static str,
+        next: Option<&'static str>,
+        ledger: &Arc<std::sync::Mutex<Vec<String>>>,
+    ) -> Self {
+        Self {
+            name,
+            next,
+            ledger: ledger.clone(),
+            refuse_undo: false,
+        }
+    }
+}
+
+#[async_trait]
+impl Step for LedgerStep {
+    async fn execute(&self, _ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+        self.ledger
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .push(self.name.to_string());
+        Ok(match self.next {
+            Some(next) => StepOutput::next(next),
+            None => StepOutput::done(),
+        })
+    }
+
+    fn name(&self) -> StepName {
+        StepName::new(self.name)
+    }
+}
+
+#[async_trait]
+impl Compensable for LedgerStep {
+    async fn compensate(&self, _ctx: &mut Context) -> Result<(), WorkflowError> {
+        if self.refuse_undo {
+            return Err(WorkflowError::StepError {
+                step_name: self.name(),
+                details: "refund declined".to_string(),
+            });
+        }
+        self.ledger
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .push(format!("undo {}", self.name));
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_failed_run_compensates_in_reverse_order() {
+    let ledger = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let workflow = Workflow::builder()
+        .add_compensable(
+            "reserve",
+            LedgerStep::new("reserve", Some("charge"), &ledger),
+        )
+        .add("charge", LedgerStep::new("charge", Some("ship"), &ledger))
+        .compensable()
+        .add_step("ship", WebhookStep::down(&Arc::default()))
+        .start_with("reserve")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let report = workflow.run(&mut ctx).await;
+
+    assert_eq!(
+        *ledger.lock().unwrap(),
+        vec!["reserve", "charge", "undo charge", "undo reserve"]
+    );
+    assert!(matches!(report.errors(), [WorkflowError::StepError { .. }]));
+    let compensated: Vec<&str> = report
+        .compensations()
+        .iter()
+        .map(|c| c.name().as_str())
+        .collect();
+    assert_eq!(compensated, vec!["charge", "reserve"]);
+    assert!(report.compensations().iter().all(|c| c.is_success()));
+}
+
+#[tokio::test]
+async fn test_compensation_errors_are_collected() {
+    let ledger = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let workflow = Workflow::builder()
+        .add_compensable(
+            "reserve",
+            LedgerStep::new("reserve", Some("charge"), &ledger),
+        )
+        .add_compensable(
+            "charge",
+            LedgerStep {
+                refuse_undo: true,
+                ..LedgerStep::new("charge", Some("ship"), &ledger)
+            },
+        )
+        .add_step("ship", WebhookStep::down(&Arc::default()))
+        .start_with("reserve")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let report = workflow.run(&mut ctx).await;
+
+    // The failed compensation does not stop the remaining ones.
+    assert_eq!(
+        *ledger.lock().unwrap(),
+        vec!["reserve", "charge", "undo reserve"]
+    );
+    assert!(matches!(
+        report.errors(),
+        [
+            WorkflowError::StepError { .. },
+            WorkflowError::CompensationFailed { step_name, details },
+        ] if step_name.as_str() == "charge" && details.contains("refund declined")
+    ));
+    assert!(report.compensations()[0].error().is_some());
+    assert!(report.compensations()[1].is_success());
+}
+
+#[tokio::test]
+async fn test_successful_run_is_not_compensated() {
+    let ledger = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let workflow = Workflow::builder()
+        .add_compensable("reserve", LedgerStep::new("reserve", None, &ledger))
+        .start_with("reserve")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    let report = workflow.run(&mut ctx).await;
+
+    assert!(report.is_success());
+    assert!(report.compensations().is_empty());
+    assert_eq!(*ledger.lock().unwrap(), vec!["reserve"]);
+}
+
+#[derive(Default)]
+struct EventLog {
+    events: std::sync::Mutex<Vec<String>>,
+}
+
+impl EventLog {
+    fn take(&self) -> Vec<String> {
+        std::mem::take(
+            &mut *self
+                .events
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner),
+        )
+    }
+}
+
+#[async_trait]
+impl WorkflowListener for EventLog {
+    async fn on_event(&self, event: &WorkflowEvent) {
+        let line = match event {
+            WorkflowEvent::WorkflowStarted { start_step } => format!("started {}", start_step),
+            WorkflowEvent::WorkflowFinished { outcome, .. } => {
+                format!("finished ok={}", outcome.is_ok())
+            }
+            WorkflowEvent::StepStarted { step_name } => format!("{}: started", step_name),
+            WorkflowEvent::AttemptFailed {
+                step_name, attempt, ..
+            } => format!("{}: attempt {} failed", step_name, attempt),
+            WorkflowEvent::StepTimedOut {
+                step_name,
+                attempt,
+                timeout,
+            } => format!(
+                "{}: attempt {} timed out after {:?}",
+                step_name, attempt, timeout
+            ),
+            WorkflowEvent::RetryScheduled {
+                step_name,
+                attempt,
+                delay,
+            } => format!("{}: attempt {} in {:?}", step_name, attempt, delay),
+            WorkflowEvent::HookFailed {
+                step_name,
+                hook_type,
+                ..
+            } => format!("{}: {} failed", step_name, hook_type),
+            WorkflowEvent::StepCompleted {
+                step_name,
+                next_step,
+                ..
+            } => format!(
+                "{}: completed, next {:?}",
+                step_name,
+                next_step.as_ref().map(StepName::as_str)
+            ),
+            WorkflowEvent::StepFailed { step_name, .. } => format!("{}: failed", step_name),
+            _ => "unknown".to_string(),
+        };
+        self.events
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .push(line);
+    }
+}
+
+#[tokio::test]
+async fn test_listener_receives_events_in_order() {
+    let log = Arc::new(EventLog::default());
+    let workflow = Workflow::builder()
+        .add_step("step1", Step1)
+        .add_retryable(
+            "step2",
+            RetryableStep {
+                attempts: Arc::default(),
+                fail_until: 1,
+            },
+        )
+        .listener(log.clone())
+        .start_with("step1")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    assert!(workflow.execute(&mut ctx).await.is_ok());
+    assert_eq!(
+        log.take(),
+        [
+            "started step1",
+            "step1: started",
+            "step1: completed, next Some(\"step2\")",
+            "step2: started",
+            "step2: attempt 1 failed",
+            "step2: attempt 2 in 10ms",
+            "step2: completed, next None",
+            "finished ok=true",
+        ]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_listener_receives_timeouts_and_hook_failures() {
+    let log = Arc::new(EventLog::default());
+    let workflow = Workflow::builder()
+        .add("slow", SlowStep)
+        .timeout(Duration::from_millis(50))
+        .retry_policy(RetryPolicy::fixed(1, Duration::ZERO))
+        .done()
+        .listener(log.clone())
+        .start_with("slow")
+        .build()
+        .expect("valid workflow");
+
+    let mut ctx = Context::new();
+    assert!(workflow.execute(&mut ctx).await.is_err());
+    assert_eq!(
+        log.take(),
+        [
+            "started slow",
+            "slow: started",
+            "slow: attempt 1 timed out after 50ms",
+            "slow: attempt 1 failed",
+            "slow: attempt 2 in 0ns",
+            "slow: attempt 2 timed out after 50ms",
+            "slow: attempt 2 failed",
+            "slow: failed",
+            "finished ok=false",
+        ]
+    );
+
+    let workflow = Workflow::builder()
+        .add_with_hooks(
+            "hooked",
+            HookedStep {
+                fail: false,
+                fail_hook: true,
+            },
+        )
+        .listener(log.clone())
+        .start_with("hooked")
+        .build()
+        .expect("valid workflow");
+    assert!(workflow.execute(&mut ctx).await.is_err());
+    assert_eq!(
+        log.take(),
+        [
+            "started hooked",
+            "hooked: started",
+            "hooked: on_success failed",
+            "hooked: failed",
+            "finished ok=false",
+        ]
+    );
+}
+
+/// A span captured by [`SpanLog`].
+#[derive(Debug)]
+struct CapturedSpan {
+    id: u64,
+    name: &'static str,
+    parent: Option<&'static str>,
+    fields: Vec<(&'static str, String)>,
+}
+
+impl CapturedSpan {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .rev()
+            .find(|(field, _)| *field == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+struct FieldVisitor<'a>(&'a mut Vec<(&'static str, String)>);
+
+impl tracing::field::Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        self.0.push((field.name(), value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        self.0.push((field.name(), format!("{:?}", value)));
+    }
+}
+
+/// A tracing layer that records every span with its fields.
+#[derive(Clone, Default)]
+struct SpanLog {
+    spans: Arc<std::sync::Mutex<Vec<CapturedSpan>>>,
+}
+
+impl SpanLog {
+    fn take(&self) -> Vec<CapturedSpan> {
+        std::mem::take(
+            &mut *self
+                .spans
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner),
+        )
+    }
+}
+
+impl<S> tracing_subscriber::Layer<S> for SpanLog
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+{
+    fn on_new_span(
+        &self,
+        attrs: &tracing::span::Attributes<'_>,
+        id: &tracing::span::Id,
+        ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let parent = ctx
+            .span(id)
+            .and_then(|span| span.parent())
+            .map(|parent| parent.name());
+        let mut fields = Vec::new();
+        attrs.record(&mut FieldVisitor(&mut fields));
+        self.spans
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .push(CapturedSpan {
+                id: id.into_u64(),
+                name: attrs.metadata().name(),
+                parent,
+                fields,
+            });
+    }
+
+    fn on_record(
+        &self,
+        id: &tracing::span::Id,
+        values: &tracing::span::Record<'_>,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let mut spans = self
+            .spans
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        // Ids of closed spans are reused, so the latest span with the id is meant.
+        if let Some(span) = spans.iter_mut().rev().find(|span| span.id == id.into_u64()) {
+            values.record(&mut FieldVisitor(&mut span.fields));
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_runs_are_traced_with_spans() {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let log = SpanLog::default();
+    let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(log.clone()));
+
+    let workflow = Workflow::builder()
+        .name("orders")
+        .add_step("step1", Step1)
+        .add_retryable(
+            "step2",
+            RetryableStep {
+                attempts: Arc::default(),
+                fail_until: 1,
+            },
+        )
+        .start_with("step1")
+        .build()
+        .expect("valid workflow");
+    assert_eq!(workflow.name(), Some("orders"));
+
+    let mut ctx = Context::new();
+    let report = workflow
+        .run_with(&mut ctx, ExecuteOptions::new().run_id("request-42"))
+        .await;
+    assert!(report.is_success());
+    assert_eq!(report.run_id(), "request-42");
+
+    let spans = log.take();
+    let run = spans
+        .iter()
+        .find(|span| span.name == "workflow_run")
+        .expect("run span");
+    assert_eq!(run.parent, None);
+    assert_eq!(run.field("workflow"), Some("orders"));
+    assert_eq!(run.field("run_id"), Some("request-42"));
+    assert_eq!(run.field("outcome"), Some("success"));
+
+    let steps: Vec<_> = spans
+        .iter()
+        .filter(|span| span.name == "step")
+        .map(|span| {
+            (
+                span.parent,
+                span.field("step"),
+                span.field("outcome"),
+                span.field("next_step"),
+            )
+        })
+        .collect();
+    assert_eq!(
+        steps,
+        [
+            (
+                Some("workflow_run"),
+                Some("step1"),
+                Some("success"),
+                Some("step2")
+            ),
+            (Some("workflow_run"), Some("step2"), Some("success"), None),
+        ]
+    );
+
+    let attempts: Vec<_> = spans
+        .iter()
+        .filter(|span| span.name == "attempt")
+        .map(|span| {
+            (
+                span.parent,
+                span.field("step"),
+                span.field("attempt"),
+                span.field("outcome"),
+            )
+        })
+        .collect();
+    assert_eq!(
+        attempts,
+        [
+            (Some("step"), Some("step1"), Some("1"), Some("success")),
+            (Some("step"), Some("step2"), Some("1"), Some("error")),
+            (Some("step"), Some("step2"), Some("2"), Some("success")),
+        ]
+    );
+    let attempt = spans
+        .iter()
+        .find(|span| span.name == "attempt")
+        .expect("attempt span");
+    assert_eq!(attempt.field("timeout_ms"), Some("30000"));
+    assert!(attempt.field("retry_policy").is_some());
+}
+
+#[tokio::test]
+async fn test_run_ids_are_generated() {
+    let workflow = Workflow::builder()
+        .add_step("step1", Step1)
+        .add_step("step2", Step2)
+        .start_with("step1")
+        .build()
+        .expect("valid workflow");
+
+    let first = workflow.run(&mut Context::new()).await;
+    let second = workflow.run(&mut Context::new()).await;
+    assert_eq!(first.run_id().len(), 16);
+    assert_ne!(first.run_id(), second.run_id());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_diagrams_overlay_runs() {
+    let workflow = Workflow::builder()
+        .name("orders")
+        .add_step("step1", Step1)
+        .add_retryable(
+            "step2",
+            RetryableStep {
+                attempts: Arc::default(),
+                fail_until: 10,
+            },
+        )
+        .terminal("step2")
+        .start_with("step1")
+        .build()
+        .expect("valid workflow");
+
+    // Step1 declares no successors, so the graph has no edges until a run.
+    assert_eq!(
+        workflow.to_dot(),
+        "digraph \"orders\" {
+    node [shape=box, style=rounded];
+    \"__start\" [shape=point];
+    \"__start\" -> \"step1\";
+    \"step1\" [label=\"step1\\ntimeout 30s\", penwidth=2];
+    \"step2\" [label=\"step2\\ntimeout 30s\\nretry 3x, fixed 10ms\"];
+}
+"
+    );
+
+    let report = workflow.run(&mut Context::new()).await;
+    assert!(!report.is_success());
+    assert_eq!(
+        report
+            .step("step1")
+            .and_then(tsumugi::StepReport::next_step),
+        Some(&StepName::new("step2"))
+    );
+    assert_eq!(
+        report
+            .step("step2")
+            .and_then(tsumugi::StepReport::next_step),
+        None
+    );
+
+    assert_eq!(
+        workflow.to_dot_with_run(&report),
+        "digraph \"orders\" {
+    node [shape=box, style=rounded];
+    \"__start\" [shape=point];
+    \"__start\" -> \"step1\";
+    \"step1\" [label=\"step1\\ntimeout 30s\", penwidth=2, style=\"rounded,filled\", fillcolor=\"#c8e6c9\"];
+    \"step2\" [label=\"step2\\ntimeout 30s\\nretry 3x, fixed 10ms\", style=\"rounded,filled\", fillcolor=\"#ffcdd2\"];
+    \"step1\" -> \"step2\" [color=\"#2e7d32\", penwidth=2];
+}
+"
+    );
+    assert_eq!(
+        workflow.to_mermaid_with_run(&report),
+        "flowchart TD
+    start((start))
+    n0(\"step1<br/>timeout 30s\")
+    n1(\"step2<br/>timeout 30s<br/>retry 3x, fixed 10ms\")
+    start --> n0
+    n0 --> n1
+    classDef startStep stroke-width:3px
+    class n0 startStep
+    classDef succeeded fill:#c8e6c9
+    class n0 succeeded
+    classDef failed fill:#ffcdd2
+    class n1 failed
+    linkStyle 1 stroke:#2e7d32,stroke-width:2px
+"
+    );
+}
+
+#[cfg(feature = "checkpoint")]
+mod checkpointing {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+
+    #[derive(Debug)]
+    struct ExtractStep {
+        calls: Arc<AtomicU32>,
+    }
+
+    #[async_trait]
+    impl Step for ExtractStep {
+        async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            ctx.insert("rows", vec![1u32, 2, 3]);
+            Ok(StepOutput::next("transform"))
+        }
+
+        fn name(&self) -> StepName {
+            StepName::new("Extract")
+        }
+    }
+
+    #[derive(Debug)]
+    struct TransformStep {
+        crash: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl Step for TransformStep {
+        async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+            if self.crash.load(Ordering::SeqCst) {
+                return Err(WorkflowError::StepError {
+                    step_name: self.name(),
+                    details: "process killed".to_string(),
+                });
+            }
+            let total: u32 = ctx.get::<Vec<u32>>("rows").into_iter().flatten().sum();
+            ctx.insert("total", total);
+            Ok(StepOutput::done())
+        }
+
+        fn name(&self) -> StepName {
+            StepName::new("Transform")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resume_continues_after_last_completed_step() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileCheckpointStore::new(dir.path());
+        let calls = Arc::new(AtomicU32::new(0));
+        let crash = Arc::new(AtomicBool::new(true));
+        let workflow = Workflow::builder()
+            .add_step(
+                "extract",
+                ExtractStep {
+                    calls: calls.clone(),
+                },
+            )
+            .add_step(
+                "transform",
+                TransformStep {
+                    crash: crash.clone(),
+                },
+            )
+            .checkpoint_key::<Vec<u32>>("rows")
+            .checkpoint_key::<u32>("total")
+            .start_with("extract")
+            .build()
+            .expect("valid workflow");
+
+        let mut ctx = Context::new();
+        let report = workflow.resume("etl-1", &store, &mut ctx).await;
+        assert!(!report.is_success());
+        let checkpoint = store.load("etl-1").await.unwrap().unwrap();
+        assert_eq!(checkpoint.last_step().as_str(), "extract");
+        assert_eq!(
+            checkpoint.next_step().map(StepName::as_str),
+            Some("transform")
+        );
+
+        // A new process: the context is empty and extract is not run again.
+        crash.store(false, Ordering::SeqCst);
+        let mut ctx = Context::new();
+        let report = workflow.resume("etl-1", &store, &mut ctx).await;
+        assert!(report.is_success());
+        assert_eq!(report.run_id(), "etl-1");
+        assert_eq!(
+            report.path().map(StepName::as_str).collect::<Vec<_>>(),
+            vec!["transform"]
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(ctx.get::<u32>("total"), Some(&6));
+        assert!(store.load("etl-1").await.unwrap().unwrap().is_complete());
+
+        // A completed run is not run again, but its values are restored.
+        let mut ctx = Context::new();
+        let report = workflow.resume("etl-1", &store, &mut ctx).await;
+        assert!(report.is_success());
+        assert!(report.steps().is_empty());
+        assert_eq!(report.run_id(), "etl-1");
+        assert_eq!(ctx.get::<u32>("total"), Some(&6));
+    }
+
+    #[derive(Debug)]
+    struct ShipStep {
+        outcome: Arc<std::sync::Mutex<Option<bool>>>,
+    }
+
+    #[async_trait]
+    impl Step for ShipStep {
+        async fn execute(&self, _ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+            let outcome = *self
+                .outcome
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            match outcome {
+                // Stands in for a process that dies mid-step.
+                None => std::future::pending().await,
+                Some(true) => Ok(StepOutput::done()),
+                Some(false) => Err(WorkflowError::StepError {
+                    step_name: self.name(),
+                    details: "carrier rejected the parcel".to_string(),
+                }),
+            }
+        }
+
+        fn name(&self) -> StepName {
+            StepName::new("Ship")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resumed_run_compensates_and_starts_over() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileCheckpointStore::new(dir.path());
+        let ledger = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let outcome = Arc::new(std::sync::Mutex::new(None));
+        let workflow = Workflow::builder()
+            .add_compensable(
+                "reserve",
+                LedgerStep::new("reserve", Some("charge"), &ledger),
+            )
+            .add_compensable("charge", LedgerStep::new("charge", Some("ship"), &ledger))
+            .add_step(
+                "ship",
+                ShipStep {
+                    outcome: outcome.clone(),
+                },
+            )
+            .start_with("reserve")
+            .build()
+            .expect("valid workflow");
+
+        // The process dies while shipping, after both charges were checkpointed.
+        let mut ctx = Context::new();
+        let crashed = tokio::time::timeout(
+            Duration::from_millis(50),
+            workflow.resume("order-1", &store, &mut ctx),
+        )
+        .await;
+        assert!(crashed.is_err());
+        let checkpoint = store.load("order-1").await.unwrap().unwrap();
+        assert_eq!(
+            checkpoint
+                .compensable()
+                .iter()
+                .map(StepName::as_str)
+                .collect::<Vec<_>>(),
+            vec!["reserve", "charge"]
+        );
+
+        // The resumed run fails and undoes the steps of the crashed one.
+        *outcome.lock().unwrap() = Some(false);
+        let mut ctx = Context::new();
+        let report = workflow.resume("order-1", &store, &mut ctx).await;
+        assert!(!report.is_success());
+        let compensated: Vec<&str> = report
+            .compensations()
+            .iter()
+            .map(|c| c.name().as_str())
+            .collect();
+        assert_eq!(compensated, vec!["charge", "reserve"]);
+        assert!(store.load("order-1").await.unwrap().is_none());
+
+        // With the checkpoint gone, the next call runs every step again.
+        *outcome.lock().unwrap() = Some(true);
+        let mut ctx = Context::new();
+        let report = workflow.resume("order-1", &store, &mut ctx).await;
+        assert!(report.is_success());
+        assert_eq!(
+            report.path().map(StepName::as_str).collect::<Vec<_>>(),
+            vec!["reserve", "charge", "ship"]
+        );
+        assert_eq!(
+            *ledger.lock().unwrap(),
+            vec![
+                "reserve",
+                "charge",
+                "undo charge",
+                "undo reserve",
+                "reserve",
+                "charge"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_rejects_mistyped_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileCheckpointStore::new(dir.path());
+        let workflow = Workflow::builder()
+            .add_step(
+                "extract",
+                ExtractStep {
+                    calls: Arc::default(),
+                },
+            )
+            .add_step(
+                "transform",
+                TransformStep {
+                    crash: Arc::default(),
+                },
+            )
+            .checkpoint_key::<String>("rows")
+            .start_with("extract")
+            .build()
+            .expect("valid workflow");
+
+        let mut ctx = Context::new();
+        let report = workflow.resume("etl-2", &store, &mut ctx).await;
+
+        assert!(matches!(
+            report.errors(),
+            [WorkflowError::Checkpoint(details)] if details.contains("'rows'")
+        ));
+        assert_eq!(report.steps().len(), 1);
+        assert!(store.load("etl-2").await.unwrap().is_none());
+    }
+}
+
+#[cfg(feature = "metrics")]
+mod metrics_recording {
+    use super::*;
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+    /// Recorded metrics as (name, labels as `key=value`, value).
+    type Recorded = Vec<(String, Vec<String>, DebugValue)>;
+
+    /// Executes `workflow` once on `runtime` and returns the metrics it
+    /// recorded.
+    fn record_run(
+        runtime: &tokio::runtime::Runtime,
+        workflow: &Workflow,
+    ) -> (Result<(), Vec<WorkflowError>>, Recorded) {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let outcome = metrics::with_local_recorder(&recorder, || {
+            runtime.block_on(workflow.execute(&mut Context::new()))
+        });
+        let recorded = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| {
+                let key = key.key();
+                let labels = key
+                    .labels()
+                    .map(|label| format!("{}={}", label.key(), label.value()))
+                    .collect();
+                (key.name().to_string(), labels, value)
+            })
+            .collect();
+        (outcome, recorded)
+    }
+
+    fn runtime() -> std::io::Result<tokio::runtime::Runtime> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .start_paused(true)
+            .build()
+    }
+
+    fn counter(recorded: &Recorded, name: &str, label: &str) -> Option<u64> {
+        recorded.iter().find_map(|(n, labels, value)| match value {
+            DebugValue::Counter(count) if n == name && labels.iter().any(|l| l == label) => {
+                Some(*count)
+            }
+            _ => None,
+        })
+    }
+
+    fn samples(recorded: &Recorded, name: &str, label: &str) -> usize {
+        recorded
+            .iter()
+            .find_map(|(n, labels, value)| match value {
+                DebugValue::Histogram(samples)
+                    if n == name && labels.iter().any(|l| l == label) =>
+                {
+                    Some(samples.len())
+                }
+                _ => None,
+            })
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn test_metrics_count_steps_and_retries() {
+        let workflow = Workflow::builder()
+            .name("orders")
+            .add_step("step1", Step1)
+            .add_retryable(
+                "step2",
+                RetryableStep {
+                    attempts: Arc::default(),
+                    fail_until: 1,
+                },
+            )
+            .start_with("step1")
+            .metrics()
+            .build()
+            .expect("valid workflow");
+
+        let (outcome, recorded) = record_run(&runtime().expect("runtime"), &workflow);
+        assert!(outcome.is_ok());
+
+        let workflow_label = "workflow=orders";
+        assert_eq!(
+            counter(
+                &recorded,
+                "tsumugi_workflow_executions_total",
+                workflow_label
+            ),
+            Some(1)
+        );
+        assert_eq!(
+            counter(
+                &recorded,
+                "tsumugi_workflow_successes_total",
+                workflow_label
+            ),
+            Some(1)
+        );
+        assert_eq!(
+            counter(&recorded, "tsumugi_workflow_failures_total", workflow_label),
+            None
+        );
+        assert_eq!(
+            samples(
+                &recorded,
+                "tsumugi_workflow_duration_seconds",
+                workflow_label
+            ),
+            1
+        );
+
+        for step in ["step=step1", "step=step2"] {
+            assert_eq!(
+                counter(&recorded, "tsumugi_step_executions_total", step),
+                Some(1)
+            );
+            assert_eq!(
+                counter(&recorded, "tsumugi_step_successes_total", step),
+                Some(1)
+            );
+            assert_eq!(samples(&recorded, "tsumugi_step_duration_seconds", step), 1);
+        }
+        assert_eq!(
+            counter(&recorded, "tsumugi_step_retries_total", "step=step1"),
+            None
+        );
+        assert_eq!(
+            counter(&recorded, "tsumugi_step_retries_total", "step=step2"),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_metrics_count_timeouts_and_failures() {
+        let workflow = Workflow::builder()
+            .add("slow", SlowStep)
+            .timeout(Duration::from_millis(50))
+            .retry_policy(RetryPolicy::fixed(1, Duration::ZERO))
+            .done()
+            .start_with("slow")
+            .metrics()
+            .build()
+            .expect("valid workflow");
+
+        let (outcome, recorded) = record_run(&runtime().expect("runtime"), &workflow);
+        assert!(outcome.is_err());
+
+        assert_eq!(
+            counter(&recorded, "tsumugi_workflow_failures_total", "workflow="),
+            Some(1)
+        );
+        assert_eq!(
+            counter(&recorded, "tsumugi_step_timeouts_total", "step=slow"),
+            Some(2)
+        );
+        assert_eq!(
+            counter(&recorded, "tsumugi_step_retries_total", "step=slow"),
+            Some(1)
+        );
+        assert_eq!(
+            counter(&recorded, "tsumugi_step_failures_total", "step=slow"),
+            Some(1)
+        );
+        assert_eq!(
+            samples(&recorded, "tsumugi_step_duration_seconds", "step=slow"),
+            1
+        );
+    }
+
+    #[test]
+    fn test_metrics_are_opt_in() {
+        let workflow = Workflow::builder()
+            .add_step("step1", Step1)
+            .add_step("step2", Step2)
+            .start_with("step1")
+            .build()
+            .expect("valid workflow");
+
+        let (outcome, recorded) = record_run(&runtime().expect("runtime"), &workflow);
+        assert!(outcome.is_ok());
+        assert!(recorded.is_empty());
+    }
+}
+
+#[cfg(feature = "definition")]
+mod definitions {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Greet {
+        greeting: String,
+    }
+
+    #[async_trait]
+    impl Step for Greet {
+        async fn execute(&self, ctx: &mut Context) -> Result<StepOutput, WorkflowError> {
+            ctx.insert("greeting", self.greeting.clone());
+            Ok(StepOutput::done())
+        }
+
+        fn name(&self) -> StepName {
+            StepName::new("Greet")
+        }
+    }
+
+    fn registry() -> StepRegistry {
+        StepRegistry::new()
+            .register("first", |_| Ok::<_, String>(Step1))
+            .register("greet", |config| {
+                match config.get("greeting").and_then(|v| v.as_str()) {
+                    Some(greeting) => Ok(Greet {
+                        greeting: greeting.to_string(),
+                    }),
+                    None => Err("`greeting` must be a string"),
+                }
+            })
+    }
+
+    const YAML: &str = "\
+name: greetings
+start: step1
+deadline: 1m
+steps:
+  step1:
+    type: first
+    next: step2
+  step2:
+    type: greet
+    config:
+      greeting: hello
+    timeout: 5s
+    retry: { policy: fixed, max_retries: 2, delay: 10ms }
+    terminal: true
+";
+
+    #[tokio::test]
+    async fn test_load_yaml_definition() {
+        let workflow = Workflow::from_yaml(YAML, &registry()).expect("valid definition");
+        assert_eq!(workflow.name(), Some("greetings"));
+        assert_eq!(workflow.start_step().as_str(), "step1");
+        assert!(workflow
+            .to_dot()
+            .contains("label=\"step2\\ntimeout 5s\\nretry 2x, fixed 10ms\""));
+
+        let mut ctx = Context::new();
+        assert!(workflow.execute(&mut ctx).await.is_ok());
+        assert_eq!(
+            ctx.get::<String>("greeting").map(String::as_str),
+            Some("hello")
+        );
+    }
+
+    #[test]
+    fn test_formats_load_the_same_workflow() {
+        let toml = r#"
+name = "greetings"
+start = "step1"
+deadline = "1m"
+
+[steps.step1]
+type = "first"
+next = ["step2"]
+
+[steps.step2]
+type = "greet"
+config = { greeting = "hello" }
+timeout = "5s"
+retry = { policy = "fixed", max_retries = 2, delay = "10ms" }
+terminal = true
+"#;
+        let json = r#"{
+  "name": "greetings",
+  "start": "step1",
+  "deadline": "1m",
+  "steps": {
+    "step1": { "type": "first", "next": ["step2"] },
+    "step2": {
+      "type": "greet",
+      "config": { "greeting": "hello" },
+      "timeout": "5s",
+      "retry": { "policy": "fixed", "max_retries": 2, "delay": "10ms" },
+      "terminal": true
+    }
+  }
+}"#;
+        let registry = registry();
+        let expected = Workflow::from_yaml(YAML, &registry)
+            .expect("valid definition")
+            .to_dot();
+        let from_toml = Workflow::from_toml(toml, &registry).expect("valid definition");
+        let from_json = Workflow::from_json(json, &registry).expect("valid definition");
+        assert_eq!(from_toml.to_dot(), expected);
+        assert_eq!(from_json.to_dot(), expected);
+        assert_eq!(from_json.deadline(), Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_errors_point_at_the_offending_line() {
+        let registry = registry();
+        let yaml = |from: &str, to: &str| Workflow::from_yaml(&YAML.replace(from, to), &registry);
+
+        let err = yaml("type: greet", "type: greeter").unwrap_err();
+        assert_eq!(err.line(), Some(9));
+        assert!(err
+            .to_string()
+            .contains("unknown step type 'greeter', expected one of: first, greet"));
+
+        let err = yaml("next: step2", "next: [step3]").unwrap_err();
+        assert_eq!(err.line(), Some(7));
+        assert!(err.to_string().contains("unknown step 'step3'"));
+
+        let err = yaml("start: step1", "start: step0").unwrap_err();
+        assert_eq!(err.line(), Some(2));
+
+        let err = yaml("timeout: 5s", "timeout: 5 seconds").unwrap_err();
+        assert_eq!(err.line(), Some(12));
+        assert!(err
+            .to_string()
+            .contains("invalid value: string \"5 seconds\""));
+
+        let err = yaml("timeout: 5s", "tiemout: 5s").unwrap_err();
+        assert_eq!(err.line(), Some(12));
+        assert!(err.to_string().contains("unknown field `tiemout`"));
+
+        let err = yaml("delay: 10ms", "delay: 10ms, increment: 1s").unwrap_err();
+        assert_eq!(err.line(), Some(13));
+        assert!(err
+            .to_string()
+            .contains("`increment` does not apply to the fixed policy"));
+
+        let err = yaml("greeting: hello", "greeting: 42").unwrap_err();
+        assert_eq!(err.line(), Some(9));
+        assert!(err
+            .to_string()
+            .contains("invalid config for step type 'greet': `greeting` must be a string"));
+
+        let toml = "start = \"a\"\n\n[steps.a]\ntype = \"first\"\nnext = [\"b\"]\n";
+        let err = Workflow::from_toml(toml, &registry).unwrap_err();
+        assert_eq!((err.line(), err.column()), (Some(5), Some(9)));
+        assert!(err.to_string().contains("unknown step 'b'"));
+
+        let json = "{\n  \"start\": \"a\",\n  \"steps\": {\n    \"a\": { \"type\": 1 }\n  }\n}";
+        let err = Workflow::from_json(json, &registry).unwrap_err();
+        assert_eq!(err.line(), Some(4));
+        assert!(err.to_string().contains("expected a registered step type"));
+    }
+
+    #[test]
+    fn test_graph_errors_point_at_the_step() {
+        let yaml = format!(
+            "{}  orphan:\n    type: first\n    next: step2\n  spin:\n    type: first\n    next: spin\n",
+            YAML
+        );
+        let err = Workflow::from_yaml(&yaml, &registry()).unwrap_err();
+        assert_eq!((err.line(), err.column()), (Some(15), Some(3)));
+        let message = err.to_string();
+        assert!(message
+            .contains("Step 'orphan' is unreachable from the start step at line 15 column 3"));
+        assert!(
+            message.contains("Step 'spin' is unreachable from the start step at line 18 column 3")
+        );
+
+        let toml = "start = \"a\"\n\n[steps.a]\ntype = \"first\"\n\n[steps.spin]\ntype = \"first\"\nnext = \"spin\"\n";
+        let err = Workflow::from_toml(toml, &registry()).unwrap_err();
+        assert_eq!(err.line(), Some(6));
+        assert!(err
+            .to_string()
+            .contains("Step 'spin' has no path to completion"));
+
+        let json = r#"{
+  "start": "a",
+  "steps": {
+    "a": { "type": "first" },
+    "spin": { "type": "first", "next": "spin" }
+  }
+}"#;
+        let err = Workflow::from_json(json, &registry()).unwrap_err();
+        assert_eq!(err.line(), Some(5));
+        assert!(matches!(err, DefinitionError::Invalid { .. }));
+    }
+}